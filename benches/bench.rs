@@ -830,7 +830,34 @@ fn bench_scaling(density: f64, min: usize) {
     );
 }
 
+fn bench_adversarial() {
+    // Every key is a multiple of the table size, so without the
+    // keyed-hash feature they all want the same slot.
+    const CAP: usize = 2048;
+    let keys = |n: usize| (1..=n as u64).map(|i| i*CAP as u64 + CAP as u64);
+    let fill = |n: usize| {
+        let mut s = tinyset::SetU64::with_capacity_and_bits(CAP, 0);
+        for k in keys(n) {
+            s.insert(k);
+        }
+        s
+    };
+    let full = fill(CAP/2);
+    let mode = if cfg!(feature = "keyed-hash") { "keyed" } else { "unkeyed" };
+    println!("\nadversarial keys ({}), {} elements in {} slots", mode, CAP/2, CAP);
+    println!("  fill:          {:>10.0} ns",
+             bench_gen_env(|| (), |_| fill(CAP/2)).ns_per_iter);
+    println!("  contains hit:  {:>10.1} ns",
+             bench_gen_env(|| rand::random::<u64>() % (CAP/2) as u64 + 1,
+                           |x| full.contains(*x*CAP as u64 + CAP as u64)).ns_per_iter);
+    println!("  contains miss: {:>10.1} ns",
+             bench_gen_env(|| rand::random::<u64>() % 1000 + CAP as u64,
+                           |x| full.contains(*x*CAP as u64)).ns_per_iter);
+}
+
 fn main() {
+    bench_adversarial();
+
     // let mut s = tinyset::SetU32::new();
     // while s.len() < 10000 {
     //     s.insert(rand::random::<u32>() % 15000);
@@ -5,12 +5,15 @@ set -ex
 main() {
     cross build --target $TARGET
     cross build --target $TARGET --release
+    cross build --target $TARGET --no-default-features
 
     if [ ! -z $DISABLE_TESTS ]; then
         return
     fi
 
     cross test --target $TARGET
+    cross test --target $TARGET --features deterministic
+    cross test --target $TARGET --features keyed-hash
     cross bench --target $TARGET
     # cross test --target $TARGET --release
 
@@ -0,0 +1,295 @@
+// Copyright 2019 David Roundy
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A multiset of [Fits64] elements.
+
+use core::marker::PhantomData;
+use crate::map64::{self, Map64U};
+use crate::set64::Fits64;
+
+/// A bag (or multiset) of [Fits64] elements, which counts how many
+/// times each element has been inserted.
+///
+/// Each element is stored together with its count in a single `u64`
+/// where both fit, using just as many bits for each as the largest
+/// element and count need.  A bag with one distinct element (however
+/// many times it was inserted) is stored inline with no allocation.
+///
+/// # Examples
+///
+/// ```
+/// use tinyset::Bag64;
+///
+/// let mut b: Bag64<char> = "hello world".chars().collect();
+/// assert_eq!(b.count('l'), 3);
+/// assert_eq!(b.total(), 11);
+/// assert!(b.remove_one('l'));
+/// assert_eq!(b.count('l'), 2);
+/// assert_eq!(b.count('z'), 0);
+/// ```
+pub struct Bag64<T: Fits64> {
+    counts: Map64U,
+    total: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Fits64> Default for Bag64<T> {
+    fn default() -> Self {
+        Bag64::new()
+    }
+}
+
+impl<T: Fits64> Clone for Bag64<T> {
+    fn clone(&self) -> Self {
+        Bag64 { counts: self.counts.clone(), total: self.total, phantom: PhantomData }
+    }
+}
+
+impl<T: Fits64> Bag64<T> {
+    /// Creates an empty bag.
+    pub const fn new() -> Self {
+        Bag64 { counts: Map64U::new(), total: 0, phantom: PhantomData }
+    }
+    /// Adds one copy of `x`, returning how many there were before.
+    pub fn insert(&mut self, x: T) -> usize {
+        self.insert_n(x, 1)
+    }
+    /// Adds `n` copies of `x`, returning how many there were before.
+    pub fn insert_n(&mut self, x: T, n: usize) -> usize {
+        let k = x.to_u64();
+        let old = self.counts.get(k).unwrap_or(0);
+        if n > 0 {
+            self.counts.insert(k, old + n as u64);
+            self.total += n;
+        }
+        old as usize
+    }
+    /// Removes one copy of `x`, returning true if there was one.
+    pub fn remove_one(&mut self, x: T) -> bool {
+        let k = x.to_u64();
+        match self.counts.get(k) {
+            None => return false,
+            Some(1) => { self.counts.remove(k); }
+            Some(c) => { self.counts.insert(k, c - 1); }
+        }
+        self.total -= 1;
+        true
+    }
+    /// Removes every copy of `x`, returning how many there were.
+    pub fn remove_all(&mut self, x: T) -> usize {
+        let old = self.counts.remove(x.to_u64()).unwrap_or(0) as usize;
+        self.total -= old;
+        old
+    }
+    /// Returns how many copies of `x` the bag holds.
+    pub fn count(&self, x: T) -> usize {
+        self.counts.get(x.to_u64()).unwrap_or(0) as usize
+    }
+    /// Returns true if the bag holds at least one `x`.
+    pub fn contains(&self, x: T) -> bool {
+        self.count(x) > 0
+    }
+    /// Returns the number of distinct elements.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+    /// Returns true if the bag is empty.
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+    /// Returns the number of elements counting every copy, which is
+    /// the sum of the counts.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+    /// Iterates over the distinct elements with their counts, in no
+    /// particular order.
+    pub fn iter_counts(&self) -> IterCounts<'_, T> {
+        IterCounts(self.counts.iter(), PhantomData)
+    }
+    /// Returns the multiset union, in which each element has the
+    /// larger of its counts in the two bags.
+    ///
+    /// ```
+    /// use tinyset::Bag64;
+    ///
+    /// let a: Bag64<u8> = vec![1, 1, 2].into_iter().collect();
+    /// let b: Bag64<u8> = vec![1, 2, 2, 3].into_iter().collect();
+    /// let u = a.union(&b);
+    /// assert_eq!((u.count(1), u.count(2), u.count(3)), (2, 2, 1));
+    /// let i = a.intersection(&b);
+    /// assert_eq!((i.count(1), i.count(2), i.count(3)), (1, 1, 0));
+    /// ```
+    pub fn union(&self, other: &Bag64<T>) -> Bag64<T> {
+        let (big, small) = if self.len() >= other.len() { (self, other) } else { (other, self) };
+        let mut b = big.clone();
+        for (x, c) in small.iter_counts() {
+            let have = b.count(x);
+            if c > have {
+                b.insert_n(x, c - have);
+            }
+        }
+        b
+    }
+    /// Returns the multiset intersection, in which each element has
+    /// the smaller of its counts in the two bags.
+    pub fn intersection(&self, other: &Bag64<T>) -> Bag64<T> {
+        let (big, small) = if self.len() >= other.len() { (self, other) } else { (other, self) };
+        let mut b = Bag64::new();
+        for (x, c) in small.iter_counts() {
+            let c = core::cmp::min(c, big.count(x));
+            if c > 0 {
+                b.insert_n(x, c);
+            }
+        }
+        b
+    }
+}
+
+/// An iterator over the elements of a [`Bag64`] and their counts.
+#[derive(Clone)]
+pub struct IterCounts<'a, T: Fits64>(map64::Iter<'a>, PhantomData<T>);
+
+impl<'a, T: Fits64> Iterator for IterCounts<'a, T> {
+    type Item = (T, usize);
+    fn next(&mut self) -> Option<(T, usize)> {
+        self.0.next().map(|(k, c)| (unsafe { T::from_u64(k) }, c as usize))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+impl<'a, T: Fits64> ExactSizeIterator for IterCounts<'a, T> {}
+impl<'a, T: Fits64> core::iter::FusedIterator for IterCounts<'a, T> {}
+
+impl<'a, T: Fits64> IntoIterator for &'a Bag64<T> {
+    type Item = (T, usize);
+    type IntoIter = IterCounts<'a, T>;
+    fn into_iter(self) -> IterCounts<'a, T> {
+        self.iter_counts()
+    }
+}
+
+impl<T: Fits64 + core::fmt::Debug> core::fmt::Debug for Bag64<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_map().entries(self.iter_counts()).finish()
+    }
+}
+
+impl<T: Fits64> PartialEq for Bag64<T> {
+    fn eq(&self, other: &Bag64<T>) -> bool {
+        self.len() == other.len() && self.total == other.total
+            && self.iter_counts().all(|(x, c)| other.count(x) == c)
+    }
+}
+impl<T: Fits64> Eq for Bag64<T> {}
+
+impl<T: Fits64> Extend<T> for Bag64<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for x in iter {
+            self.insert(x);
+        }
+    }
+}
+
+impl<T: Fits64> core::iter::FromIterator<T> for Bag64<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut b = Bag64::new();
+        b.extend(iter);
+        b
+    }
+}
+
+#[cfg(test)]
+fn check_bag<T: Fits64 + Ord + core::fmt::Debug>(ops: &[(u8, u64)], elems: impl Fn(u64) -> T) {
+    use alloc::collections::BTreeMap;
+    let mut b = Bag64::new();
+    let mut model: BTreeMap<T, usize> = BTreeMap::new();
+    for &(op, x) in ops {
+        let x = elems(x);
+        let have = model.get(&x).cloned().unwrap_or(0);
+        match op {
+            0 => {
+                assert_eq!(b.remove_all(x), have);
+                model.remove(&x);
+            }
+            1..=3 => {
+                assert_eq!(b.remove_one(x), have > 0);
+                if have > 1 {
+                    model.insert(x, have - 1);
+                } else {
+                    model.remove(&x);
+                }
+            }
+            7 => {
+                assert_eq!(b.insert_n(x, 1000), have);
+                model.insert(x, have + 1000);
+            }
+            _ => {
+                assert_eq!(b.insert(x), have);
+                model.insert(x, have + 1);
+            }
+        }
+        assert_eq!(b.count(x), model.get(&x).cloned().unwrap_or(0));
+        assert_eq!(b.len(), model.len());
+    }
+    assert_eq!(b.total(), model.values().sum::<usize>());
+    let mut v: alloc::vec::Vec<_> = b.iter_counts().collect();
+    v.sort();
+    assert_eq!(v, model.iter().map(|(&x, &c)| (x, c)).collect::<alloc::vec::Vec<_>>());
+    for (&x, &c) in model.iter() {
+        for _ in 0..c {
+            assert!(b.remove_one(x));
+        }
+    }
+    assert!(b.is_empty());
+    assert_eq!(b.len(), 0);
+    assert!(matches!(b.counts, Map64U::Empty));
+}
+
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+proptest!{
+    #[test]
+    fn check_bags(ops in prop::collection::vec((0u8..8, 0u64..300), 0usize..1000)) {
+        check_bag(&ops, |x| x as u8);
+        check_bag(&ops, |x| x * 0x1234_5678_9abc);
+        check_bag(&ops, |x| x as i64 - 150);
+        check_bag(&ops, |x| u64::MAX - x);
+    }
+}
+
+#[test]
+fn test_bag64() {
+    let mut b: Bag64<u64> = (0..100).chain(0..50).collect();
+    assert_eq!((b.len(), b.total()), (100, 150));
+    for x in 1..100 {
+        b.remove_all(x);
+    }
+    assert!(matches!(b.counts, Map64U::One(0, 2)));
+    assert!(b.remove_one(0));
+    assert!(matches!(b.counts, Map64U::One(0, 1)));
+    assert!(b.remove_one(0) && !b.remove_one(0));
+    assert!(matches!(b.counts, Map64U::Empty));
+    assert_eq!(b.total(), 0);
+}
+
+#[test]
+fn test_bag64_union() {
+    let a: Bag64<u32> = vec![1, 1, 2, 7, 7, 7].into_iter().collect();
+    let b: Bag64<u32> = vec![1, 2, 2, 3].into_iter().collect();
+    let u = a.union(&b);
+    assert_eq!(u, vec![1, 1, 2, 2, 3, 7, 7, 7].into_iter().collect());
+    assert_eq!(u.total(), 8);
+    assert_eq!(a.intersection(&b), vec![1, 2].into_iter().collect());
+    assert_eq!(b.intersection(&a).total(), 2);
+    let mut one = Bag64::new();
+    one.insert_n(5u64, 1 << 40);
+    assert!(matches!(one.counts, Map64U::One(5, _)));
+    assert_eq!(format!("{:?}", one), "{5: 1099511627776}");
+}
@@ -0,0 +1,298 @@
+// Copyright 2019 David Roundy
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A one-to-one map between two [Fits64] types.
+
+use core::marker::PhantomData;
+use crate::map64::{self, Map64U};
+use crate::set64::Fits64;
+
+/// A one-to-one map between [Fits64] values, which can be looked up
+/// from either side.
+///
+/// Each direction is a table in which a key and its value share a
+/// single `u64` where they fit, so a map between small ids costs
+/// about as much as two sets of them.  The tables shrink as pairs are
+/// removed, and a map with one pair needs no allocation.
+///
+/// # Examples
+///
+/// ```
+/// use tinyset::BiMap64;
+///
+/// let mut ids: BiMap64<u32, u64> = BiMap64::new();
+/// ids.insert(1, 1_000_000);
+/// ids.insert(2, 5);
+/// assert_eq!(ids.get_by_left(1), Some(1_000_000));
+/// assert_eq!(ids.get_by_right(5), Some(2));
+/// // Pairing 2 with another right value drops its old pair.
+/// ids.insert(2, 6);
+/// assert_eq!(ids.get_by_right(5), None);
+/// assert_eq!(ids.len(), 2);
+/// ```
+pub struct BiMap64<L: Fits64, R: Fits64> {
+    left: Map64U,
+    right: Map64U,
+    phantom: PhantomData<(L, R)>,
+}
+
+/// The pairs that an [`insert`](BiMap64::insert) removed to make way
+/// for the new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overwritten<L, R> {
+    /// Neither value was in the map.
+    Neither,
+    /// The left value was paired with another right value.
+    Left(L, R),
+    /// The right value was paired with another left value.
+    Right(L, R),
+    /// The same pair was already in the map, which is unchanged.
+    Pair(L, R),
+    /// Both values were in the map in two different pairs, the one
+    /// with the left value first.
+    Both((L, R), (L, R)),
+}
+
+impl<L: Fits64, R: Fits64> Default for BiMap64<L, R> {
+    fn default() -> Self {
+        BiMap64::new()
+    }
+}
+
+impl<L: Fits64, R: Fits64> Clone for BiMap64<L, R> {
+    fn clone(&self) -> Self {
+        BiMap64 { left: self.left.clone(), right: self.right.clone(), phantom: PhantomData }
+    }
+}
+
+impl<L: Fits64, R: Fits64> BiMap64<L, R> {
+    /// Creates an empty map.
+    pub const fn new() -> Self {
+        BiMap64 { left: Map64U::new(), right: Map64U::new(), phantom: PhantomData }
+    }
+    /// Returns the number of pairs.
+    pub fn len(&self) -> usize {
+        self.left.len()
+    }
+    /// Returns true if there are no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The right value paired with `l`.
+    pub fn get_by_left(&self, l: L) -> Option<R> {
+        self.left.get(l.to_u64()).map(|r| unsafe { R::from_u64(r) })
+    }
+    /// The left value paired with `r`.
+    pub fn get_by_right(&self, r: R) -> Option<L> {
+        self.right.get(r.to_u64()).map(|l| unsafe { L::from_u64(l) })
+    }
+    /// Returns true if `l` is paired with some right value.
+    pub fn contains_left(&self, l: L) -> bool {
+        self.left.get(l.to_u64()).is_some()
+    }
+    /// Returns true if `r` is paired with some left value.
+    pub fn contains_right(&self, r: R) -> bool {
+        self.right.get(r.to_u64()).is_some()
+    }
+    /// Pairs `l` with `r`, first removing any pairs that hold either
+    /// of them, and returns those pairs.
+    pub fn insert(&mut self, l: L, r: R) -> Overwritten<L, R> {
+        let (lk, rk) = (l.to_u64(), r.to_u64());
+        let old_r = self.left.get(lk);
+        if old_r == Some(rk) {
+            return Overwritten::Pair(l, r);
+        }
+        let old_l = self.right.get(rk);
+        if let Some(old_r) = old_r {
+            self.right.remove(old_r);
+        }
+        if let Some(old_l) = old_l {
+            self.left.remove(old_l);
+        }
+        self.left.insert(lk, rk);
+        self.right.insert(rk, lk);
+        let pair = |l: u64, r: u64| unsafe { (L::from_u64(l), R::from_u64(r)) };
+        match (old_r, old_l) {
+            (None, None) => Overwritten::Neither,
+            (Some(old_r), None) => Overwritten::Left(l, pair(lk, old_r).1),
+            (None, Some(old_l)) => Overwritten::Right(pair(old_l, rk).0, r),
+            (Some(old_r), Some(old_l)) => Overwritten::Both(pair(lk, old_r), pair(old_l, rk)),
+        }
+    }
+    /// Pairs `l` with `r` if neither is already in the map, and
+    /// otherwise hands them back.
+    pub fn insert_no_overwrite(&mut self, l: L, r: R) -> Result<(), (L, R)> {
+        if self.contains_left(l) || self.contains_right(r) {
+            return Err((l, r));
+        }
+        self.left.insert(l.to_u64(), r.to_u64());
+        self.right.insert(r.to_u64(), l.to_u64());
+        Ok(())
+    }
+    /// Removes the pair holding `l`, and returns it.
+    pub fn remove_by_left(&mut self, l: L) -> Option<(L, R)> {
+        let r = self.left.remove(l.to_u64())?;
+        self.right.remove(r);
+        Some((l, unsafe { R::from_u64(r) }))
+    }
+    /// Removes the pair holding `r`, and returns it.
+    pub fn remove_by_right(&mut self, r: R) -> Option<(L, R)> {
+        let l = self.right.remove(r.to_u64())?;
+        self.left.remove(l);
+        Some((unsafe { L::from_u64(l) }, r))
+    }
+    /// Iterates over the pairs, in no particular order.
+    pub fn iter(&self) -> Iter<'_, L, R> {
+        Iter(self.left.iter(), PhantomData)
+    }
+}
+
+/// An iterator over the pairs of a [`BiMap64`].
+#[derive(Clone)]
+pub struct Iter<'a, L: Fits64, R: Fits64>(map64::Iter<'a>, PhantomData<(L, R)>);
+
+impl<'a, L: Fits64, R: Fits64> Iterator for Iter<'a, L, R> {
+    type Item = (L, R);
+    fn next(&mut self) -> Option<(L, R)> {
+        self.0.next().map(|(l, r)| unsafe { (L::from_u64(l), R::from_u64(r)) })
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+impl<'a, L: Fits64, R: Fits64> ExactSizeIterator for Iter<'a, L, R> {}
+impl<'a, L: Fits64, R: Fits64> core::iter::FusedIterator for Iter<'a, L, R> {}
+
+impl<'a, L: Fits64, R: Fits64> IntoIterator for &'a BiMap64<L, R> {
+    type Item = (L, R);
+    type IntoIter = Iter<'a, L, R>;
+    fn into_iter(self) -> Iter<'a, L, R> {
+        self.iter()
+    }
+}
+
+impl<L: Fits64 + core::fmt::Debug, R: Fits64 + core::fmt::Debug> core::fmt::Debug for BiMap64<L, R> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<L: Fits64, R: Fits64> PartialEq for BiMap64<L, R> {
+    fn eq(&self, other: &BiMap64<L, R>) -> bool {
+        self.len() == other.len()
+            && self.left.iter().all(|(l, r)| other.left.get(l) == Some(r))
+    }
+}
+impl<L: Fits64, R: Fits64> Eq for BiMap64<L, R> {}
+
+/// Inserts each pair in turn, so that later pairs replace earlier
+/// ones that they conflict with.
+impl<L: Fits64, R: Fits64> Extend<(L, R)> for BiMap64<L, R> {
+    fn extend<I: IntoIterator<Item = (L, R)>>(&mut self, iter: I) {
+        for (l, r) in iter {
+            self.insert(l, r);
+        }
+    }
+}
+
+impl<L: Fits64, R: Fits64> core::iter::FromIterator<(L, R)> for BiMap64<L, R> {
+    fn from_iter<I: IntoIterator<Item = (L, R)>>(iter: I) -> Self {
+        let mut m = BiMap64::new();
+        m.extend(iter);
+        m
+    }
+}
+
+#[cfg(test)]
+fn check_bimap(ops: &[(u8, u16, i64)]) {
+    use alloc::collections::BTreeMap;
+    let mut m: BiMap64<u16, i64> = BiMap64::new();
+    let mut by_left: BTreeMap<u16, i64> = BTreeMap::new();
+    let mut by_right: BTreeMap<i64, u16> = BTreeMap::new();
+    for &(op, l, x) in ops {
+        let x = x * 1_000_003 - 100_000_000;
+        match op {
+            0 => {
+                let want = by_left.remove(&l).map(|x| (l, x));
+                if let Some((_, x)) = want {
+                    by_right.remove(&x);
+                }
+                assert_eq!(m.remove_by_left(l), want);
+            }
+            1 => {
+                let want = by_right.remove(&x).map(|l| (l, x));
+                if let Some((l, _)) = want {
+                    by_left.remove(&l);
+                }
+                assert_eq!(m.remove_by_right(x), want);
+            }
+            2 => {
+                let free = !by_left.contains_key(&l) && !by_right.contains_key(&x);
+                assert_eq!(m.insert_no_overwrite(l, x).is_ok(), free);
+                if free {
+                    by_left.insert(l, x);
+                    by_right.insert(x, l);
+                }
+            }
+            _ => {
+                let want = match (by_left.get(&l).cloned(), by_right.get(&x).cloned()) {
+                    (Some(y), _) if y == x => Overwritten::Pair(l, x),
+                    (None, None) => Overwritten::Neither,
+                    (Some(y), None) => Overwritten::Left(l, y),
+                    (None, Some(k)) => Overwritten::Right(k, x),
+                    (Some(y), Some(k)) => Overwritten::Both((l, y), (k, x)),
+                };
+                if let Some(y) = by_left.insert(l, x) {
+                    by_right.remove(&y);
+                }
+                if let Some(k) = by_right.insert(x, l) {
+                    if k != l {
+                        by_left.remove(&k);
+                    }
+                }
+                assert_eq!(m.insert(l, x), want);
+            }
+        }
+        assert_eq!(m.len(), by_left.len());
+        assert_eq!(m.get_by_left(l), by_left.get(&l).cloned());
+        assert_eq!(m.get_by_right(x), by_right.get(&x).cloned());
+    }
+    let mut pairs: alloc::vec::Vec<_> = m.iter().collect();
+    pairs.sort();
+    assert_eq!(pairs, by_left.into_iter().collect::<alloc::vec::Vec<_>>());
+    assert_eq!(m.iter().collect::<BiMap64<_, _>>(), m);
+}
+
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+proptest!{
+    #[test]
+    fn check_bimaps(ops in prop::collection::vec((0u8..6, 0u16..200, 0i64..200), 0usize..1000)) {
+        check_bimap(&ops);
+    }
+}
+
+#[test]
+fn test_bimap64() {
+    let mut m: BiMap64<u16, i64> = (0..300).map(|l| (l, l as i64 * 1_000_003 - 100_000_000)).collect();
+    assert_eq!(m.len(), 300);
+    while m.len() > 1 {
+        let (l, x) = m.iter().next().unwrap();
+        if l % 2 == 0 {
+            assert_eq!(m.remove_by_left(l), Some((l, x)));
+        } else {
+            assert_eq!(m.remove_by_right(x), Some((l, x)));
+        }
+    }
+    let (l, x) = m.iter().next().unwrap();
+    assert!(matches!(m.left, Map64U::One(..)));
+    assert!(matches!(m.right, Map64U::One(..)));
+    assert_eq!(m.get_by_right(x), Some(l));
+    m.remove_by_left(l);
+    assert!(matches!((&m.left, &m.right), (Map64U::Empty, Map64U::Empty)));
+}
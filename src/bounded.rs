@@ -0,0 +1,220 @@
+// Copyright 2019 David Roundy
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A set drawn from a known range of values.
+
+use crate::SetU32;
+use crate::error::TryReserveError;
+
+/// A set of `u32` values drawn from a fixed universe `0..n`, such as
+/// the registers of a machine or the blocks of a function.  Knowing
+/// the universe means the set can be complemented, and can tell when
+/// it is full.
+///
+/// The elements are stored in a [`SetU32`], so a nearly full set takes
+/// about as little space as a nearly empty one.
+///
+/// # Examples
+///
+/// ```
+/// use tinyset::BoundedSet;
+///
+/// let mut live = BoundedSet::new(64);
+/// live.insert(3);
+/// live.insert(40);
+/// let dead = live.complement();
+/// assert_eq!(dead.len(), 62);
+/// assert!(!dead.contains(3) && dead.contains(4));
+/// assert!(BoundedSet::all(64).is_full());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BoundedSet {
+    set: SetU32,
+    universe: u32,
+}
+
+impl BoundedSet {
+    /// Creates an empty set drawn from `0..universe`.
+    pub fn new(universe: u32) -> Self {
+        BoundedSet { set: SetU32::new(), universe }
+    }
+    /// Creates the set of every value in `0..universe`.
+    pub fn all(universe: u32) -> Self {
+        let mut set = SetU32::new();
+        set.insert_range(0..universe);
+        BoundedSet { set, universe }
+    }
+    /// Creates a set drawn from `0..universe` holding the elements of
+    /// `set`, or returns `None` if any of them is outside that range.
+    pub fn from_set(universe: u32, set: SetU32) -> Option<Self> {
+        if set.iter().max().iter().all(|&x| x < universe) {
+            Some(BoundedSet { set, universe })
+        } else {
+            None
+        }
+    }
+    /// The number of values the set is drawn from.
+    pub fn universe(&self) -> u32 {
+        self.universe
+    }
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+    /// Returns true if the set holds every value in its universe.
+    pub fn is_full(&self) -> bool {
+        self.set.len() == self.universe as usize
+    }
+    /// Returns true if the set contains a value.
+    pub fn contains(&self, value: u32) -> bool {
+        self.set.contains(value)
+    }
+    /// Adds a value to the set.
+    ///
+    /// If the set did not have this value present, `true` is returned.
+    ///
+    /// If the set did have this value present, `false` is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` is not in the universe.
+    pub fn insert(&mut self, value: u32) -> bool {
+        assert!(value < self.universe, "{} is outside the universe 0..{}", value, self.universe);
+        self.set.insert(value)
+    }
+    /// Removes an element, and returns true if that element was present.
+    pub fn remove(&mut self, value: u32) -> bool {
+        self.set.remove(value)
+    }
+    /// The set of values in the universe that are not in this set.
+    ///
+    /// A dense bitset is complemented by flipping its words, and a
+    /// complement with few elements is stored compactly rather than
+    /// as a bitset.
+    pub fn complement(&self) -> Self {
+        self.try_complement().unwrap_or_else(|err| err.bail())
+    }
+    /// The set of values in the universe that are not in this set, or
+    /// an error if we could not allocate the memory needed.
+    pub fn try_complement(&self) -> Result<Self, TryReserveError> {
+        let set = self.set.try_complement(self.universe)?;
+        Ok(BoundedSet { set, universe: self.universe })
+    }
+    /// Adds the elements of `other`, which must have the same universe.
+    pub fn union_with(&mut self, other: &BoundedSet) {
+        assert_eq!(self.universe, other.universe, "universes differ");
+        self.set.union_with(&other.set)
+    }
+    /// Keeps only the elements also in `other`, which must have the
+    /// same universe.
+    pub fn intersect_with(&mut self, other: &BoundedSet) {
+        assert_eq!(self.universe, other.universe, "universes differ");
+        self.set.intersect_with(&other.set)
+    }
+    /// Iterate
+    pub fn iter<'a>(&'a self) -> crate::setu32::Iter<'a> {
+        self.set.iter()
+    }
+    /// A cursor at the first element that is at least `x`, which then
+    /// moves through the set in increasing order.
+    pub fn cursor_at<'a>(&'a self, x: u32) -> crate::setu32::Cursor<'a> {
+        self.set.cursor_at(x)
+    }
+    /// The elements, as a set that is not bounded.
+    pub fn as_set(&self) -> &SetU32 {
+        &self.set
+    }
+    /// Converts into a set that is not bounded.
+    pub fn into_set(self) -> SetU32 {
+        self.set
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a> rayon::iter::IntoParallelIterator for &'a BoundedSet {
+    type Item = u32;
+    type Iter = crate::setu32::ParIter<'a>;
+    fn into_par_iter(self) -> crate::setu32::ParIter<'a> {
+        rayon::iter::IntoParallelIterator::into_par_iter(&self.set)
+    }
+}
+
+#[test]
+fn test_complement() {
+    let check = |b: &BoundedSet| {
+        let c = b.complement();
+        let n = b.universe();
+        assert_eq!(c.len() + b.len(), n as usize);
+        assert_eq!(c.is_full(), b.is_empty());
+        let mut got: Vec<u32> = c.iter().collect();
+        got.sort();
+        assert_eq!(got, (0..n).filter(|&x| !b.contains(x)).collect::<Vec<_>>());
+        let mut again: Vec<u32> = c.complement().iter().collect();
+        again.sort();
+        let mut orig: Vec<u32> = b.iter().collect();
+        orig.sort();
+        assert_eq!(again, orig);
+        c
+    };
+    for &n in &[0, 1, 31, 32, 33, 1000, 100_000] {
+        check(&BoundedSet::new(n));
+        check(&BoundedSet::all(n));
+        // Sets holding about 1%, 50% and 99% of the universe.
+        for &percent in &[1, 50, 99] {
+            let mut b = BoundedSet::new(n);
+            let mut x: u64 = 12345;
+            for i in 0..n {
+                x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                if (x >> 40) % 100 < percent {
+                    b.insert(i);
+                }
+            }
+            check(&b);
+        }
+    }
+
+    // A few elements leave a complement that is nearly all of the
+    // universe, which is best as a bitset.
+    let mut sparse = BoundedSet::new(100_000);
+    for i in 0..1000 {
+        sparse.insert(i*97 % 100_000);
+    }
+    let c = check(&sparse);
+    assert!(matches!(c.as_set().repr(), crate::Repr::Dense { .. }));
+    // Its complement in turn is small again.
+    assert!(c.complement().as_set().memory_report().total() < 10_000);
+    // Runs complement without visiting every value.
+    let mut runs = BoundedSet::all(1 << 30);
+    runs.remove(12345);
+    assert!(matches!(runs.as_set().repr(), crate::Repr::Runs { .. }));
+    assert_eq!(runs.complement().iter().collect::<Vec<_>>(), vec![12345]);
+    let back = runs.complement().complement();
+    assert_eq!(back.len(), (1 << 30) - 1);
+    assert!(!back.contains(12345) && back.contains(12346));
+}
+
+#[test]
+fn test_bounded_eq() {
+    use core::hash::{Hash, Hasher};
+    let hash = |b: &BoundedSet| {
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        b.hash(&mut h);
+        h.finish()
+    };
+    let mut a = BoundedSet::new(100);
+    let mut b = BoundedSet::new(100);
+    for x in 0..50 {
+        a.insert(x);
+        b.insert(49 - x);
+    }
+    assert_eq!(a, b);
+    assert_eq!(hash(&a), hash(&b));
+    assert_eq!(a.complement().complement(), b);
+    // The same elements drawn from another universe are another set.
+    let c = BoundedSet::from_set(200, a.as_set().clone()).unwrap();
+    assert_ne!(a, c);
+}
@@ -0,0 +1,588 @@
+//! The chunks of a large `SetU32`, split by the top 16 bits of its
+//! elements.
+//!
+//! The set keeps an index of entries sorted by key, each `ENTRY`
+//! words long.  An entry holds its chunk's key and the capacity of its
+//! array, then the number of elements, and then either up to four
+//! elements inline (as a `Tiny` set would) or a pointer to a sorted
+//! array or a bitmap, whichever the number of elements calls for.
+//! Arrays and bitmaps come from the set's allocator.  Every element is
+//! stored as its low 16 bits.
+
+use itertools::Itertools;
+use alloc::vec::Vec;
+use allocator_api2::alloc::Allocator;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use crate::error::TryReserveError;
+
+/// The number of words in each entry of the index.
+pub const ENTRY: usize = 4;
+/// The most elements kept inline in an entry.
+const INLINE_MAX: usize = 4;
+/// The most elements kept in a sorted array, above which a bitmap
+/// takes less memory.
+const ARRAY_MAX: usize = 4096;
+/// The smallest array we allocate.
+const ARRAY_MIN: usize = 8;
+/// The number of words in a bitmap of a whole chunk.
+const BITMAP_WORDS: usize = 1024;
+
+/// The elements of one chunk.
+pub enum Lows<'a> {
+    /// Up to `ARRAY_MAX` elements, in order.
+    Sorted(&'a [u16]),
+    /// A bit for every value in the chunk.
+    Bitmap(&'a [u64]),
+}
+
+/// The top 16 bits shared by the elements of chunk `e`.
+#[inline]
+pub fn key(e: &[u32]) -> u32 {
+    e[0] >> 16
+}
+/// The number of elements in chunk `e`.
+#[inline]
+pub fn len(e: &[u32]) -> usize {
+    e[1] as usize
+}
+fn cap(e: &[u32]) -> usize {
+    (e[0] & 0xffff) as usize
+}
+fn ptr(e: &[u32]) -> *mut u8 {
+    ((e[3] as u64) << 32 | e[2] as u64) as usize as *mut u8
+}
+fn set_storage(e: &mut [u32], key: u32, cap: usize, p: *mut u8) {
+    let p = p as usize as u64;
+    e[0] = key << 16 | cap as u32;
+    e[2] = p as u32;
+    e[3] = (p >> 32) as u32;
+}
+fn inline_mut(e: &mut [u32]) -> &mut [u16] {
+    unsafe { core::slice::from_raw_parts_mut(e[2..].as_mut_ptr() as *mut u16, INLINE_MAX) }
+}
+/// The whole array of chunk `e`, which must have one.
+unsafe fn array_mut<'a>(e: &[u32]) -> &'a mut [u16] {
+    core::slice::from_raw_parts_mut(ptr(e) as *mut u16, cap(e))
+}
+/// The bitmap of chunk `e`, which must have one.
+unsafe fn bitmap_mut<'a>(e: &[u32]) -> &'a mut [u64] {
+    core::slice::from_raw_parts_mut(ptr(e) as *mut u64, BITMAP_WORDS)
+}
+
+/// The elements of chunk `e`.
+#[inline]
+pub fn lows<'a>(e: &'a [u32]) -> Lows<'a> {
+    let n = len(e);
+    unsafe {
+        if n <= INLINE_MAX {
+            Lows::Sorted(core::slice::from_raw_parts(e[2..].as_ptr() as *const u16, n))
+        } else if n <= ARRAY_MAX {
+            Lows::Sorted(core::slice::from_raw_parts(ptr(e) as *const u16, n))
+        } else {
+            Lows::Bitmap(core::slice::from_raw_parts(ptr(e) as *const u64, BITMAP_WORDS))
+        }
+    }
+}
+
+/// The elements of chunk `e`, in order.
+fn values<'a>(e: &'a [u32]) -> impl Iterator<Item = u16> + 'a {
+    let (sorted, bitmap): (&[u16], &[u64]) = match lows(e) {
+        Lows::Sorted(v) => (v, &[]),
+        Lows::Bitmap(b) => (&[], b),
+    };
+    sorted.iter().cloned().chain(bits(bitmap))
+}
+
+/// The bits set in `b`, in order.
+fn bits<'a>(b: &'a [u64]) -> impl Iterator<Item = u16> + 'a {
+    b.iter().enumerate().flat_map(|(i, &w)| {
+        (0..64).filter(move |&bit| w >> bit & 1 != 0).map(move |bit| (i*64 + bit) as u16)
+    })
+}
+
+/// Whether chunk `e` holds `lo`.
+#[inline]
+pub fn contains(e: &[u32], lo: u16) -> bool {
+    match lows(e) {
+        Lows::Sorted(v) => v.binary_search(&lo).is_ok(),
+        Lows::Bitmap(b) => b[lo as usize >> 6] >> (lo & 63) & 1 != 0,
+    }
+}
+
+fn array_cap(n: usize) -> usize {
+    core::cmp::max(ARRAY_MIN, n.next_power_of_two())
+}
+fn array_layout(cap: usize) -> Layout {
+    Layout::array::<u16>(cap).unwrap()
+}
+fn bitmap_layout() -> Layout {
+    Layout::array::<u64>(BITMAP_WORDS).unwrap()
+}
+/// The layout of the storage of chunk `e`, if it has any.
+fn layout(e: &[u32]) -> Option<Layout> {
+    match len(e) {
+        n if n > ARRAY_MAX => Some(bitmap_layout()),
+        n if n > INLINE_MAX => Some(array_layout(cap(e))),
+        _ => None,
+    }
+}
+fn try_allocate<A: Allocator>(alloc: &A, layout: Layout) -> Result<*mut u8, TryReserveError> {
+    match alloc.allocate_zeroed(layout) {
+        Ok(p) => Ok(p.cast::<u8>().as_ptr()),
+        Err(_) => Err(TryReserveError::alloc_error(layout)),
+    }
+}
+
+/// The bytes of storage outside the index that a chunk of `n`
+/// elements built in one go would use.
+pub fn storage_for(n: usize) -> usize {
+    if n > ARRAY_MAX {
+        8*BITMAP_WORDS
+    } else if n > INLINE_MAX {
+        2*array_cap(n)
+    } else {
+        0
+    }
+}
+
+/// The bytes of storage outside the index used by chunk `e`, split
+/// into those that hold elements and those that do not.
+pub fn storage(e: &[u32]) -> (usize, usize) {
+    match lows(e) {
+        Lows::Sorted(_) if len(e) <= INLINE_MAX => (0, 0),
+        Lows::Sorted(v) => (2*v.len(), 2*(cap(e) - v.len())),
+        Lows::Bitmap(b) => {
+            let used = b.iter().filter(|&&w| w != 0).count();
+            (8*used, 8*(BITMAP_WORDS - used))
+        }
+    }
+}
+
+/// A new entry for chunk `key`, holding the `n` sorted elements
+/// `lows`.
+pub fn try_new<A: Allocator>(alloc: &A, key: u32, n: usize, lows: impl Iterator<Item = u16>)
+                             -> Result<[u32; ENTRY], TryReserveError> {
+    let mut e = [0; ENTRY];
+    if n > ARRAY_MAX {
+        let p = try_allocate(alloc, bitmap_layout())?;
+        let b = unsafe { core::slice::from_raw_parts_mut(p as *mut u64, BITMAP_WORDS) };
+        for lo in lows {
+            b[lo as usize >> 6] |= 1 << (lo & 63);
+        }
+        set_storage(&mut e, key, 0, p);
+    } else if n > INLINE_MAX {
+        let cap = array_cap(n);
+        let p = try_allocate(alloc, array_layout(cap))?;
+        let a = unsafe { core::slice::from_raw_parts_mut(p as *mut u16, cap) };
+        for (x, lo) in a.iter_mut().zip(lows) {
+            *x = lo;
+        }
+        set_storage(&mut e, key, cap, p);
+    } else {
+        e[0] = key << 16;
+        for (x, lo) in inline_mut(&mut e).iter_mut().zip(lows) {
+            *x = lo;
+        }
+    }
+    e[1] = n as u32;
+    Ok(e)
+}
+
+/// A copy of chunk `e` with storage of its own.
+pub fn try_clone<A: Allocator>(alloc: &A, e: &[u32]) -> Result<[u32; ENTRY], TryReserveError> {
+    let mut new = [0; ENTRY];
+    new.copy_from_slice(e);
+    if let Some(layout) = layout(e) {
+        let p = try_allocate(alloc, layout)?;
+        unsafe { core::ptr::copy_nonoverlapping(ptr(e), p, layout.size()) };
+        set_storage(&mut new, key(e), cap(e), p);
+    }
+    Ok(new)
+}
+
+/// Free the storage of chunk `e`, if it has any.
+///
+/// # Safety
+///
+/// `e` must have been made by this module using `alloc`, and must not
+/// be used again except to be overwritten or have its length zeroed.
+pub unsafe fn free<A: Allocator>(alloc: &A, e: &[u32]) {
+    if let Some(layout) = layout(e) {
+        alloc.deallocate(NonNull::new_unchecked(ptr(e)), layout);
+    }
+}
+
+/// Replace chunk `e` with `new`, freeing the storage of `e`.
+fn replace<A: Allocator>(alloc: &A, e: &mut [u32], new: [u32; ENTRY]) {
+    unsafe { free(alloc, e) };
+    e.copy_from_slice(&new);
+}
+
+/// Insert `lo` into chunk `e`, returning whether it was not already
+/// present.  On error the chunk is left unchanged.
+pub fn try_insert<A: Allocator>(alloc: &A, e: &mut [u32], lo: u16) -> Result<bool, TryReserveError> {
+    let n = len(e);
+    let i = match lows(e) {
+        Lows::Bitmap(_) => {
+            let w = unsafe { &mut bitmap_mut(e)[lo as usize >> 6] };
+            let bit = 1 << (lo & 63);
+            if *w & bit != 0 {
+                return Ok(false);
+            }
+            *w |= bit;
+            e[1] += 1;
+            return Ok(true);
+        }
+        Lows::Sorted(v) => match v.binary_search(&lo) {
+            Ok(_) => return Ok(false),
+            Err(i) => i,
+        },
+    };
+    if n < INLINE_MAX {
+        let v = inline_mut(e);
+        v.copy_within(i..n, i + 1);
+        v[i] = lo;
+    } else if n == INLINE_MAX || n == ARRAY_MAX {
+        // This needs the next bigger kind of storage.
+        let new = {
+            let (head, tail) = match lows(e) {
+                Lows::Sorted(v) => v.split_at(i),
+                Lows::Bitmap(_) => unreachable!(),
+            };
+            let all = head.iter().cloned().chain(Some(lo)).chain(tail.iter().cloned());
+            try_new(alloc, key(e), n + 1, all)?
+        };
+        replace(alloc, e, new);
+        return Ok(true);
+    } else {
+        if n == cap(e) {
+            let (old, new) = (array_layout(n), array_layout(2*n));
+            let p = match unsafe { alloc.grow(NonNull::new_unchecked(ptr(e)), old, new) } {
+                Ok(p) => p.cast::<u8>().as_ptr(),
+                Err(_) => return Err(TryReserveError::alloc_error(new)),
+            };
+            set_storage(e, key(e), 2*n, p);
+        }
+        let a = unsafe { array_mut(e) };
+        a.copy_within(i..n, i + 1);
+        a[i] = lo;
+    }
+    e[1] += 1;
+    Ok(true)
+}
+
+/// Remove `lo` from chunk `e`, returning whether it was present.  On
+/// error the chunk is left unchanged.
+pub fn try_remove<A: Allocator>(alloc: &A, e: &mut [u32], lo: u16) -> Result<bool, TryReserveError> {
+    let n = len(e);
+    let i = match lows(e) {
+        Lows::Bitmap(b) => {
+            let bit = 1 << (lo & 63);
+            if b[lo as usize >> 6] & bit == 0 {
+                return Ok(false);
+            }
+            if n > ARRAY_MAX + 1 {
+                unsafe { bitmap_mut(e)[lo as usize >> 6] &= !bit };
+                e[1] -= 1;
+                return Ok(true);
+            }
+            None
+        }
+        Lows::Sorted(v) => match v.binary_search(&lo) {
+            Ok(i) => Some(i),
+            Err(_) => return Ok(false),
+        },
+    };
+    match i {
+        Some(i) if n <= INLINE_MAX => {
+            inline_mut(e).copy_within(i + 1..n, i);
+        }
+        Some(i) if n > INLINE_MAX + 1 => {
+            unsafe { array_mut(e) }.copy_within(i + 1..n, i);
+            // Halve the array once it is three quarters empty.
+            let cap = cap(e);
+            if n - 1 <= cap/4 && cap > ARRAY_MIN {
+                let (old, new) = (array_layout(cap), array_layout(cap/2));
+                if let Ok(p) = unsafe { alloc.shrink(NonNull::new_unchecked(ptr(e)), old, new) } {
+                    set_storage(e, key(e), cap/2, p.cast::<u8>().as_ptr());
+                }
+            }
+        }
+        _ => {
+            // This fits in the next smaller kind of storage.
+            let new = try_new(alloc, key(e), n - 1, values(e).filter(|&x| x != lo))?;
+            replace(alloc, e, new);
+            return Ok(true);
+        }
+    }
+    e[1] -= 1;
+    Ok(true)
+}
+
+/// Add the elements of chunk `b` to chunk `e`, which has the same key.
+pub fn try_union<A: Allocator>(alloc: &A, e: &mut [u32], b: &[u32]) -> Result<(), TryReserveError> {
+    if let Lows::Bitmap(_) = lows(e) {
+        let x = unsafe { bitmap_mut(e) };
+        match lows(b) {
+            Lows::Bitmap(y) => crate::simd::or_words(x, y),
+            Lows::Sorted(y) => y.iter().for_each(|&lo| x[lo as usize >> 6] |= 1 << (lo & 63)),
+        }
+        e[1] = crate::simd::count_ones_u64(x) as u32;
+        return Ok(());
+    }
+    let merged: Vec<u16> = crate::error::try_collect(values(e).merge(values(b)).dedup())?;
+    let new = try_new(alloc, key(e), merged.len(), merged.iter().cloned())?;
+    replace(alloc, e, new);
+    Ok(())
+}
+
+/// Remove the elements of chunk `e` that are not in chunk `b`, which
+/// has the same key.
+pub fn try_intersect<A: Allocator>(alloc: &A, e: &mut [u32], b: &[u32]) -> Result<(), TryReserveError> {
+    if let (Lows::Bitmap(_), Lows::Bitmap(y)) = (lows(e), lows(b)) {
+        let x = unsafe { bitmap_mut(e) };
+        crate::simd::and_words(x, y);
+        let n = crate::simd::count_ones_u64(x);
+        if n > ARRAY_MAX {
+            e[1] = n as u32;
+        } else {
+            let new = try_new(alloc, key(e), n, bits(x))?;
+            replace(alloc, e, new);
+        }
+        return Ok(());
+    }
+    let kept: Vec<u16> = crate::error::try_collect(values(e).filter(|&lo| contains(b, lo)))?;
+    let new = try_new(alloc, key(e), kept.len(), kept.iter().cloned())?;
+    replace(alloc, e, new);
+    Ok(())
+}
+
+/// An iterator over the elements in an index of chunks, in order.
+#[derive(Debug, Clone)]
+pub struct ChunksIter<'a> {
+    sz_left: usize,
+    index: &'a [u32],
+    front: Cursor<'a>,
+    back: Cursor<'a>,
+}
+
+impl<'a> ChunksIter<'a> {
+    /// Iterate over the `sz` elements in the chunks of `index`.
+    pub fn new(sz: usize, index: &'a [u32]) -> Self {
+        ChunksIter { sz_left: sz, index, front: Cursor::default(), back: Cursor::default() }
+    }
+}
+
+impl<'a> Iterator for ChunksIter<'a> {
+    type Item = u32;
+    #[inline]
+    fn next(&mut self) -> Option<u32> {
+        if self.sz_left == 0 {
+            return None;
+        }
+        loop {
+            if let Some(x) = self.front.next() {
+                self.sz_left -= 1;
+                return Some(x);
+            }
+            if self.index.len() < ENTRY {
+                // All that is left is in the chunk begun from the back.
+                self.front = core::mem::take(&mut self.back);
+                self.sz_left -= 1;
+                return self.front.next();
+            }
+            let (e, rest) = self.index.split_at(ENTRY);
+            self.index = rest;
+            self.front = Cursor::new(e);
+        }
+    }
+    #[inline]
+    fn last(mut self) -> Option<u32> {
+        self.next_back()
+    }
+    #[inline]
+    fn max(self) -> Option<u32> {
+        self.last()
+    }
+    #[inline]
+    fn count(self) -> usize {
+        self.sz_left
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.sz_left, Some(self.sz_left))
+    }
+    #[inline]
+    fn min(mut self) -> Option<u32> {
+        self.next()
+    }
+}
+
+impl<'a> DoubleEndedIterator for ChunksIter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<u32> {
+        if self.sz_left == 0 {
+            return None;
+        }
+        loop {
+            if let Some(x) = self.back.next_back() {
+                self.sz_left -= 1;
+                return Some(x);
+            }
+            if self.index.len() < ENTRY {
+                // All that is left is in the chunk begun from the front.
+                self.back = core::mem::take(&mut self.front);
+                self.sz_left -= 1;
+                return self.back.next_back();
+            }
+            let (rest, e) = self.index.split_at(self.index.len() - ENTRY);
+            self.index = rest;
+            self.back = Cursor::new(e);
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a> crate::par::Split for ChunksIter<'a> {
+    fn split(self) -> (Self, Option<Self>) {
+        let n = self.index.len() / ENTRY;
+        if self.sz_left < crate::par::MIN_LEN || n < 2
+            || !self.front.is_empty() || !self.back.is_empty()
+        {
+            return (self, None);
+        }
+        let (front, back) = self.index.split_at(n / 2 * ENTRY);
+        let right = back.chunks(ENTRY).map(len).sum::<usize>();
+        (ChunksIter::new(self.sz_left - right, front), Some(ChunksIter::new(right, back)))
+    }
+}
+
+/// The values left in one chunk, which may be taken from either end.
+#[derive(Debug, Clone, Default)]
+struct Cursor<'a> {
+    high: u32,
+    sorted: &'a [u16],
+    bitmap: &'a [u64],
+    // The values left in the bitmap are those in lo..hi.
+    lo: u32,
+    hi: u32,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(e: &'a [u32]) -> Self {
+        let high = key(e) << 16;
+        match lows(e) {
+            Lows::Sorted(sorted) => Cursor { high, sorted, bitmap: &[], lo: 0, hi: 0 },
+            Lows::Bitmap(bitmap) => Cursor { high, sorted: &[], bitmap, lo: 0, hi: 1 << 16 },
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.sorted.is_empty() && self.lo >= self.hi
+    }
+
+    fn next(&mut self) -> Option<u32> {
+        if let Some((&lo, rest)) = self.sorted.split_first() {
+            self.sorted = rest;
+            return Some(self.high | lo as u32);
+        }
+        while self.lo < self.hi {
+            let i = self.lo / 64;
+            let w = self.bitmap[i as usize] >> (self.lo % 64);
+            if w != 0 {
+                let x = self.lo + w.trailing_zeros();
+                if x >= self.hi {
+                    break;
+                }
+                self.lo = x + 1;
+                return Some(self.high | x);
+            }
+            self.lo = (i + 1)*64;
+        }
+        None
+    }
+
+    /// Skip the values below `lo` in this chunk.
+    fn seek(&mut self, lo: u32) {
+        let i = match self.sorted.binary_search(&(lo as u16)) {
+            Ok(i) | Err(i) => i,
+        };
+        self.sorted = &self.sorted[i..];
+        self.lo = core::cmp::max(self.lo, lo);
+    }
+
+    fn next_back(&mut self) -> Option<u32> {
+        if let Some((&lo, rest)) = self.sorted.split_last() {
+            self.sorted = rest;
+            return Some(self.high | lo as u32);
+        }
+        while self.hi > self.lo {
+            let i = (self.hi - 1) / 64;
+            let w = self.bitmap[i as usize] << (63 - (self.hi - 1) % 64);
+            if w != 0 {
+                let x = self.hi - 1 - w.leading_zeros();
+                if x < self.lo {
+                    break;
+                }
+                self.hi = x;
+                return Some(self.high | x);
+            }
+            self.hi = i*64;
+        }
+        None
+    }
+}
+
+/// The elements in the chunks of an index in increasing order, which
+/// can skip ahead to any value.
+#[derive(Debug, Clone)]
+pub struct ChunksCursor<'a> {
+    index: &'a [u32],
+    cur: Cursor<'a>,
+}
+
+impl<'a> ChunksCursor<'a> {
+    /// Walk the chunks of `index`.
+    pub fn new(index: &'a [u32]) -> Self {
+        ChunksCursor { index, cur: Cursor::default() }
+    }
+
+    /// The first element that is at least `x`, skipping over any
+    /// chunks of smaller values without looking inside them.
+    pub fn seek(&mut self, x: u32) -> Option<u32> {
+        let high = x >> 16;
+        if self.cur.is_empty() || self.cur.high >> 16 < high {
+            let n = self.index.len() / ENTRY;
+            let i = crate::search::gallop(0, n, |i| key(&self.index[i*ENTRY..]) < high);
+            self.index = &self.index[i*ENTRY..];
+            self.cur = Cursor::default();
+            if self.index.len() >= ENTRY && key(self.index) == high {
+                let (e, rest) = self.index.split_at(ENTRY);
+                self.index = rest;
+                self.cur = Cursor::new(e);
+            }
+        }
+        if self.cur.high >> 16 == high {
+            self.cur.seek(x & 0xffff);
+        }
+        self.next()
+    }
+}
+
+impl<'a> Iterator for ChunksCursor<'a> {
+    type Item = u32;
+    fn next(&mut self) -> Option<u32> {
+        loop {
+            if let Some(x) = self.cur.next() {
+                return Some(x);
+            }
+            if self.index.is_empty() {
+                return None;
+            }
+            let (e, rest) = self.index.split_at(ENTRY);
+            self.index = rest;
+            self.cur = Cursor::new(e);
+        }
+    }
+}
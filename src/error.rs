@@ -0,0 +1,110 @@
+//! Errors returned by the fallible methods.
+
+use alloc::alloc::Layout;
+use alloc::vec::Vec;
+
+/// The error returned by `try_insert`, `try_reserve` and
+/// `try_from_iter` when a set cannot get the memory it needs.
+///
+/// When this is returned the set is left exactly as it was before the
+/// call.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TryReserveError {
+    kind: TryReserveErrorKind,
+}
+
+/// Why a [`TryReserveError`] happened.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TryReserveErrorKind {
+    /// The requested capacity does not fit in the set's size fields,
+    /// or the allocation would exceed `isize::MAX` bytes.
+    CapacityOverflow,
+    /// The allocator returned an error.
+    AllocError {
+        /// The layout of the allocation that failed.
+        layout: Layout,
+    },
+}
+
+impl TryReserveError {
+    /// Details about the allocation that caused the error.
+    pub fn kind(&self) -> TryReserveErrorKind {
+        self.kind.clone()
+    }
+
+    pub(crate) fn capacity_overflow() -> Self {
+        TryReserveError { kind: TryReserveErrorKind::CapacityOverflow }
+    }
+
+    pub(crate) fn alloc_error(layout: Layout) -> Self {
+        TryReserveError { kind: TryReserveErrorKind::AllocError { layout } }
+    }
+
+    /// Fail the way the infallible methods do: a panic for a
+    /// capacity overflow, or `handle_alloc_error` if we ran out of
+    /// memory.
+    pub(crate) fn bail(self) -> ! {
+        match self.kind {
+            TryReserveErrorKind::CapacityOverflow => panic!("capacity overflow"),
+            TryReserveErrorKind::AllocError { layout } =>
+                alloc::alloc::handle_alloc_error(layout),
+        }
+    }
+}
+
+impl core::fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.kind {
+            TryReserveErrorKind::CapacityOverflow =>
+                f.write_str("memory allocation failed because the computed capacity \
+                             exceeded the set's maximum"),
+            TryReserveErrorKind::AllocError { .. } =>
+                f.write_str("memory allocation failed because the memory allocator \
+                             returned an error"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", test))]
+impl std::error::Error for TryReserveError {}
+
+/// The error returned when a [`TinySetU64`](crate::TinySetU64) cannot
+/// hold its elements in its one word.
+///
+/// When this is returned the set is left exactly as it was before the
+/// call.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Full;
+
+impl core::fmt::Display for Full {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("the elements do not fit in a TinySetU64")
+    }
+}
+
+#[cfg(any(feature = "std", test))]
+impl std::error::Error for Full {}
+
+/// Collect an iterator into a `Vec` without aborting if we run out of
+/// memory.
+pub(crate) fn try_collect<T, I: Iterator<Item = T>>(iter: I) -> Result<Vec<T>, TryReserveError> {
+    let mut v: Vec<T> = Vec::new();
+    try_grow(&mut v, iter.size_hint().0)?;
+    for x in iter {
+        if v.len() == v.capacity() {
+            let cap = core::cmp::max(4, v.capacity());
+            try_grow(&mut v, cap)?;
+        }
+        v.push(x);
+    }
+    Ok(v)
+}
+
+fn try_grow<T>(v: &mut Vec<T>, additional: usize) -> Result<(), TryReserveError> {
+    v.try_reserve_exact(additional).map_err(|_| {
+        match v.capacity().checked_add(additional).map(Layout::array::<T>) {
+            Some(Ok(layout)) => TryReserveError::alloc_error(layout),
+            _ => TryReserveError::capacity_overflow(),
+        }
+    })
+}
@@ -0,0 +1,70 @@
+//! Where keys live in the `Heap` and `Big` hash tables.
+//!
+//! By default a key `k` in a table of `n` slots belongs in slot
+//! `k % n`, which is fast and works well for typical keys.  It also
+//! means that anyone who can choose the keys (and guess `n`) can make
+//! every key land in the same slot, which turns each lookup into a
+//! linear scan.  With the `keyed-hash` feature we instead mix each key
+//! with a secret that is chosen at random the first time a table is
+//! used, so that the slot a key lands in cannot be predicted without
+//! knowing that secret.  This costs a couple of multiplications per
+//! probe sequence.  Under the `deterministic` feature the secret is
+//! derived from the seed given to `set_random_seed`.
+
+#[cfg(feature = "keyed-hash")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "keyed-hash")]
+static KEY: AtomicUsize = AtomicUsize::new(0);
+
+/// The process-wide secret.  Every table must agree on this for as
+/// long as it exists, so we only ever set it once.
+#[cfg(feature = "keyed-hash")]
+fn key() -> u64 {
+    let k = KEY.load(Ordering::Relaxed);
+    if k != 0 {
+        return k as u64;
+    }
+    // The salt is arbitrary, it just spells "keyed".
+    let new = crate::rand::rand_usize(0x6b_6579_6564) | 1;
+    match KEY.compare_exchange(0, new, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => new as u64,
+        Err(k) => k as u64,
+    }
+}
+
+/// The slot where `k` would like to live in a table of `n` slots.
+#[cfg(not(feature = "keyed-hash"))]
+#[inline]
+pub fn home64(k: u64, n: usize) -> usize {
+    (k % n as u64) as usize
+}
+
+/// The slot where `k` would like to live in a table of `n` slots.
+#[cfg(feature = "keyed-hash")]
+#[inline]
+pub fn home64(k: u64, n: usize) -> usize {
+    // The SplitMix64 finalizer.
+    let z = k ^ key().wrapping_mul(0x9e3779b97f4a7c15);
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    ((z ^ (z >> 31)) % n as u64) as usize
+}
+
+/// The slot where `k` would like to live in a table of `n` slots.
+#[cfg(not(feature = "keyed-hash"))]
+#[inline]
+pub fn home32(k: u32, n: usize) -> usize {
+    (k % n as u32) as usize
+}
+
+/// The slot where `k` would like to live in a table of `n` slots.
+#[cfg(feature = "keyed-hash")]
+#[inline]
+pub fn home32(k: u32, n: usize) -> usize {
+    // The MurmurHash3 finalizer.
+    let z = k ^ (key() as u32);
+    let z = (z ^ (z >> 16)).wrapping_mul(0x85eb_ca6b);
+    let z = (z ^ (z >> 13)).wrapping_mul(0xc2b2_ae35);
+    ((z ^ (z >> 16)) % n as u32) as usize
+}
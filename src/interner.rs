@@ -0,0 +1,293 @@
+// Copyright 2019 David Roundy
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Interning sets, so that equal sets share one id.
+
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+use crate::map64::Map64U;
+
+/// The id of a set in a [`SetInterner`].  Two ids from the same
+/// interner are equal exactly when their sets are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SetId(u32);
+
+/// Marks the end of a chain of sets with the same hash.
+const NONE: u32 = u32::MAX;
+
+/// Stores one copy of each distinct set it is given, and hands out a
+/// [`SetId`] for it, so that equal sets can be compared (and hashed,
+/// and stored) as ids.  Sets are equal when they hold the same
+/// elements, whatever their internal layout.
+///
+/// Unions and intersections are remembered by the pair of ids they
+/// were computed from, so asking for one again costs a lookup.
+///
+/// # Examples
+///
+/// ```
+/// use tinyset::{Set64, SetInterner};
+///
+/// let mut sets = SetInterner::new();
+/// let a = sets.intern(Set64::from([1u32, 2]));
+/// let b = sets.intern(Set64::from([3u32]));
+/// let ab = sets.union(a, b);
+/// assert_eq!(ab, sets.intern(Set64::from([3, 2, 1])));
+/// assert_eq!(sets.intersection(ab, a), a);
+/// assert_eq!(sets[ab].len(), 3);
+/// assert_eq!(sets.len(), 3);
+/// ```
+#[derive(Clone)]
+pub struct SetInterner<S> {
+    sets: Vec<S>,
+    /// For each set, the id of an earlier set with the same hash.
+    next: Vec<u32>,
+    /// The latest set with each hash.
+    by_hash: Map64U,
+    unions: Map64U,
+    intersections: Map64U,
+}
+
+impl<S: Hash + Eq> Default for SetInterner<S> {
+    fn default() -> Self {
+        SetInterner::new()
+    }
+}
+
+impl<S: Hash + Eq> SetInterner<S> {
+    /// Creates an interner with no sets.
+    pub fn new() -> Self {
+        SetInterner {
+            sets: Vec::new(),
+            next: Vec::new(),
+            by_hash: Map64U::new(),
+            unions: Map64U::new(),
+            intersections: Map64U::new(),
+        }
+    }
+    /// Returns the number of distinct sets.
+    pub fn len(&self) -> usize {
+        self.sets.len()
+    }
+    /// Returns true if no sets have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+    /// The id of `s`, adding it if no equal set has been interned.
+    pub fn intern(&mut self, s: S) -> SetId {
+        let h = hash(&s);
+        if let Some(id) = self.find(h, &s) {
+            return id;
+        }
+        assert!(self.sets.len() < NONE as usize, "too many sets to intern");
+        let id = self.sets.len() as u32;
+        self.next.push(self.by_hash.insert(h, id as u64).map_or(NONE, |i| i as u32));
+        self.sets.push(s);
+        SetId(id)
+    }
+    /// The id of a set equal to `s`, if there is one.
+    pub fn id_of(&self, s: &S) -> Option<SetId> {
+        self.find(hash(s), s)
+    }
+    /// The set with the given id.
+    pub fn get(&self, id: SetId) -> &S {
+        &self.sets[id.0 as usize]
+    }
+    /// Iterates over the ids and sets, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (SetId, &S)> {
+        self.sets.iter().enumerate().map(|(i, s)| (SetId(i as u32), s))
+    }
+    fn find(&self, h: u64, s: &S) -> Option<SetId> {
+        let mut i = self.by_hash.get(h).map_or(NONE, |i| i as u32);
+        while i != NONE {
+            if self.sets[i as usize] == *s {
+                return Some(SetId(i));
+            }
+            i = self.next[i as usize];
+        }
+        None
+    }
+}
+
+impl<S: Hash + Eq> SetInterner<S>
+    where for<'a> &'a S: core::ops::BitOr<&'a S, Output = S> + core::ops::BitAnd<&'a S, Output = S>
+{
+    /// The id of the union of two sets.
+    pub fn union(&mut self, a: SetId, b: SetId) -> SetId {
+        if a == b {
+            return a;
+        }
+        let key = pair(a, b);
+        if let Some(id) = self.unions.get(key) {
+            return SetId(id as u32);
+        }
+        let id = self.intern(self.get(a) | self.get(b));
+        self.unions.insert(key, id.0 as u64);
+        id
+    }
+    /// The id of the intersection of two sets.
+    pub fn intersection(&mut self, a: SetId, b: SetId) -> SetId {
+        if a == b {
+            return a;
+        }
+        let key = pair(a, b);
+        if let Some(id) = self.intersections.get(key) {
+            return SetId(id as u32);
+        }
+        let id = self.intern(self.get(a) & self.get(b));
+        self.intersections.insert(key, id.0 as u64);
+        id
+    }
+}
+
+impl<S: Hash + Eq> core::ops::Index<SetId> for SetInterner<S> {
+    type Output = S;
+    fn index(&self, id: SetId) -> &S {
+        self.get(id)
+    }
+}
+
+impl<S: Hash + Eq + core::fmt::Debug> core::fmt::Debug for SetInterner<S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// The memo key for an operation that does not care about order.
+fn pair(a: SetId, b: SetId) -> u64 {
+    let (a, b) = if a <= b { (a, b) } else { (b, a) };
+    (a.0 as u64) << 32 | b.0 as u64
+}
+
+fn hash<S: Hash>(s: &S) -> u64 {
+    let mut h = FxHasher(0);
+    s.hash(&mut h);
+    h.finish()
+}
+
+/// The hash from `rustc` (which `std`'s hashers are not available
+/// without `std`), which is quick for the integers the sets hash.
+struct FxHasher(u64);
+
+impl FxHasher {
+    fn add(&mut self, x: u64) {
+        self.0 = (self.0.rotate_left(5) ^ x).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.add(b as u64);
+        }
+    }
+    fn write_u32(&mut self, x: u32) {
+        self.add(x as u64);
+    }
+    fn write_u64(&mut self, x: u64) {
+        self.add(x);
+    }
+    fn write_usize(&mut self, x: usize) {
+        self.add(x as u64);
+    }
+}
+
+#[test]
+fn test_interner() {
+    use crate::{Repr, SetU64Builder, SetU32};
+    // The same elements in four layouts intern as one set.
+    let elems = || (0..3000u64).map(|x| x*3);
+    let layouts = vec![
+        SetU64Builder::new().build_from_iter(elems()),
+        SetU64Builder::new().force_big().build_from_iter(elems()),
+        SetU64Builder::new().dense(false).runs(false).build_from_iter(elems()),
+        SetU64Builder::new().runs(false).build_from_iter(elems()),
+    ];
+    let reprs: Vec<_> = layouts.iter().map(|s| core::mem::discriminant(&s.repr())).collect();
+    assert!(reprs.iter().any(|&r| r != reprs[0]));
+    let mut sets = SetInterner::new();
+    let ids: Vec<SetId> = layouts.into_iter().map(|s| sets.intern(s)).collect();
+    assert!(ids.iter().all(|&id| id == ids[0]));
+    assert_eq!(sets.len(), 1);
+    assert!(!matches!(sets[ids[0]].repr(), Repr::Empty));
+
+    // Unions and intersections agree with computing them directly.
+    let mut sets: SetInterner<SetU32> = SetInterner::new();
+    let pieces: Vec<SetU32> = (0..20).map(|i| (0..50).map(|x| x*(i + 1) % 97).collect()).collect();
+    let ids: Vec<SetId> = pieces.iter().cloned().map(|s| sets.intern(s)).collect();
+    for (i, a) in pieces.iter().enumerate() {
+        for (j, b) in pieces.iter().enumerate() {
+            let u = sets.union(ids[i], ids[j]);
+            assert_eq!(sets[u], a | b);
+            assert_eq!(sets.union(ids[j], ids[i]), u);
+            let n = sets.intersection(ids[i], ids[j]);
+            assert_eq!(sets[n], a & b);
+            assert_eq!(sets.id_of(&(a & b)), Some(n));
+        }
+    }
+    assert_eq!(sets.id_of(&SetU32::from([1000])), None);
+    let distinct: alloc::collections::BTreeSet<Vec<u32>> = sets.iter().map(|(_, s)| s.cursor_at(0).collect()).collect();
+    assert_eq!(distinct.len(), sets.len());
+}
+
+#[test]
+fn test_interner_hash_tables() {
+    use crate::{Repr, SetU64Builder};
+    // Sets built in different orders, some of them grown further and
+    // then shrunk back, hold their elements in different slots of
+    // their hash tables, but hash (and intern) as one set.
+    let mut sets = SetInterner::new();
+    let mut first = None;
+    let mut orders = alloc::collections::BTreeSet::new();
+    for i in 0..20u64 {
+        let order = (0..200u64).map(|x| (x*37 + i) % 200);
+        let mut s = if i % 2 == 0 {
+            SetU64Builder::new().runs(false).dense(false).build_from_iter(order.map(|x| x*1_000_003))
+        } else {
+            SetU64Builder::new().force_big().build_from_iter(order.map(|x| x*1_000_003))
+        };
+        let extra: Vec<u64> = (0..i % 5 * 100).map(|x| x*1_000_003 + 1).collect();
+        s.insert_slice(&extra);
+        s.remove_slice(&extra);
+        assert!(matches!(s.repr(), Repr::Heap { .. } | Repr::Big { .. }));
+        orders.insert(s.iter().collect::<Vec<u64>>());
+        let id = sets.intern(s);
+        assert_eq!(*first.get_or_insert(id), id);
+    }
+    assert_eq!(sets.len(), 1);
+    assert!(orders.len() > 2);
+}
+
+#[cfg(test)]
+fn check_intern_orders(v: &[u64], rotate: usize, grow: usize) {
+    use crate::SetU64Builder;
+    let mut sets = SetInterner::new();
+    let id = sets.intern(SetU64Builder::new().runs(false).dense(false).build_from_iter(v.iter().cloned()));
+    let rotate = rotate % (v.len() + 1);
+    let order = v[rotate..].iter().chain(&v[..rotate]).cloned();
+    let mut s = SetU64Builder::new().force_big().build_from_iter(order);
+    let extra: Vec<u64> = (0..grow as u64).map(|x| x.wrapping_mul(1_000_003) ^ 1)
+        .filter(|x| !v.contains(x)).collect();
+    s.insert_slice(&extra);
+    s.remove_slice(&extra);
+    assert_eq!(sets.intern(s), id);
+    assert_eq!(sets.intern(SetU64Builder::new().build_from_iter(v.iter().cloned())), id);
+    assert_eq!(sets.len(), 1);
+}
+
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+proptest!{
+    #[test]
+    fn check_interned_orders(v: Vec<u64>, rotate: usize, grow in 0usize..500) {
+        check_intern_orders(&v, rotate, grow);
+    }
+}
@@ -0,0 +1,397 @@
+// Copyright 2019 David Roundy
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A trait for writing code that works with any of the sets.
+
+use crate::set64::{self, Fits64, Set64};
+use crate::setu32::{self, SetU32};
+use crate::setu64::{self, SetU64};
+use crate::setu64inline::{self, Set64Inline, SetU64Inline};
+use crate::setusize::{self, SetUsize};
+
+/// A set of small `Copy` values, for code that should work with any
+/// of the sets in this crate (and, with the `std-sets` feature, with
+/// `HashSet` and `BTreeSet`).
+///
+/// Elements are passed and iterated by value, as with the sets of
+/// this crate.  The set operations return new sets, like the `|`,
+/// `&`, `-` and `^` operators.
+///
+/// # Examples
+///
+/// ```
+/// use tinyset::{IntSet, SetU32, SetUsize};
+///
+/// fn reachable<S: IntSet>(edges: &[(S::Item, S::Item)], start: S::Item) -> S {
+///     let mut seen = S::with_capacity(1);
+///     seen.insert(start);
+///     let mut todo = vec![start];
+///     while let Some(n) = todo.pop() {
+///         for &(a, b) in edges {
+///             if a == n && seen.insert(b) {
+///                 todo.push(b);
+///             }
+///         }
+///     }
+///     seen
+/// }
+///
+/// let r: SetU32 = reachable(&[(0, 1), (1, 2), (3, 4)], 0);
+/// assert_eq!(r, SetU32::from([0, 1, 2]));
+/// let r: SetUsize = reachable(&[(0, 1), (1, 2), (3, 4)], 3);
+/// assert_eq!(r, SetUsize::from([3, 4]));
+/// ```
+pub trait IntSet: Default + Clone {
+    /// The type of the elements.
+    type Item: Copy + Eq;
+    /// An iterator over the elements.
+    type Iter<'a>: Iterator<Item = Self::Item> where Self: 'a;
+
+    /// Creates an empty set with room for `cap` elements.
+    fn with_capacity(cap: usize) -> Self;
+    /// Adds a value, returning true if it was not already present.
+    fn insert(&mut self, x: Self::Item) -> bool;
+    /// Removes a value, returning true if it was present.
+    fn remove(&mut self, x: Self::Item) -> bool;
+    /// Returns true if the set contains a value.
+    fn contains(&self, x: Self::Item) -> bool;
+    /// Returns the number of elements in the set.
+    fn len(&self) -> usize;
+    /// Iterates over the elements, in no particular order.
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /// Returns true if the set has no elements.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns the elements in either set.
+    fn union(&self, other: &Self) -> Self {
+        let mut s = self.clone();
+        for x in other.iter() {
+            s.insert(x);
+        }
+        s
+    }
+    /// Returns the elements in both sets.
+    fn intersection(&self, other: &Self) -> Self {
+        let mut s = Self::default();
+        for x in self.iter().filter(|&x| other.contains(x)) {
+            s.insert(x);
+        }
+        s
+    }
+    /// Returns the elements of this set that are not in `other`.
+    fn difference(&self, other: &Self) -> Self {
+        let mut s = self.clone();
+        for x in other.iter() {
+            s.remove(x);
+        }
+        s
+    }
+    /// Returns the elements in exactly one of the sets.
+    fn symmetric_difference(&self, other: &Self) -> Self {
+        let mut s = self.clone();
+        for x in other.iter() {
+            if !s.remove(x) {
+                s.insert(x);
+            }
+        }
+        s
+    }
+    /// Returns true if every element of this set is in `other`.
+    fn is_subset(&self, other: &Self) -> bool {
+        self.len() <= other.len() && self.iter().all(|x| other.contains(x))
+    }
+    /// Returns true if the sets have no elements in common.
+    fn is_disjoint(&self, other: &Self) -> bool {
+        let (small, big) = if self.len() <= other.len() { (self, other) } else { (other, self) };
+        !small.iter().any(|x| big.contains(x))
+    }
+}
+
+impl IntSet for SetU64 {
+    type Item = u64;
+    type Iter<'a> = setu64::Iter<'a>;
+    fn with_capacity(cap: usize) -> Self {
+        let mut s = SetU64::new();
+        s.try_reserve(cap).unwrap_or_else(|err| err.bail());
+        s
+    }
+    fn insert(&mut self, x: u64) -> bool {
+        self.insert(x)
+    }
+    fn remove(&mut self, x: u64) -> bool {
+        self.remove(x)
+    }
+    fn contains(&self, x: u64) -> bool {
+        self.contains(x)
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn iter(&self) -> setu64::Iter<'_> {
+        self.iter()
+    }
+    fn union(&self, other: &Self) -> Self {
+        self | other
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        self & other
+    }
+    fn difference(&self, other: &Self) -> Self {
+        self - other
+    }
+    fn symmetric_difference(&self, other: &Self) -> Self {
+        self ^ other
+    }
+}
+
+impl IntSet for SetU32 {
+    type Item = u32;
+    type Iter<'a> = setu32::Iter<'a>;
+    fn with_capacity(cap: usize) -> Self {
+        let mut s = SetU32::new();
+        s.try_reserve(cap).unwrap_or_else(|err| err.bail());
+        s
+    }
+    fn insert(&mut self, x: u32) -> bool {
+        self.insert(x)
+    }
+    fn remove(&mut self, x: u32) -> bool {
+        self.remove(x)
+    }
+    fn contains(&self, x: u32) -> bool {
+        self.contains(x)
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn iter(&self) -> setu32::Iter<'_> {
+        self.iter()
+    }
+    fn union(&self, other: &Self) -> Self {
+        self | other
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        self & other
+    }
+    fn difference(&self, other: &Self) -> Self {
+        self - other
+    }
+    fn symmetric_difference(&self, other: &Self) -> Self {
+        self ^ other
+    }
+}
+
+impl IntSet for SetUsize {
+    type Item = usize;
+    type Iter<'a> = setusize::Iter<'a>;
+    fn with_capacity(cap: usize) -> Self {
+        SetUsize::with_capacity(cap)
+    }
+    fn insert(&mut self, x: usize) -> bool {
+        self.insert(x)
+    }
+    fn remove(&mut self, x: usize) -> bool {
+        self.remove(x)
+    }
+    fn contains(&self, x: usize) -> bool {
+        self.contains(x)
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn iter(&self) -> setusize::Iter<'_> {
+        self.iter()
+    }
+    fn union(&self, other: &Self) -> Self {
+        self | other
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        self & other
+    }
+    fn difference(&self, other: &Self) -> Self {
+        self - other
+    }
+    fn symmetric_difference(&self, other: &Self) -> Self {
+        self ^ other
+    }
+}
+
+impl<T: Fits64 + Eq> IntSet for Set64<T> {
+    type Item = T;
+    type Iter<'a> = set64::Iter<'a, T> where T: 'a;
+    fn with_capacity(cap: usize) -> Self {
+        Set64::with_capacity(cap)
+    }
+    fn insert(&mut self, x: T) -> bool {
+        self.insert(x)
+    }
+    fn remove(&mut self, x: T) -> bool {
+        self.remove(&x)
+    }
+    fn contains(&self, x: T) -> bool {
+        self.contains(x)
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn iter(&self) -> set64::Iter<'_, T> {
+        self.iter()
+    }
+    fn union(&self, other: &Self) -> Self {
+        self | other
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        self & other
+    }
+    fn difference(&self, other: &Self) -> Self {
+        self - other
+    }
+    fn symmetric_difference(&self, other: &Self) -> Self {
+        self ^ other
+    }
+}
+
+impl<const WORDS: usize> IntSet for SetU64Inline<WORDS> {
+    type Item = u64;
+    type Iter<'a> = setu64inline::Iter<'a, WORDS>;
+    fn with_capacity(_cap: usize) -> Self {
+        SetU64Inline::new()
+    }
+    fn insert(&mut self, x: u64) -> bool {
+        self.insert(x)
+    }
+    fn remove(&mut self, x: u64) -> bool {
+        self.remove(x)
+    }
+    fn contains(&self, x: u64) -> bool {
+        self.contains(x)
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn iter(&self) -> setu64inline::Iter<'_, WORDS> {
+        self.iter()
+    }
+}
+
+impl<T: Fits64 + Eq, const WORDS: usize> IntSet for Set64Inline<T, WORDS> {
+    type Item = T;
+    type Iter<'a> = setu64inline::Iter64<'a, T, WORDS> where T: 'a;
+    fn with_capacity(_cap: usize) -> Self {
+        Set64Inline::new()
+    }
+    fn insert(&mut self, x: T) -> bool {
+        self.insert(x)
+    }
+    fn remove(&mut self, x: T) -> bool {
+        self.remove(&x)
+    }
+    fn contains(&self, x: T) -> bool {
+        self.contains(x)
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn iter(&self) -> setu64inline::Iter64<'_, T, WORDS> {
+        self.iter()
+    }
+}
+
+#[cfg(feature = "std-sets")]
+impl<T, S> IntSet for std::collections::HashSet<T, S>
+    where T: Copy + Eq + core::hash::Hash,
+          S: core::hash::BuildHasher + Default + Clone,
+{
+    type Item = T;
+    type Iter<'a> = core::iter::Copied<std::collections::hash_set::Iter<'a, T>> where Self: 'a;
+    fn with_capacity(cap: usize) -> Self {
+        std::collections::HashSet::with_capacity_and_hasher(cap, S::default())
+    }
+    fn insert(&mut self, x: T) -> bool {
+        self.insert(x)
+    }
+    fn remove(&mut self, x: T) -> bool {
+        self.remove(&x)
+    }
+    fn contains(&self, x: T) -> bool {
+        self.contains(&x)
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn iter(&self) -> Self::Iter<'_> {
+        self.iter().copied()
+    }
+}
+
+#[cfg(feature = "std-sets")]
+impl<T: Copy + Ord> IntSet for alloc::collections::BTreeSet<T> {
+    type Item = T;
+    type Iter<'a> = core::iter::Copied<alloc::collections::btree_set::Iter<'a, T>> where T: 'a;
+    /// A `BTreeSet` has no capacity, so this is just an empty set.
+    fn with_capacity(_cap: usize) -> Self {
+        alloc::collections::BTreeSet::new()
+    }
+    fn insert(&mut self, x: T) -> bool {
+        self.insert(x)
+    }
+    fn remove(&mut self, x: T) -> bool {
+        self.remove(&x)
+    }
+    fn contains(&self, x: T) -> bool {
+        self.contains(&x)
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn iter(&self) -> Self::Iter<'_> {
+        self.iter().copied()
+    }
+}
+
+#[cfg(test)]
+fn check<S: IntSet + core::fmt::Debug>(elems: impl Fn(u8) -> S::Item) {
+    let make = |v: &[u8]| {
+        let mut s = S::with_capacity(v.len());
+        for &x in v {
+            s.insert(elems(x));
+        }
+        s
+    };
+    let members = |s: &S| (0..=255).filter(|&x| s.contains(elems(x))).collect::<alloc::vec::Vec<u8>>();
+    let a = make(&[1, 2, 3, 200]);
+    let b = make(&[3, 4, 200, 7]);
+    assert_eq!(a.len(), 4);
+    assert!(!a.is_empty() && S::default().is_empty());
+    assert_eq!(a.iter().count(), 4);
+    assert_eq!(members(&a.union(&b)), [1, 2, 3, 4, 7, 200]);
+    assert_eq!(members(&a.intersection(&b)), [3, 200]);
+    assert_eq!(members(&a.difference(&b)), [1, 2]);
+    assert_eq!(members(&a.symmetric_difference(&b)), [1, 2, 4, 7]);
+    assert!(make(&[2, 3]).is_subset(&a) && !b.is_subset(&a));
+    assert!(make(&[5, 6]).is_disjoint(&a) && !b.is_disjoint(&a));
+    let mut c = a.clone();
+    assert!(c.remove(elems(2)) && !c.remove(elems(2)));
+    assert!(!c.contains(elems(2)) && c.contains(elems(1)));
+}
+
+#[test]
+fn test_int_set() {
+    check::<SetU64>(|x| x as u64 * 1000);
+    check::<SetU32>(|x| x as u32);
+    check::<SetUsize>(|x| x as usize + 1);
+    check::<Set64<i16>>(|x| x as i16 - 100);
+    check::<SetU64Inline<2>>(|x| x as u64);
+    check::<Set64Inline<i16, 1>>(|x| x as i16 - 100);
+    #[cfg(feature = "std-sets")]
+    {
+        check::<std::collections::HashSet<u64>>(|x| x as u64);
+        check::<alloc::collections::BTreeSet<char>>(|x| x as char);
+    }
+}
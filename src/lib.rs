@@ -24,6 +24,25 @@
 //! 4. [`SetUsize`] holds `usize` items, and uses either [SetU64] or
 //! [SetU32] internally.
 //!
+//! 5. [`BoundedSet`] holds `u32` items drawn from a known range
+//! `0..n`, and can be complemented.
+//!
+//! 6. [`Bag64`] counts how many times each of its elements (of the
+//! same types as [`Set64`]) has been inserted.
+//!
+//! 7. [`BiMap64`] pairs values of two such types one to one, and
+//! looks them up from either side.
+//!
+//! 8. [`SetU64Inline`] holds `u64` items like [`SetU64`], but has a
+//! buffer of a chosen number of words, so more elements fit before it
+//! allocates.
+//!
+//! 9. [`Set64Inline`] does the same for the types of [`Set64`].
+//!
+//! 10. [`TinySetU64`] holds up to seven `u64` items in a single word,
+//! and is `Copy`.  It never allocates, so an insert that does not fit
+//! fails instead.
+//!
 //! All of these set types will do no heap allocation for small sets of
 //! small elements.  `TinySet` will store up to 16 bytes of elements
 //! before doing any heap allocation, while `Set` stores sets up to size 8
@@ -37,6 +56,83 @@
 //! type-specific sets further differ in that `remove` and `contains`
 //! accept values rather than references.
 //!
+//! # `no_std` support
+//!
+//! The sets only need a global allocator, so `tinyset` can be built
+//! with `default-features = false` for `#![no_std]` targets that
+//! provide the `alloc` crate.  The `std` feature (on by default) is
+//! only used to seed the random number generator from the clock; see
+//! [`set_random_seed`] for supplying a seed yourself.
+//!
+//! # Deterministic layout
+//!
+//! By default the sets pick table sizes and sentinel values at
+//! random, so memory use and iteration order change from run to run.
+//! With the `deterministic` feature every such choice is instead
+//! computed from the seed given to [`set_random_seed`] (or a fixed
+//! default) and the state of the set, so the same sequence of
+//! operations always gives the same set, even when other sets are in
+//! use on other threads.  Without it, calling [`set_random_seed`]
+//! still swaps the random choices for a seeded generator, which gives
+//! the same sets as long as the whole program makes the same
+//! operations in the same order.
+//!
+//! # Hash flooding
+//!
+//! The hash tables place each key at its value modulo the table size,
+//! so someone who chooses your keys can make them all collide.  If
+//! your sets hold untrusted input (e.g. ids from network requests),
+//! enable the `keyed-hash` feature, which mixes every key with a
+//! secret chosen at random when the first table is used.
+//!
+//! # Custom allocators
+//!
+//! [`SetU32`] and [`SetU64`] take an optional [`Allocator`] parameter
+//! (from the `allocator-api2` crate, which works on stable Rust).  Use
+//! `SetU64::new_in(alloc)` to put a set's heap storage in an arena or
+//! other allocator.  Sets small enough to be stored inline never
+//! allocate.
+//!
+//! # Representation policies
+//!
+//! [`SetU64Builder`] and [`SetU32Builder`] make sets that follow a
+//! [`Policy`] rather than the built-in heuristics, for instance to
+//! forbid dense bitsets, to always use a plain hash table, or to grow
+//! tables by a fixed factor.  The set remembers its policy, so it
+//! keeps applying as elements are inserted and removed.
+//!
+//! ```
+//! use tinyset::{Repr, SetU64Builder};
+//! let mut s = SetU64Builder::new().dense(false).build();
+//! for i in 0..1000 {
+//!     s.insert(i);
+//! }
+//! assert!(!matches!(s.repr(), Repr::Dense { .. }));
+//! ```
+//!
+//! # Parallel iteration
+//!
+//! With the `rayon` feature, the sets get `par_iter()` (through
+//! `rayon::prelude::*`) and implement `FromParallelIterator` and
+//! `ParallelExtend`.  A parallel iterator splits a set along its own
+//! structure, so no thread has to walk past elements that another is
+//! handling, and a parallel collect builds a set on each thread and
+//! then merges them, combining bitsets, runs and chunks wholesale.
+//!
+//! # Generic code
+//!
+//! The [`IntSet`] trait covers inserting, removing, looking up,
+//! iterating and set algebra, so an algorithm can be written once and
+//! given whichever set suits the data.  With the `std-sets` feature it
+//! is also implemented for `HashSet` and `BTreeSet`, for comparison.
+//!
+//! # Interning
+//!
+//! A [`SetInterner`] keeps one copy of each distinct set and names it
+//! by a [`SetId`], for programs that build many equal sets.  Ids are
+//! compared in constant time, and unions and intersections of interned
+//! sets are remembered by id.
+//!
 //! # Examples
 //!
 //! ```
@@ -47,9 +143,31 @@
 //! ```
 
 #![deny(missing_docs)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 mod rand;
+mod hash;
+mod simd;
+mod chunks;
+mod search;
+#[cfg(feature = "rayon")]
+mod par;
+pub use crate::rand::set_random_seed;
+pub use allocator_api2::alloc::{Allocator, Global};
+
+mod error;
+pub use crate::error::{Full, TryReserveError, TryReserveErrorKind};
+mod repr;
+pub use crate::repr::{MemoryReport, Repr};
+#[macro_use]
+mod policy;
+pub use crate::policy::{DefaultPolicy, Policy, SetPolicy};
+
 mod sets;
+mod intset;
+pub use crate::intset::IntSet;
 
 #[doc(hidden)]
 pub mod setu32b;
@@ -58,16 +176,36 @@ pub mod setusize;
 pub use setusize::SetUsize;
 
 pub mod setu32;
-pub use setu32::SetU32;
+pub use setu32::{SetU32, SetU32Builder};
 
 pub mod setu64;
-pub use setu64::SetU64;
+pub use setu64::{SetU64, SetU64Builder};
+
+pub mod setu64inline;
+pub use setu64inline::{SetU64Inline, Set64Inline};
+
+pub mod tinysetu64;
+pub use tinysetu64::TinySetU64;
 
 pub mod set64;
 pub use crate::set64::{Set64, Fits64};
 
+pub mod bounded;
+pub use crate::bounded::BoundedSet;
+
+pub mod bag64;
+pub use crate::bag64::Bag64;
+
+pub mod bimap64;
+pub use crate::bimap64::BiMap64;
+
+pub mod interner;
+pub use crate::interner::{SetId, SetInterner};
+
 #[doc(hidden)]
 mod map64;
 
+#[cfg(test)]
 mod copyset;
+#[cfg(test)]
 mod anymap;
@@ -5,14 +5,332 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-//! A map from [Fits64] types to any other type.
+//! Maps from [Fits64](crate::Fits64) types, packed into `u64` tables.
+//!
+//! [`Map64U`] maps `u64` keys to `u64` values, and underlies the bags,
+//! bidirectional maps and interners.  Each key is stored together with
+//! its value (plus one, so that a slot of zeros is empty) in a single
+//! `u64` where both fit, using just as many bits for each as the
+//! largest key and value need.  Where they do not fit, each slot takes
+//! two words and a bitmap at the end of the table says which slots are
+//! in use.  A map with at most one entry is stored inline.
 
-/// A map from a [Fits64] key to another type.
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The number of bits needed to hold `x`, which is at least one.
+fn bits(x: u64) -> u8 {
+    core::cmp::max(1, 64 - x.leading_zeros() as u8)
+}
+
+/// The number of bits needed to hold `v + 1`.
+fn value_bits(v: u64) -> u8 {
+    v.checked_add(1).map_or(65, bits)
+}
+
+/// How many bits the keys and values of a table are given.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Lay {
+    key_bits: u8,
+    value_bits: u8,
+}
+
+impl Lay {
+    fn of(k: u64, v: u64) -> Lay {
+        Lay { key_bits: bits(k), value_bits: value_bits(v) }
+    }
+    fn wide(self) -> bool {
+        self.key_bits as u32 + self.value_bits as u32 > 64
+    }
+    fn fits(self, k: u64, v: u64) -> bool {
+        bits(k) <= self.key_bits && value_bits(v) <= self.value_bits
+    }
+    fn grow(self, k: u64, v: u64) -> Lay {
+        Lay {
+            key_bits: core::cmp::max(self.key_bits, bits(k)),
+            value_bits: core::cmp::max(self.value_bits, value_bits(v)),
+        }
+    }
+    /// The words taken by a table of `n` slots.
+    fn words(self, n: usize) -> usize {
+        if self.wide() { 2*n + n/64 + 1 } else { n }
+    }
+}
+
+/// A hash table with linear probing.
+#[derive(Clone)]
+pub(crate) struct Table {
+    lay: Lay,
+    len: usize,
+    slots: usize,
+    data: Box<[u64]>,
+}
+
+impl Table {
+    fn with_slots(lay: Lay, n: usize) -> Table {
+        Table { lay, len: 0, slots: n, data: vec![0; lay.words(n)].into_boxed_slice() }
+    }
+    /// The key and value in slot `i`, if it is in use.
+    fn slot(&self, i: usize) -> Option<(u64, u64)> {
+        if self.lay.wide() {
+            if self.data[2*self.slots + i/64] >> (i % 64) & 1 == 0 {
+                None
+            } else {
+                Some((self.data[2*i], self.data[2*i + 1]))
+            }
+        } else {
+            match self.data[i] {
+                0 => None,
+                x => Some((x & ((1 << self.lay.key_bits) - 1), (x >> self.lay.key_bits) - 1)),
+            }
+        }
+    }
+    fn set(&mut self, i: usize, k: u64, v: u64) {
+        if self.lay.wide() {
+            self.data[2*i] = k;
+            self.data[2*i + 1] = v;
+            self.data[2*self.slots + i/64] |= 1 << (i % 64);
+        } else {
+            self.data[i] = k | (v + 1) << self.lay.key_bits;
+        }
+    }
+    fn clear(&mut self, i: usize) {
+        if self.lay.wide() {
+            self.data[2*self.slots + i/64] &= !(1 << (i % 64));
+        } else {
+            self.data[i] = 0;
+        }
+    }
+    /// The slot holding `k`, or else the empty slot where it belongs.
+    fn find(&self, k: u64) -> (usize, Option<u64>) {
+        let mut i = crate::hash::home64(k, self.slots);
+        loop {
+            match self.slot(i) {
+                None => return (i, None),
+                Some((kk, v)) if kk == k => return (i, Some(v)),
+                _ => i = if i + 1 == self.slots { 0 } else { i + 1 },
+            }
+        }
+    }
+    /// The narrowest layout that holds every entry.
+    fn tight_lay(&self) -> Lay {
+        (0..self.slots).filter_map(|i| self.slot(i))
+            .fold(Lay::of(0, 0), |lay, (k, v)| lay.grow(k, v))
+    }
+    /// Move every entry into a table with the given layout and number
+    /// of slots.
+    fn rebuild(&mut self, lay: Lay, n: usize) {
+        let mut t = Table::with_slots(lay, n);
+        for (k, v) in (0..self.slots).filter_map(|i| self.slot(i)) {
+            let (i, _) = t.find(k);
+            t.set(i, k, v);
+        }
+        t.len = self.len;
+        *self = t;
+    }
+    fn insert(&mut self, k: u64, v: u64) -> Option<u64> {
+        let (mut i, old) = self.find(k);
+        if old.is_none() && 4*(self.len + 1) > 3*self.slots {
+            self.rebuild(self.lay.grow(k, v), 2*self.slots);
+            i = self.find(k).0;
+        } else if !self.lay.fits(k, v) {
+            self.rebuild(self.lay.grow(k, v), self.slots);
+            i = self.find(k).0;
+        }
+        if old.is_none() {
+            self.len += 1;
+        }
+        self.set(i, k, v);
+        old
+    }
+    /// Empty slot `i`, shifting back any later entries that would
+    /// otherwise no longer be found.
+    fn remove_at(&mut self, mut i: usize) {
+        let n = self.slots;
+        let mut j = i;
+        loop {
+            j = if j + 1 == n { 0 } else { j + 1 };
+            let (k, v) = match self.slot(j) {
+                None => break,
+                Some(e) => e,
+            };
+            let h = crate::hash::home64(k, n);
+            let stays = if i <= j { i < h && h <= j } else { i < h || h <= j };
+            if !stays {
+                self.set(i, k, v);
+                i = j;
+            }
+        }
+        self.clear(i);
+        self.len -= 1;
+    }
+}
+
+/// A map from `u64` keys to `u64` values.
+#[derive(Clone)]
+pub(crate) enum Map64U {
+    Empty,
+    One(u64, u64),
+    Table(Table),
+}
+
+impl Map64U {
+    pub(crate) const fn new() -> Map64U {
+        Map64U::Empty
+    }
+    pub(crate) fn len(&self) -> usize {
+        match *self {
+            Map64U::Empty => 0,
+            Map64U::One(..) => 1,
+            Map64U::Table(ref t) => t.len,
+        }
+    }
+    pub(crate) fn get(&self, k: u64) -> Option<u64> {
+        match *self {
+            Map64U::Empty => None,
+            Map64U::One(kk, v) => if kk == k { Some(v) } else { None },
+            Map64U::Table(ref t) => t.find(k).1,
+        }
+    }
+    /// Set the value of `k`, returning its old value.
+    pub(crate) fn insert(&mut self, k: u64, v: u64) -> Option<u64> {
+        match self {
+            Map64U::Empty => {
+                *self = Map64U::One(k, v);
+                None
+            }
+            Map64U::One(kk, vv) if *kk == k => Some(core::mem::replace(vv, v)),
+            Map64U::One(kk, vv) => {
+                let mut t = Table::with_slots(Lay::of(*kk, *vv).grow(k, v), 4);
+                t.insert(*kk, *vv);
+                t.insert(k, v);
+                *self = Map64U::Table(t);
+                None
+            }
+            Map64U::Table(t) => t.insert(k, v),
+        }
+    }
+    /// Remove `k`, returning its value.
+    pub(crate) fn remove(&mut self, k: u64) -> Option<u64> {
+        match self {
+            Map64U::Empty => None,
+            Map64U::One(kk, v) => {
+                if *kk != k {
+                    return None;
+                }
+                let v = *v;
+                *self = Map64U::Empty;
+                Some(v)
+            }
+            Map64U::Table(t) => {
+                let (i, old) = t.find(k);
+                if old.is_some() {
+                    t.remove_at(i);
+                    if t.len == 1 {
+                        let (k, v) = (0..t.slots).find_map(|i| t.slot(i)).unwrap();
+                        *self = Map64U::One(k, v);
+                    } else if 4*t.len < t.slots && t.slots > 4 {
+                        // Halving leaves the table under half full, so
+                        // that it takes many inserts to grow it again.
+                        t.rebuild(t.tight_lay(), t.slots/2);
+                    }
+                }
+                old
+            }
+        }
+    }
+    pub(crate) fn iter(&self) -> Iter<'_> {
+        Iter { map: self, at: 0, left: self.len() }
+    }
+}
+
+/// An iterator over the keys and values of a [`Map64U`], in no
+/// particular order.
+#[derive(Clone)]
+pub(crate) struct Iter<'a> {
+    map: &'a Map64U,
+    at: usize,
+    left: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (u64, u64);
+    fn next(&mut self) -> Option<(u64, u64)> {
+        if self.left == 0 {
+            return None;
+        }
+        self.left -= 1;
+        match self.map {
+            Map64U::Empty => None,
+            Map64U::One(k, v) => Some((*k, *v)),
+            Map64U::Table(t) => loop {
+                self.at += 1;
+                if let Some(e) = t.slot(self.at - 1) {
+                    return Some(e);
+                }
+            },
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+#[cfg(test)]
+fn check_map64u(ops: &[(bool, u64, u64)]) {
+    use alloc::collections::BTreeMap;
+    for &(kscale, vscale) in &[(1u64, 1u64), (1 << 40, 3), (7, 1 << 50), (u64::MAX / 300, u64::MAX / 300)] {
+        let mut m = Map64U::new();
+        let mut model = BTreeMap::new();
+        for &(remove, k, v) in ops {
+            let k = k * kscale;
+            if remove {
+                assert_eq!(m.remove(k), model.remove(&k));
+            } else {
+                let v = v * vscale;
+                assert_eq!(m.insert(k, v), model.insert(k, v));
+            }
+            assert_eq!(m.get(k), model.get(&k).cloned());
+            assert_eq!(m.len(), model.len());
+        }
+        let mut v: Vec<_> = m.iter().collect();
+        v.sort();
+        assert_eq!(v, model.into_iter().collect::<Vec<_>>());
+        assert_eq!(m.insert(u64::MAX, u64::MAX), None);
+        assert_eq!(m.get(u64::MAX), Some(u64::MAX));
+    }
+}
+
+#[test]
+fn test_map64u_shrink() {
+    let mut m = Map64U::new();
+    for k in 0..1000 {
+        m.insert(k << 40, k);
+    }
+    for k in 10..1000 {
+        assert_eq!(m.remove(k << 40), Some(k));
+    }
+    match m {
+        Map64U::Table(ref t) => {
+            assert!(t.slots <= 64, "{} slots for 10 entries", t.slots);
+            assert!(!t.lay.wide());
+        }
+        _ => panic!("ten entries should be in a table"),
+    }
+    assert!((0..10).all(|k| m.get(k << 40) == Some(k)));
+    for k in 1..10 {
+        m.remove(k << 40);
+    }
+    assert!(matches!(m, Map64U::One(0, 0)));
+}
+
+/// A map from a [Fits64](crate::Fits64) key to another type.
 #[derive(Clone)]
 pub struct Map64<K,V> {
     map: Map64U,
-    elems: Vec<V>,
-    phantom: std::marker::PhantomData<K>,
+    elems: Vec<(K,V)>,
 }
 
 impl<K: crate::Fits64, V> Default for Map64<K,V> {
@@ -27,7 +345,6 @@ impl<K: crate::Fits64, V> Map64<K,V> {
         Map64 {
             map: Map64U::new(),
             elems: Vec::new(),
-            phantom: std::marker::PhantomData
         }
     }
     /// How many elements
@@ -36,16 +353,17 @@ impl<K: crate::Fits64, V> Map64<K,V> {
     }
     /// Insert a value.
     pub fn insert(&mut self, k: K, v: V) -> Option<V> {
-        if let Some(i) = self.map.insert(k.to_u64(), self.elems.len()) {
-            Some(std::mem::replace(&mut self.elems[i], v))
+        if let Some(i) = self.map.get(k.to_u64()) {
+            Some(core::mem::replace(&mut self.elems[i as usize].1, v))
         } else {
-            self.elems.push(v);
+            self.map.insert(k.to_u64(), self.elems.len() as u64);
+            self.elems.push((k, v));
             None
         }
     }
     /// Lookup a value
     pub fn get(&self, k: K) -> Option<&V> {
-        self.map.get(k.to_u64()).map(|i| &self.elems[i])
+        self.map.get(k.to_u64()).map(|i| &self.elems[i as usize].1)
     }
     /// Does key exist
     pub fn contains_key(&self, k: K) -> bool {
@@ -53,8 +371,12 @@ impl<K: crate::Fits64, V> Map64<K,V> {
     }
     /// remove element
     pub fn remove(&mut self, k: K) -> Option<V> {
-        self.map.remove(k.to_u64(), self.elems.len()-1)
-            .map(|oldi| self.elems.swap_remove(oldi))
+        let i = self.map.remove(k.to_u64())? as usize;
+        let (_, v) = self.elems.swap_remove(i);
+        if let Some(&(moved, _)) = self.elems.get(i) {
+            self.map.insert(moved.to_u64(), i as u64);
+        }
+        Some(v)
     }
 }
 
@@ -65,149 +387,9 @@ fn map64() {
     assert!(x.insert(b'X', "X is awesome".to_string()).is_none());
 }
 
-#[derive(Clone)]
-struct Lay {
-    elem_bits: u8,
-    key_bits: u8,
-}
-
-impl Lay {
-    fn keymask(&self) -> u64 {
-        (1 << self.key_bits) - 1
-    }
-    fn getvalue(&self, x: u64) -> usize {
-        (x >> self.key_bits) as usize - 1
-    }
-    fn putvalue(&self, v: usize) -> u64 {
-        (v as u64 + 1) << self.key_bits
-    }
-    fn update(&self, k: u64, v: usize, len: usize) -> Option<Lay> {
-        if log_2(v as u64+1) > self.elem_bits || log_2(k) > self.key_bits || len == v {
-            Some(Lay {
-                elem_bits: std::cmp::max(log_2(v as u64+1), self.elem_bits),
-                key_bits: std::cmp::max(log_2(k), self.key_bits),
-            })
-        } else {
-            None
-        }
-    }
-}
-
-#[derive(Clone)]
-struct Map64U {
-    lay: Lay,
-    data: Box<[u64]>,
-}
-
-impl Map64U {
-    fn new() -> Self {
-        Map64U {
-            lay: Lay {
-                elem_bits: 0,
-                key_bits: 0,
-            },
-            data: Box::new([]),
-        }
-    }
-    fn get(&self, k: u64) -> Option<usize> {
-        if self.lay.key_bits + self.lay.elem_bits <= 64 {
-            for x in self.data.iter().cloned() {
-                if x != 0 && x & self.lay.keymask() == k {
-                    return Some( self.lay.getvalue(x) );
-                }
-            }
-            None
-        } else {
-            unimplemented!()
-        }
-    }
-    // It is contractual that we must *never* attempt to insert a
-    // value that is different from the total number of elements.  If
-    // the key is already present, the value of the element is *not*
-    // changed, but instead the previous value is returned.
-    fn insert(&mut self, k: u64, v: usize) -> Option<usize> {
-        if self.data.len() == v {
-            // need to allocate more space
-            let mut newdata = vec![0; (v+1)*2].into_boxed_slice();
-            for (i,x) in self.data.iter().cloned().enumerate() {
-                newdata[i] = x;
-            }
-            self.data = newdata;
-        }
-        if let Some(newl) = self.lay.update(k,v, self.data.len()) {
-            let mut newmap = Map64U {
-                lay: newl,
-                data: if self.data.len() == v {
-                    vec![0; (v+1)*2].into_boxed_slice()
-                } else {
-                    self.data.clone()
-                },
-            };
-            let mut vec = self.data.iter().cloned()
-                .filter(|x| *x != 0)
-                .map(|x| (self.lay.getvalue(x), x & self.lay.keymask()))
-                .collect::<Vec<_>>();
-            vec.sort();
-            for (v,k) in vec.into_iter() {
-                newmap.insert(k,v);
-            }
-            *self = newmap;
-        }
-        if self.lay.key_bits + self.lay.elem_bits <= 63 {
-            for x in self.data.iter().cloned() {
-                if x != 0 && x & self.lay.keymask() == k {
-                    return Some( self.lay.getvalue(x) );
-                }
-            }
-            for x in self.data.iter_mut() {
-                if *x == 0 {
-                    *x = k | self.lay.putvalue(v);
-                    return None;
-                }
-            }
-            unreachable!()
-        } else {
-            unimplemented!()
-        }
-    }
-    // remove the element with key u64, and put the value of k in
-    // whatever element has value sz, then return the old value of
-    // k.
-    fn remove(&mut self, k: u64, sz: usize) -> Option<usize> {
-        if self.lay.key_bits + self.lay.elem_bits <= 63 {
-            let mut oldval = None;
-            for x in self.data.iter_mut() {
-                if *x != 0 && *x & self.lay.keymask() == k {
-                    oldval = Some(self.lay.getvalue(*x));
-                    *x = 0;
-                    break;
-                }
-            }
-            if let Some(oldval) = oldval {
-                for x in self.data.iter_mut() {
-                    if *x != 0 && self.lay.getvalue(*x) == sz {
-                        *x = (*x & self.lay.keymask()) | self.lay.putvalue(oldval);
-                        return None;
-                    }
-                }
-            }
-            oldval
-        } else {
-            unimplemented!()
-        }
-    }
-}
-
-fn log_2(x: u64) -> u8 {
-    if x == 0 {
-        1
-    } else {
-        64 - x.leading_zeros() as u8
-    }
-}
-
-impl<K: Copy + Eq + Ord + std::fmt::Display + std::fmt::Debug + crate::Fits64,
-     V: Clone + Eq + Ord + std::fmt::Display + std::fmt::Debug> crate::anymap::AnyMap for Map64<K, V> {
+#[cfg(test)]
+impl<K: Copy + Eq + Ord + core::fmt::Display + core::fmt::Debug + crate::Fits64,
+     V: Clone + Eq + Ord + core::fmt::Display + core::fmt::Debug> crate::anymap::AnyMap for Map64<K, V> {
     type Key = K;
     type Elem = V;
     fn ins(&mut self, k: Self::Key, v: Self::Elem) -> Option<Self::Elem> {
@@ -223,27 +405,31 @@ impl<K: Copy + Eq + Ord + std::fmt::Display + std::fmt::Debug + crate::Fits64,
         self.contains_key(k)
     }
     fn vec(&self) -> Vec<(Self::Key, Self::Elem)> {
-        unimplemented!()
+        self.elems.clone()
     }
     fn ln(&self) -> usize {
         self.len()
     }
 }
 
-// #[cfg(test)]
-// use proptest::prelude::*;
-// #[cfg(test)]
-// proptest!{
-//     #[test]
-//     fn check_string_maps(slice: Vec<(u64,String)>) {
-//         crate::anymap::check_map::<Map64<u64,String>>(&slice);
-//     }
-//     #[test]
-//     fn check_u8_maps(slice: Vec<(u8,i8)>) {
-//         crate::anymap::check_map::<Map64<u8,i8>>(&slice);
-//     }
-//     #[test]
-//     fn check_i8_maps(slice: Vec<(i8,u8)>) {
-//         crate::anymap::check_map::<Map64<i8,u8>>(&slice);
-//     }
-// }
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+proptest!{
+    #[test]
+    fn check_packed_maps(ops in prop::collection::vec((prop::bool::weighted(0.3), 0u64..301, 0u64..301), 0usize..2000)) {
+        check_map64u(&ops);
+    }
+    #[test]
+    fn check_string_maps(slice: Vec<(u64,String)>) {
+        crate::anymap::check_map::<Map64<u64,String>>(&slice);
+    }
+    #[test]
+    fn check_u8_maps(slice: Vec<(u8,i8)>) {
+        crate::anymap::check_map::<Map64<u8,i8>>(&slice);
+    }
+    #[test]
+    fn check_i8_maps(slice: Vec<(i8,u8)>) {
+        crate::anymap::check_map::<Map64<i8,u8>>(&slice);
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2019 David Roundy
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Parallel iteration and collection with `rayon`.
+
+use alloc::vec::Vec;
+use rayon::iter::plumbing::{Folder, UnindexedProducer};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Iterators with fewer elements than this are not split any further.
+pub(crate) const MIN_LEN: usize = 1024;
+
+/// An iterator over a set that can be cut in two along the set's own
+/// structure: words of a bitset, slots of a table, runs or chunks.
+pub(crate) trait Split: Iterator + Sized {
+    /// Split off the second part of the elements, if there are enough
+    /// of them to be worth it.
+    fn split(self) -> (Self, Option<Self>);
+}
+
+/// Hands the pieces of a [`Split`] iterator to rayon.
+pub(crate) struct Producer<I>(pub(crate) I);
+
+impl<I: Split + Send> UnindexedProducer for Producer<I> where I::Item: Send {
+    type Item = I::Item;
+    fn split(self) -> (Self, Option<Self>) {
+        let (a, b) = self.0.split();
+        (Producer(a), b.map(Producer))
+    }
+    fn fold_with<F: Folder<I::Item>>(self, folder: F) -> F {
+        folder.consume_iter(self.0)
+    }
+}
+
+/// Collect the elements into one set per thread, each built in one go
+/// from a vec, and then merge those sets pairwise, always into the
+/// larger of the two.
+pub(crate) fn collect<T, S, I, B, L, U>(par_iter: I, build: B, len: L, union: U) -> S
+    where T: Send,
+          S: Send,
+          I: IntoParallelIterator<Item = T>,
+          B: Fn(Vec<T>) -> S + Sync + Send,
+          L: Fn(&S) -> usize + Sync + Send,
+          U: Fn(&mut S, &S) + Sync + Send,
+{
+    par_iter.into_par_iter()
+        .fold(Vec::new, |mut v, x| {
+            v.push(x);
+            v
+        })
+        .map(&build)
+        .reduce(|| build(Vec::new()), |mut a, mut b| {
+            if len(&a) < len(&b) {
+                core::mem::swap(&mut a, &mut b);
+            }
+            union(&mut a, &b);
+            a
+        })
+}
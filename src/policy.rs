@@ -0,0 +1,285 @@
+//! Tuning how sets choose between their representations.
+//!
+//! By default [`SetU32`](crate::SetU32) and [`SetU64`](crate::SetU64)
+//! pick a representation using built-in heuristics, and grow their
+//! tables by a random amount.  A [`Policy`], usually set up with
+//! [`SetU64Builder`](crate::SetU64Builder) or
+//! [`SetU32Builder`](crate::SetU32Builder), overrides those choices.
+//! The policy is stored in the set (it takes no space for the default
+//! policy), so it keeps applying as the set grows and shrinks.
+
+/// When a set may use a dense bitset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dense {
+    Never,
+    Auto,
+    Cutoff(u64),
+    Always,
+}
+
+/// Rules for choosing a set's representation and growing its tables.
+///
+/// The `Big` hash table can hold any set of values, so it is always
+/// available as a fallback and cannot be forbidden.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Policy {
+    tiny: bool,
+    heap: bool,
+    runs: bool,
+    chunks: bool,
+    dense: Dense,
+    growth: Option<f32>,
+    max_load: Option<f32>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::DEFAULT
+    }
+}
+
+impl Policy {
+    /// The policy used by sets that were not given one.
+    pub const DEFAULT: Policy = Policy {
+        tiny: true,
+        heap: true,
+        runs: true,
+        chunks: true,
+        dense: Dense::Auto,
+        growth: None,
+        max_load: None,
+    };
+
+    /// Allow or forbid storing small sets inline, without a heap
+    /// allocation.
+    pub fn tiny(mut self, allow: bool) -> Self {
+        self.tiny = allow;
+        self
+    }
+    /// Allow or forbid the `Heap` table, which packs several nearby
+    /// values into each slot.
+    pub fn heap(mut self, allow: bool) -> Self {
+        self.heap = allow;
+        self
+    }
+    /// Allow or forbid storing sets as sorted runs of consecutive
+    /// values, which the set does when that takes far less memory than
+    /// a table would.
+    pub fn runs(mut self, allow: bool) -> Self {
+        self.runs = allow;
+        self
+    }
+    /// Allow or forbid splitting a large [`SetU32`](crate::SetU32)
+    /// into chunks of 2^16 values, each stored as a sorted list or a
+    /// bitmap, which the set does when that takes less memory than a
+    /// single table would.
+    pub fn chunks(mut self, allow: bool) -> Self {
+        self.chunks = allow;
+        self
+    }
+    /// Allow or forbid dense bitsets.  Allowing them restores the
+    /// built-in heuristics for when to use them.
+    pub fn dense(mut self, allow: bool) -> Self {
+        self.dense = if allow { Dense::Auto } else { Dense::Never };
+        self
+    }
+    /// Use a dense bitset whenever at least one in `n` of the values
+    /// between the smallest and largest elements is present, and a hash
+    /// table otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn dense_cutoff(mut self, n: u64) -> Self {
+        assert!(n > 0, "the dense cutoff must be positive");
+        self.dense = Dense::Cutoff(n);
+        self
+    }
+    /// Always store elements in a dense bitset.  Beware that the
+    /// bitset needs one bit for every value between the smallest and
+    /// largest elements.
+    pub fn force_dense(mut self) -> Self {
+        self.tiny = false;
+        self.heap = false;
+        self.runs = false;
+        self.chunks = false;
+        self.dense = Dense::Always;
+        self
+    }
+    /// Always store elements in the `Big` hash table.
+    pub fn force_big(mut self) -> Self {
+        self.tiny = false;
+        self.heap = false;
+        self.runs = false;
+        self.chunks = false;
+        self.dense = Dense::Never;
+        self
+    }
+    /// Multiply a table's capacity by `factor` when it fills up,
+    /// rather than growing it by a random amount.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `factor` is greater than one.
+    pub fn growth_factor(mut self, factor: f32) -> Self {
+        assert!(factor > 1.0, "the growth factor must be greater than one");
+        self.growth = Some(factor);
+        self
+    }
+    /// Grow a table before more than `load` of its slots are in use,
+    /// rather than only once it is full.  For the `Heap` table this
+    /// means counting the slots in use whenever a new slot is needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `load` is greater than zero and at most one.
+    pub fn max_load(mut self, load: f32) -> Self {
+        assert!(load > 0.0 && load <= 1.0, "the maximum load must be in (0, 1]");
+        self.max_load = Some(load);
+        self
+    }
+
+    pub(crate) fn allows_tiny(&self) -> bool {
+        self.tiny
+    }
+    pub(crate) fn allows_heap(&self) -> bool {
+        self.heap
+    }
+    pub(crate) fn allows_runs(&self) -> bool {
+        self.runs
+    }
+    pub(crate) fn allows_chunks(&self) -> bool {
+        self.chunks
+    }
+    pub(crate) fn forces_dense(&self) -> bool {
+        self.dense == Dense::Always
+    }
+    /// Whether `len` elements spread over `span` values belong in a
+    /// dense bitset, given what the built-in heuristic (`auto`) says.
+    pub(crate) fn use_dense(&self, len: u64, span: u64, auto: bool) -> bool {
+        match self.dense {
+            Dense::Never => false,
+            Dense::Auto => auto,
+            Dense::Cutoff(n) => len > span / n,
+            Dense::Always => true,
+        }
+    }
+    /// The capacity to grow a full table of `cap` slots to, so that it
+    /// can hold `n` entries, given what the built-in rule (`auto`) says.
+    pub(crate) fn grow(&self, cap: usize, n: usize, auto: impl FnOnce() -> usize) -> usize {
+        let cap = match self.growth {
+            Some(g) => core::cmp::max(cap + 1, (cap as f32 * g) as usize),
+            None => auto(),
+        };
+        match self.capacity_for(n) {
+            Some(c) => core::cmp::max(cap, c),
+            None => cap,
+        }
+    }
+    /// The capacity for a new table that will hold `n` entries, if the
+    /// policy decides it.
+    pub(crate) fn capacity_for(&self, n: usize) -> Option<usize> {
+        self.max_load.map(|l| (n as f32 / l) as usize + 1)
+    }
+    /// Whether a table of `cap` slots, of which `used` are in use, has
+    /// room for another entry, given what the built-in rule (`auto`)
+    /// says.
+    pub(crate) fn has_room(&self, cap: usize, used: impl FnOnce() -> usize,
+                           auto: impl FnOnce() -> bool) -> bool {
+        match self.max_load {
+            Some(l) => (used() + 1) as f32 <= l * cap as f32,
+            None => auto(),
+        }
+    }
+}
+
+/// Something that supplies a [`Policy`] to a set.
+///
+/// This is implemented by [`DefaultPolicy`], which takes no space, and
+/// by [`Policy`] itself.  You can implement it for your own zero-sized
+/// type to give a fixed policy to sets without making them bigger.
+pub trait SetPolicy: Clone {
+    /// The policy to follow.
+    fn policy(&self) -> Policy;
+}
+
+/// The policy of sets that were not given one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DefaultPolicy;
+
+impl SetPolicy for DefaultPolicy {
+    #[inline]
+    fn policy(&self) -> Policy {
+        Policy::DEFAULT
+    }
+}
+
+impl SetPolicy for Policy {
+    #[inline]
+    fn policy(&self) -> Policy {
+        *self
+    }
+}
+
+/// Forward the `Policy` setters from a builder with a `policy` field.
+macro_rules! policy_setters {
+    () => {
+        /// Allow or forbid storing small sets inline.  See [`Policy::tiny`].
+        pub fn tiny(mut self, allow: bool) -> Self {
+            self.policy = self.policy.tiny(allow);
+            self
+        }
+        /// Allow or forbid the `Heap` table.  See [`Policy::heap`].
+        pub fn heap(mut self, allow: bool) -> Self {
+            self.policy = self.policy.heap(allow);
+            self
+        }
+        /// Allow or forbid sorted runs.  See [`Policy::runs`].
+        pub fn runs(mut self, allow: bool) -> Self {
+            self.policy = self.policy.runs(allow);
+            self
+        }
+        /// Allow or forbid chunks.  See [`Policy::chunks`].
+        pub fn chunks(mut self, allow: bool) -> Self {
+            self.policy = self.policy.chunks(allow);
+            self
+        }
+        /// Allow or forbid dense bitsets.  See [`Policy::dense`].
+        pub fn dense(mut self, allow: bool) -> Self {
+            self.policy = self.policy.dense(allow);
+            self
+        }
+        /// Set the density above which to use a dense bitset.  See
+        /// [`Policy::dense_cutoff`].
+        pub fn dense_cutoff(mut self, n: u64) -> Self {
+            self.policy = self.policy.dense_cutoff(n);
+            self
+        }
+        /// Always use a dense bitset.  See [`Policy::force_dense`].
+        pub fn force_dense(mut self) -> Self {
+            self.policy = self.policy.force_dense();
+            self
+        }
+        /// Always use the `Big` hash table.  See [`Policy::force_big`].
+        pub fn force_big(mut self) -> Self {
+            self.policy = self.policy.force_big();
+            self
+        }
+        /// Set how much to grow full tables by.  See
+        /// [`Policy::growth_factor`].
+        pub fn growth_factor(mut self, factor: f32) -> Self {
+            self.policy = self.policy.growth_factor(factor);
+            self
+        }
+        /// Set how full tables may get.  See [`Policy::max_load`].
+        pub fn max_load(mut self, load: f32) -> Self {
+            self.policy = self.policy.max_load(load);
+            self
+        }
+        /// Use `policy` in place of everything set so far.
+        pub fn policy(mut self, policy: Policy) -> Self {
+            self.policy = policy;
+            self
+        }
+    };
+}
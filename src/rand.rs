@@ -1,46 +1,125 @@
-#[cfg(not(feature = "rand"))]
-use std::sync::atomic::{AtomicU64, Ordering};
+//! The random choices made by the sets.
+//!
+//! Every function takes a `salt`, which callers derive from the state
+//! of the set making the choice.  It is only used by the
+//! `deterministic` feature, which makes each choice a pure function of
+//! the seed and the salt, so that the same sequence of operations
+//! always gives the same layout, memory use and iteration order,
+//! regardless of what any other set (or thread) is doing.
 
-#[cfg(not(feature = "rand"))]
-static SEED: AtomicU64 = AtomicU64::new(0);
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(feature = "deterministic"))]
+use core::sync::atomic::AtomicUsize;
 
-#[cfg(not(feature = "rand"))]
-pub fn rand32() -> u32 {
-    rand64() as u32
+#[cfg(target_has_atomic = "64")]
+static SEED: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+// Without 64-bit atomics we keep the seed in two halves.  A seed is
+// only ever set before the sets that use it are made, so the halves
+// need not change together.
+#[cfg(not(target_has_atomic = "64"))]
+static SEED: [core::sync::atomic::AtomicU32; 2] =
+    [core::sync::atomic::AtomicU32::new(0), core::sync::atomic::AtomicU32::new(0)];
+static SEEDED: AtomicBool = AtomicBool::new(false);
+#[cfg(not(feature = "deterministic"))]
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(target_has_atomic = "64")]
+fn store_seed(seed: u64) {
+    SEED.store(seed, Ordering::Relaxed);
+}
+#[cfg(not(target_has_atomic = "64"))]
+fn store_seed(seed: u64) {
+    SEED[0].store(seed as u32, Ordering::Relaxed);
+    SEED[1].store((seed >> 32) as u32, Ordering::Relaxed);
+}
+
+#[cfg(target_has_atomic = "64")]
+fn load_seed() -> u64 {
+    SEED.load(Ordering::Relaxed)
+}
+#[cfg(not(target_has_atomic = "64"))]
+fn load_seed() -> u64 {
+    SEED[0].load(Ordering::Relaxed) as u64 | (SEED[1].load(Ordering::Relaxed) as u64) << 32
 }
 
-#[cfg(feature = "rand")]
-pub fn rand32() -> u32 {
-    rand::random::<u32>()
+/// Seed the random number generator that is used to pick table sizes
+/// and sentinel values.
+///
+/// Once this has been called the sets use a generator seeded with
+/// `seed` in place of `rand::random`, so a program that performs the
+/// same operations in the same order gets the same layouts.  With the
+/// `deterministic` feature each choice depends only on the seed and
+/// the set making it, so this holds even across threads.  Without
+/// the `rand` feature the generator is always used: it is seeded from
+/// the clock if you have the `std` feature and do not call this, and
+/// with a fixed constant otherwise.  Call this before creating any
+/// sets, since changing the seed does not change the layout of
+/// existing sets.
+pub fn set_random_seed(seed: u64) {
+    store_seed(seed);
+    SEEDED.store(true, Ordering::Release);
 }
 
-#[cfg(not(feature = "rand"))]
-pub fn rand64() -> u64 {
-    use std::num::Wrapping;
+#[cfg(all(not(feature = "rand"), not(feature = "deterministic"), feature = "std"))]
+fn initial_seed() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().subsec_nanos() as u64
+}
+
+#[cfg(any(feature = "deterministic", all(not(feature = "rand"), not(feature = "std"))))]
+fn initial_seed() -> u64 {
+    0x2545_f491
+}
+
+#[cfg(not(all(feature = "rand", not(feature = "deterministic"))))]
+fn seed() -> u64 {
+    if !SEEDED.load(Ordering::Acquire) {
+        set_random_seed(initial_seed());
+    }
+    load_seed()
+}
+
+fn splitmix64(z: u64) -> u64 {
+    use core::num::Wrapping;
     // This is the SplitMix64 algorithm.  It's pretty crude,
     // but should actually be good enough in most cases.
-    let z = Wrapping(SEED.fetch_add(0x9e3779b97f4a7c15, Ordering::Relaxed));
-    if z == Wrapping(0) {
-        let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().subsec_nanos();
-        SEED.store(seed as u64, Ordering::Relaxed);
-        return rand64();
-    }
+    let z = Wrapping(z) + Wrapping(0x9e3779b97f4a7c15);
     let z = (z ^ (z >> 30)) * Wrapping(0xbf58476d1ce4e5b9);
-	let z = (z ^ (z >> 27)) * Wrapping(0x94d049bb133111eb);
-	(z ^ (z >> 31)).0
+    let z = (z ^ (z >> 27)) * Wrapping(0x94d049bb133111eb);
+    (z ^ (z >> 31)).0
+}
+
+/// The next number from the seeded generator.
+#[cfg(not(feature = "deterministic"))]
+fn counted(seed: u64) -> u64 {
+    // We only keep a usize counter so that we don't need 64-bit
+    // atomics.
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed) as u64;
+    splitmix64(seed.wrapping_add(n.wrapping_mul(0x9e3779b97f4a7c15)))
+}
+
+pub fn rand32(salt: u64) -> u32 {
+    rand64(salt) as u32
 }
 
-#[cfg(feature = "rand")]
-pub fn rand64() -> u64 {
-    rand::random::<u64>()
+#[cfg(not(any(feature = "rand", feature = "deterministic")))]
+pub fn rand64(_salt: u64) -> u64 {
+    counted(seed())
 }
 
-#[cfg(not(feature = "rand"))]
-pub fn rand_usize() -> usize {
-    rand64() as usize
+#[cfg(feature = "deterministic")]
+pub fn rand64(salt: u64) -> u64 {
+    splitmix64(seed() ^ splitmix64(salt))
 }
 
-#[cfg(feature = "rand")]
-pub fn rand_usize() -> usize {
-    rand::random::<usize>()
-}
\ No newline at end of file
+#[cfg(all(feature = "rand", not(feature = "deterministic")))]
+pub fn rand64(_salt: u64) -> u64 {
+    if SEEDED.load(Ordering::Acquire) {
+        counted(load_seed())
+    } else {
+        rand::random::<u64>()
+    }
+}
+
+pub fn rand_usize(salt: u64) -> usize {
+    rand64(salt) as usize
+}
@@ -0,0 +1,69 @@
+//! Describing how a set is stored.
+
+/// How a [`SetU32`](crate::SetU32) or [`SetU64`](crate::SetU64) is
+/// currently stored, as returned by `repr()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Repr {
+    /// No elements, and no heap allocation.
+    Empty,
+    /// A few small elements packed into the pointer itself, with no
+    /// heap allocation.
+    Tiny,
+    /// A hash table of `cap` slots, each holding a key in its high bits
+    /// and a bitset of up to `bits` elements sharing that key.
+    Heap {
+        /// The number of bits used for the bitset in each slot.
+        bits: u32,
+        /// The number of slots in the table.
+        cap: usize,
+    },
+    /// A hash table of `cap` slots, each holding one element.
+    Big {
+        /// The number of slots in the table.
+        cap: usize,
+    },
+    /// Sorted runs of consecutive elements.
+    Runs {
+        /// The number of runs.
+        runs: usize,
+        /// The number of runs there is room for.
+        cap: usize,
+    },
+    /// A plain bitset of `words` words, starting just below the
+    /// smallest element.
+    Dense {
+        /// The number of words in the bitset.
+        words: usize,
+    },
+    /// An index of chunks of 2^16 consecutive values, each holding its
+    /// elements inline, as a sorted list of their low bits, or as a
+    /// bitmap.  Only a [`SetU32`](crate::SetU32) is stored this way.
+    Chunked {
+        /// The number of chunks holding elements.
+        chunks: usize,
+    },
+}
+
+/// Where the memory used by a set goes, as returned by
+/// `memory_report()`.
+///
+/// Unlike `mem_used()`, this counts the header that precedes the table
+/// on the heap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct MemoryReport {
+    /// Bytes used by the set itself, plus the size, capacity and bits
+    /// fields stored at the start of any heap allocation.
+    pub header: usize,
+    /// Bytes of table slots (or bitset words) that hold elements.
+    pub table: usize,
+    /// Bytes of table slots (or bitset words) that are empty.
+    pub slack: usize,
+}
+
+impl MemoryReport {
+    /// The total number of bytes used.
+    pub fn total(&self) -> usize {
+        self.header + self.table + self.slack
+    }
+}
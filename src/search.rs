@@ -0,0 +1,45 @@
+// Copyright 2019 David Roundy
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Searching sorted data from a known starting point.
+
+/// The first index in `at..len` for which `below` is false, given that
+/// `below` holds for every index before that one and for none after.
+///
+/// This takes steps of doubling size from `at` before bisecting, so
+/// the search costs the log of how far it goes rather than of `len`.
+pub fn gallop(at: usize, len: usize, below: impl Fn(usize) -> bool) -> usize {
+    let mut lo = at;
+    let mut hi = at;
+    let mut step = 1;
+    while hi < len && below(hi) {
+        lo = hi + 1;
+        hi = core::cmp::min(len, hi + step);
+        step *= 2;
+    }
+    while lo < hi {
+        let mid = lo + (hi - lo)/2;
+        if below(mid) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+#[test]
+fn test_gallop() {
+    let v: Vec<u32> = (0..100).map(|x| x*3).collect();
+    for at in 0..v.len() {
+        for y in 0..310 {
+            let want = at + v[at..].iter().position(|&x| x >= y).unwrap_or(v.len() - at);
+            assert_eq!(gallop(at, v.len(), |i| v[i] < y), want);
+        }
+    }
+    assert_eq!(gallop(0, 0, |_| true), 0);
+}
@@ -7,8 +7,8 @@
 
 //! A set that is compact in size.
 
-use std;
-use std::marker::PhantomData;
+use core::marker::PhantomData;
+use alloc::vec::Vec;
 #[cfg(test)]
 use proptest::prelude::*;
 
@@ -26,7 +26,7 @@ pub trait Fits64 : Copy {
 }
 /// A utility function that is useful for testing your Fits64
 /// implentation.
-pub fn test_fits64<T: Fits64+Eq+std::fmt::Debug>(x: T) {
+pub fn test_fits64<T: Fits64+Eq+core::fmt::Debug>(x: T) {
     let x64 = x.to_u64();
     let y = unsafe { T::from_u64(x64) };
     let y64 = y.to_u64();
@@ -60,7 +60,7 @@ define_fits!(usize, fits_usize);
 impl Fits64 for char {
     #[inline]
     unsafe fn from_u64(x: u64) -> Self {
-        std::char::from_u32(x as u32).unwrap()
+        core::char::from_u32(x as u32).unwrap()
     }
     #[inline]
     fn to_u64(self) -> u64 { self as u64 }
@@ -161,9 +161,29 @@ define_ifits!(isize, usize, fits_isize);
 /// Intermediate sets have intermediate storage.  The worst case
 /// scenario is large integers widely spaced apart, in which case the
 /// storage is similar to a [`std::collections::HashSet`].
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Set64<T: Fits64>(crate::setu64::SetU64, PhantomData<T>);
 
+impl<T: Fits64 + core::fmt::Debug> core::fmt::Debug for Set64<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Prints the elements in increasing order, as `{1, 2, 3}`.
+///
+/// ```
+/// use tinyset::Set64;
+///
+/// let a = Set64::from([3i8, -1, 2]);
+/// assert_eq!(format!("{}", a), "{-1, 2, 3}");
+/// ```
+impl<T: Fits64 + Ord + core::fmt::Display> core::fmt::Display for Set64<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        crate::sets::fmt_sorted(f, self.sorted().into_iter())
+    }
+}
+
 impl<T: Fits64> Default for Set64<T> {
     /// Creates an empty set..
     fn default() -> Self {
@@ -193,7 +213,7 @@ impl<T: Fits64> Set64<T> {
         self.0.len()
     }
     /// Returns true if the set contains a value.
-    pub fn contains<R: std::borrow::Borrow<T>>(&self, value: R) -> bool {
+    pub fn contains<R: core::borrow::Borrow<T>>(&self, value: R) -> bool {
         let x = value.borrow().clone().to_u64();
         self.0.contains(x)
     }
@@ -203,15 +223,76 @@ impl<T: Fits64> Set64<T> {
         self.0.remove(x)
     }
     /// Iterate
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item=T> + 'a {
-        self.0.iter().map(|x| unsafe { T::from_u64(x) })
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        Iter(self.0.iter(), PhantomData)
     }
     /// Drain
-    pub fn drain<'a>(&'a mut self) -> impl Iterator<Item=T> + 'a {
-        self.0.drain().map(|x| unsafe { T::from_u64(x) })
+    pub fn drain<'a>(&'a mut self) -> Drain<'a, T> {
+        Drain(self.0.drain(), PhantomData)
+    }
+    /// Returns the underlying [`SetU64`](crate::SetU64), which holds
+    /// each element as its [`Fits64::to_u64`].
+    pub fn into_inner(self) -> crate::setu64::SetU64 {
+        self.0
+    }
+    /// Wraps a [`SetU64`](crate::SetU64) of encoded elements.
+    ///
+    /// # Safety
+    ///
+    /// Every element of `s` must be the [`Fits64::to_u64`] of some
+    /// `T`, as it is for a set that came from [`Set64::into_inner`].
+    pub unsafe fn from_inner(s: crate::setu64::SetU64) -> Self {
+        Set64(s, PhantomData)
+    }
+    /// The elements in their own order, which for signed integers is
+    /// not that of their encodings.
+    fn sorted(&self) -> Vec<T> where T: Ord {
+        let mut v: Vec<T> = self.iter().collect();
+        v.sort_unstable();
+        v
+    }
+}
+
+/// Ranges of elements, for types whose values are stored as
+/// consecutive `u64`s (which is not so for signed integers).
+impl<T: Fits64 + Into<u64>> Set64<T> {
+    /// Adds every value in `range` to the set.
+    ///
+    /// ```
+    /// use tinyset::Set64;
+    ///
+    /// let mut a: Set64<u32> = Set64::new();
+    /// a.insert_range(10..20);
+    /// a.remove_range(15..);
+    /// assert!(a.contains_range(10..15));
+    /// assert!(!a.contains_range(10..=15));
+    /// assert_eq!(a.len(), 5);
+    /// ```
+    pub fn insert_range(&mut self, range: impl core::ops::RangeBounds<T>) {
+        self.0.insert_range(u64_bounds(range))
+    }
+    /// Removes every value in `range` from the set.
+    pub fn remove_range(&mut self, range: impl core::ops::RangeBounds<T>) {
+        self.0.remove_range(u64_bounds(range))
+    }
+    /// Returns true if the set contains every value in `range`.
+    pub fn contains_range(&self, range: impl core::ops::RangeBounds<T>) -> bool {
+        self.0.contains_range(u64_bounds(range))
     }
 }
 
+/// The bounds of `range` as bounds on the stored `u64`s.
+fn u64_bounds<T: Fits64>(range: impl core::ops::RangeBounds<T>)
+                         -> (core::ops::Bound<u64>, core::ops::Bound<u64>) {
+    use core::ops::Bound;
+    let convert = |b: Bound<&T>| match b {
+        Bound::Included(&x) => Bound::Included(x.to_u64()),
+        Bound::Excluded(&x) => Bound::Excluded(x.to_u64()),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (convert(range.start_bound()), convert(range.end_bound()))
+}
+
 impl<T: Fits64> PartialEq for Set64<T> {
     fn eq(&self, other: &Set64<T>) -> bool {
         if self.len() != other.len() {
@@ -227,17 +308,26 @@ impl<T: Fits64> PartialEq for Set64<T> {
 }
 impl<T: Fits64> Eq for Set64<T> {}
 
-impl<T: Fits64> std::hash::Hash for Set64<T> {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        let mut membs: Vec<u64> = self.iter().map(|i| i.to_u64()).collect();
-        membs.sort();
-        for memb in membs {
-            memb.hash(state);
-        }
+/// Sets are ordered by comparing their elements in increasing order,
+/// as a `BTreeSet` is.
+impl<T: Fits64 + Ord> PartialOrd for Set64<T> {
+    fn partial_cmp(&self, other: &Set64<T>) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<T: Fits64 + Ord> Ord for Set64<T> {
+    fn cmp(&self, other: &Set64<T>) -> core::cmp::Ordering {
+        self.sorted().cmp(&other.sorted())
+    }
+}
+
+impl<T: Fits64> core::hash::Hash for Set64<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
     }
 }
 
-impl<T: Fits64> std::iter::FromIterator<T> for Set64<T> {
+impl<T: Fits64> core::iter::FromIterator<T> for Set64<T> {
     fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> Self {
         let iter = iter.into_iter();
         let (sz,_) = iter.size_hint();
@@ -271,6 +361,93 @@ impl<T: Fits64> Iterator for IntoIter<T> {
         self.0.last().map(|x| unsafe { T::from_u64(x) })
     }
 }
+impl<T: Fits64> DoubleEndedIterator for IntoIter<T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        self.0.next_back().map(|x| unsafe { T::from_u64(x) })
+    }
+}
+impl<T: Fits64> ExactSizeIterator for IntoIter<T> {}
+impl<T: Fits64> core::iter::FusedIterator for IntoIter<T> {}
+
+/// An iterator over the elements of a [`Set64`].
+#[derive(Debug, Clone)]
+pub struct Iter<'a, T: Fits64>( crate::setu64::Iter<'a>, PhantomData<T> );
+
+impl<'a, T: Fits64> Iterator for Iter<'a, T> {
+    type Item = T;
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.0.next().map(|x| unsafe { T::from_u64(x) })
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+    #[inline]
+    fn count(self) -> usize {
+        self.0.count()
+    }
+    #[inline]
+    fn last(self) -> Option<T> {
+        self.0.last().map(|x| unsafe { T::from_u64(x) })
+    }
+}
+impl<'a, T: Fits64> DoubleEndedIterator for Iter<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        self.0.next_back().map(|x| unsafe { T::from_u64(x) })
+    }
+}
+impl<'a, T: Fits64> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T: Fits64> core::iter::FusedIterator for Iter<'a, T> {}
+
+/// A draining iterator over the elements of a [`Set64`].
+pub struct Drain<'a, T: Fits64>( crate::setu64::Drain<'a>, PhantomData<T> );
+
+impl<'a, T: Fits64> Iterator for Drain<'a, T> {
+    type Item = T;
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.0.next().map(|x| unsafe { T::from_u64(x) })
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+impl<'a, T: Fits64> DoubleEndedIterator for Drain<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        self.0.next_back().map(|x| unsafe { T::from_u64(x) })
+    }
+}
+impl<'a, T: Fits64> ExactSizeIterator for Drain<'a, T> {}
+impl<'a, T: Fits64> core::iter::FusedIterator for Drain<'a, T> {}
+
+/// A parallel iterator over the elements of a [`Set64`].
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone)]
+pub struct ParIter<'a, T: Fits64>( crate::setu64::ParIter<'a>, PhantomData<T> );
+
+#[cfg(feature = "rayon")]
+impl<'a, T: Fits64 + Send> rayon::iter::ParallelIterator for ParIter<'a, T> {
+    type Item = T;
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+        where C: rayon::iter::plumbing::UnindexedConsumer<T>
+    {
+        self.0.map(|x| unsafe { T::from_u64(x) }).drive_unindexed(consumer)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, T: Fits64 + Send> rayon::iter::IntoParallelIterator for &'a Set64<T> {
+    type Item = T;
+    type Iter = ParIter<'a, T>;
+    fn into_par_iter(self) -> ParIter<'a, T> {
+        ParIter(rayon::iter::IntoParallelIterator::into_par_iter(&self.0), PhantomData)
+    }
+}
 
 impl<T: Fits64> IntoIterator for Set64<T> {
     type Item = T;
@@ -281,7 +458,41 @@ impl<T: Fits64> IntoIterator for Set64<T> {
     }
 }
 
-impl<'a, 'b, T: Fits64> std::ops::Sub<&'b Set64<T>> for &'a Set64<T> {
+impl<'a, T: Fits64> IntoIterator for &'a Set64<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T: Fits64> From<Vec<T>> for Set64<T> {
+    fn from(v: Vec<T>) -> Self {
+        Set64(v.into_iter().map(|x| x.to_u64()).collect(), PhantomData)
+    }
+}
+
+impl<T: Fits64, const N: usize> From<[T; N]> for Set64<T> {
+    fn from(a: [T; N]) -> Self {
+        Set64(a.iter().map(|x| x.to_u64()).collect(), PhantomData)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Fits64, S> From<std::collections::HashSet<T, S>> for Set64<T> {
+    fn from(s: std::collections::HashSet<T, S>) -> Self {
+        Set64(s.into_iter().map(|x| x.to_u64()).collect(), PhantomData)
+    }
+}
+
+impl<T: Fits64 + Ord> From<Set64<T>> for alloc::collections::BTreeSet<T> {
+    fn from(s: Set64<T>) -> Self {
+        s.into_iter().collect()
+    }
+}
+
+impl<'a, 'b, T: Fits64> core::ops::Sub<&'b Set64<T>> for &'a Set64<T> {
     type Output = Set64<T>;
 
     /// Returns the difference of `self` and `rhs` as a new `Set64<T>`.
@@ -342,7 +553,33 @@ impl<T: Fits64> Extend<T> for Set64<T> {
     }
 }
 
-impl<'a, 'b, T: Fits64> std::ops::BitOr<&'b Set64<T>> for &'a Set64<T> {
+impl<'a, T: Fits64> Extend<&'a T> for Set64<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().cloned())
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Fits64 + Send> rayon::iter::FromParallelIterator<T> for Set64<T> {
+    fn from_par_iter<I>(par_iter: I) -> Self
+        where I: rayon::iter::IntoParallelIterator<Item = T>
+    {
+        use rayon::iter::ParallelIterator;
+        Set64(par_iter.into_par_iter().map(|x| x.to_u64()).collect(), PhantomData)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Fits64 + Send> rayon::iter::ParallelExtend<T> for Set64<T> {
+    fn par_extend<I>(&mut self, par_iter: I)
+        where I: rayon::iter::IntoParallelIterator<Item = T>
+    {
+        use rayon::iter::ParallelIterator;
+        self.0.par_extend(par_iter.into_par_iter().map(|x| x.to_u64()))
+    }
+}
+
+impl<'a, 'b, T: Fits64> core::ops::BitOr<&'b Set64<T>> for &'a Set64<T> {
     type Output = Set64<T>;
 
     /// Returns the union of `self` and `rhs` as a new `Set64<T>`.
@@ -377,7 +614,47 @@ impl<'a, 'b, T: Fits64> std::ops::BitOr<&'b Set64<T>> for &'a Set64<T> {
     }
 }
 
-impl<T: Fits64 + Eq + Ord + std::fmt::Debug + std::fmt::Display> crate::copyset::CopySet for Set64<T> {
+impl<T: Fits64> core::ops::BitAnd<&Set64<T>> for &Set64<T> {
+    type Output = Set64<T>;
+
+    /// Returns the intersection of `self` and `rhs` as a new `Set64<T>`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinyset::Set64;
+    ///
+    /// let a = Set64::from([1u32, 2, 3]);
+    /// let b = Set64::from([3u32, 4, 5]);
+    /// assert_eq!(&a & &b, Set64::from([3]));
+    /// ```
+    fn bitand(self, rhs: &Set64<T>) -> Set64<T> {
+        Set64(&self.0 & &rhs.0, PhantomData)
+    }
+}
+
+impl<T: Fits64> core::ops::BitXor<&Set64<T>> for &Set64<T> {
+    type Output = Set64<T>;
+
+    /// Returns the elements in exactly one of `self` and `rhs` as a
+    /// new `Set64<T>`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinyset::Set64;
+    ///
+    /// let a = Set64::from([1u32, 2, 3]);
+    /// let b = Set64::from([3u32, 4, 5]);
+    /// assert_eq!(&a ^ &b, Set64::from([1, 2, 4, 5]));
+    /// ```
+    fn bitxor(self, rhs: &Set64<T>) -> Set64<T> {
+        Set64(&self.0 ^ &rhs.0, PhantomData)
+    }
+}
+
+#[cfg(test)]
+impl<T: Fits64 + Eq + Ord + core::fmt::Debug + core::fmt::Display> crate::copyset::CopySet for Set64<T> {
     type Item = T;
     type Iter = IntoIter<T>;
     fn ins(&mut self, e: Self::Item) -> bool {
@@ -400,6 +677,26 @@ impl<T: Fits64 + Eq + Ord + std::fmt::Debug + std::fmt::Display> crate::copyset:
     }
 }
 
+#[test]
+fn test_std_traits() {
+    use alloc::collections::BTreeSet;
+    let a = Set64::from([5i32, -3, 0, -100]);
+    let b: Set64<i32> = vec![-3, 7].into();
+    assert_eq!(a.to_string(), "{-100, -3, 0, 5}");
+    assert_eq!(format!("{:?}", Set64::from(['x'])), "{'x'}");
+    let (ma, mb) = (BTreeSet::from(a.clone()), BTreeSet::from(b.clone()));
+    assert_eq!(a.cmp(&b), ma.cmp(&mb));
+    assert_eq!(BTreeSet::from(&a & &b), &ma & &mb);
+    assert_eq!(BTreeSet::from(&a ^ &b), &ma ^ &mb);
+    let mut c = Set64::new();
+    c.extend(&a);
+    assert_eq!(c, a);
+    let inner = a.clone().into_inner();
+    assert_eq!(inner.len(), 4);
+    assert_eq!(unsafe { Set64::<i32>::from_inner(inner) }, a);
+    assert_eq!((&a).into_iter().count(), 4);
+}
+
 #[cfg(test)]
 proptest!{
     #[test]
@@ -20,4 +20,42 @@ impl crate::SetUsize {
 impl<T: crate::Fits64> crate::Set64<T> {
     generic_set!();
 }
+impl crate::BoundedSet {
+    generic_set!();
+}
+
+
+/// Write the elements (which should come in increasing order) as
+/// `{1, 2, 3}`, for `Display`.
+pub(crate) fn fmt_sorted<T: core::fmt::Display>(f: &mut core::fmt::Formatter,
+                                                elems: impl Iterator<Item = T>)
+                                                -> core::fmt::Result {
+    f.write_str("{")?;
+    for (i, x) in elems.enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", x)?;
+    }
+    f.write_str("}")
+}
 
+/// Hash the elements of a set, which may come in any order, so that
+/// equal sets hash alike whatever their representation without our
+/// having to sort them.  Each element is mixed on its own and the
+/// results summed, followed by the number of elements.
+pub(crate) fn hash_unordered<H: core::hash::Hasher>(state: &mut H,
+                                                    elems: impl Iterator<Item = u64>) {
+    let mut sum: u64 = 0;
+    let mut n: usize = 0;
+    for x in elems {
+        // The SplitMix64 finalizer.
+        let z = x.wrapping_add(0x9e3779b97f4a7c15);
+        let z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        sum = sum.wrapping_add(z ^ (z >> 31));
+        n += 1;
+    }
+    state.write_u64(sum);
+    state.write_usize(n);
+}
@@ -2,8 +2,15 @@
 //! This is a crate for the tiniest sets ever.
 
 use itertools::Itertools;
+use alloc::vec::Vec;
+use allocator_api2::alloc::{Allocator, Global};
+use crate::error::TryReserveError;
+use crate::hash::home32;
+use crate::repr::{MemoryReport, Repr};
+use crate::policy::{DefaultPolicy, Policy, SetPolicy};
+use crate::chunks::{self, ChunksCursor, ChunksIter, ENTRY};
 
-const fn num_bits<T>() -> u32 { std::mem::size_of::<T>() as u32 * 8 }
+const fn num_bits<T>() -> u32 { core::mem::size_of::<T>() as u32 * 8 }
 
 fn log_2(x: u32) -> u32 {
     if x == 0 {
@@ -34,6 +41,18 @@ fn compute_array_bits(mx: u32) -> u32 {
     bits
 }
 
+/// The `bits` of a set stored as runs.  `compute_array_bits` never
+/// gives a `Heap` table this many bits.
+const RUNS: u32 = 31;
+
+/// The `bits` of a set split into chunks.  Big tables pick a sentinel
+/// above this.
+const CHUNKED: u32 = 33;
+
+/// The fewest elements for which a set is split into chunks.  Sets
+/// shrinking below half this are stored whole again.
+const CHUNKED_MIN: usize = 1 << 16;
+
 fn split_u32(x: u32, bits: u32) -> (u32, u32) {
     if bits > 0 {
         (x / bits, (x % bits))
@@ -51,13 +70,21 @@ fn unsplit_u32(k: u32, offset: u32, bits: u32) -> u32 {
 }
 
 /// A set of u32
-pub struct SetU32(*mut S);
+///
+/// Any heap storage is obtained from the allocator `A`, which
+/// defaults to the global allocator.  Use [`SetU32::new_in`] to keep
+/// a set's storage somewhere else, e.g. in an arena.
+///
+/// The policy `P` decides which representation the set uses as it
+/// grows and shrinks.  Use [`SetU32Builder`] to make a set with a
+/// policy of your own.
+pub struct SetU32<A: Allocator = Global, P: SetPolicy = DefaultPolicy>(*mut S, A, P);
 
-unsafe impl Send for SetU32 {}
-unsafe impl Sync for SetU32 {}
+unsafe impl<A: Allocator + Send, P: SetPolicy + Send> Send for SetU32<A, P> {}
+unsafe impl<A: Allocator + Sync, P: SetPolicy + Sync> Sync for SetU32<A, P> {}
 
-impl std::fmt::Debug for SetU32 {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+impl<A: Allocator + Clone, P: SetPolicy> core::fmt::Debug for SetU32<A, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
         write!(f, "SetU32 {:?}", self.iter().collect::<Vec<_>>())?;
         Ok(())
     }
@@ -76,6 +103,7 @@ struct S {
 struct Tiny {
     sz: u8,
     sz_spent: u8,
+    sz_back: u8,
     bits: usize,
     last: usize,
 }
@@ -88,7 +116,7 @@ impl Iterator for Tiny {
     type Item = u32;
     fn next(&mut self) -> Option<u32> {
         let bitsplits = BITSPLITS[self.sz as usize];
-        if self.sz_spent < self.sz {
+        if self.sz_spent + self.sz_back < self.sz {
             let nbits = bitsplits[self.sz_spent as usize];
             let difference = self.bits & mask(nbits as usize) as usize;
             if self.sz_spent == 0 {
@@ -104,16 +132,32 @@ impl Iterator for Tiny {
         }
     }
     fn count(self) -> usize {
-        self.sz as usize
+        (self.sz - self.sz_spent - self.sz_back) as usize
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.sz as usize, Some(self.sz as usize))
+        let n = (self.sz - self.sz_spent - self.sz_back) as usize;
+        (n, Some(n))
     }
     fn min(mut self) -> Option<u32> {
         self.next()
     }
 }
 
+impl DoubleEndedIterator for Tiny {
+    fn next_back(&mut self) -> Option<u32> {
+        let left = self.sz - self.sz_spent - self.sz_back;
+        if left == 0 {
+            return None;
+        }
+        // The elements are stored as differences, so we decode from
+        // the front up to the last one left.
+        self.sz_back += 1;
+        let mut t = *self;
+        t.sz_back = 0;
+        t.nth(left as usize - 1)
+    }
+}
+
 #[cfg(target_pointer_width = "64")]
 static BITSPLITS: [&[u32]; 7] = [
     &[],
@@ -152,6 +196,7 @@ impl Tiny {
             sz,
             bits: x >> 3,
             sz_spent: 0,
+            sz_back: 0,
             last: 0,
         }
     }
@@ -163,24 +208,29 @@ impl Tiny {
                 sz: 1,
                 bits: x as usize,
                 sz_spent: 0,
+                sz_back: 0,
                 last: 0,
             })
         }
     }
+    #[cfg(test)]
     fn new(mut v: Vec<u32>) -> Option<Self> {
+        v.sort();
+        v.dedup();
+        Tiny::new_sorted_deduped(&v)
+    }
+    fn new_sorted_deduped(v: &[u32]) -> Option<Self> {
         if v.len() == 0 {
             return None;
         } else if v.len() > BITSPLITS.len() - 1 {
             return None;
         }
         let sz = v.len() as u8;
-        v.sort();
-        v.dedup();
         let mut last = 0;
         let mut offset = 0;
         let mut bits: usize = 0;
         let bitsplits = BITSPLITS[sz as usize];
-        for (x,nbits) in v.into_iter().zip(bitsplits.iter().cloned()) {
+        for (x,nbits) in v.iter().cloned().zip(bitsplits.iter().cloned()) {
             let y = if offset == 0 {
                 x
             } else {
@@ -193,7 +243,7 @@ impl Tiny {
             offset += nbits;
             last = x;
         }
-        Some (Tiny { sz, bits, sz_spent: 0, last: 0 })
+        Some (Tiny { sz, bits, sz_spent: 0, sz_back: 0, last: 0 })
     }
     fn insert(mut self, e: u32) -> Option<Self> {
         let mut e = e as usize;
@@ -204,6 +254,7 @@ impl Tiny {
                 sz: self.sz + 1,
                 last: 0,
                 sz_spent: 0,
+                sz_back: 0,
             };
             let backup = self.clone();
             let mut offset = 0;
@@ -273,7 +324,7 @@ impl Tiny {
         }
     }
     fn contains(mut self, e: u32) -> bool {
-        if e > std::usize::MAX as u32 {
+        if e > core::usize::MAX as u32 {
             return false;
         }
         let mut e = e as usize;
@@ -309,6 +360,7 @@ fn test_tiny() {
     test_vec(vec![1,2,3,4,5,6]);
 }
 
+#[derive(Clone, Copy)]
 enum Internal<'a> {
     Empty,
     Stack(Tiny),
@@ -320,8 +372,17 @@ enum Internal<'a> {
         s: &'a S,
         a: &'a [u32],
     },
+    Runs {
+        s: &'a S,
+        a: &'a [u32],
+    },
     Dense {
         sz: u32,
+        base: u32,
+        a: &'a [u32],
+    },
+    Chunked {
+        s: &'a S,
         a: &'a [u32],
     },
 }
@@ -336,90 +397,242 @@ enum InternalMut<'a> {
         s: &'a mut S,
         a: &'a mut [u32],
     },
+    Runs {
+        s: &'a mut S,
+        a: &'a mut [u32],
+    },
     Dense {
         sz: &'a mut u32,
+        base: u32,
+        a: &'a mut [u32],
+    },
+    Chunked {
+        s: &'a mut S,
         a: &'a mut [u32],
     },
 }
 
-#[derive(Debug)]
-enum Iter<'a> {
+impl<'a> Internal<'a> {
+    fn iter(self) -> InternalIter<'a> {
+        match self {
+            Internal::Empty => InternalIter::Empty,
+            Internal::Stack(t) => InternalIter::Stack( t ),
+            Internal::Heap { s, a } => {
+                InternalIter::Heap(HeapIter {
+                    sz_left: s.sz as usize,
+                    bits: s.bits,
+                    whichbit: 0,
+                    backbit: s.bits,
+                    array: a,
+                })
+            }
+            Internal::Big { s, a } => {
+                InternalIter::Big(BigIter { sz_left: s.sz as usize, bits: s.bits, a })
+            }
+            Internal::Runs { s, a } => {
+                let runs = runs_of(a);
+                InternalIter::Runs(RunsIter { sz_left: s.sz as usize, next: runs.first().cloned().unwrap_or(0), back: runs.last().cloned().unwrap_or(0), runs })
+            }
+            Internal::Dense { sz, base, a } => {
+                InternalIter::Dense(DenseIter {
+                    sz_left: sz as usize,
+                    base,
+                    whichword: 0,
+                    whichbit: 0,
+                    endword: a.len(),
+                    endmask: !0,
+                    a
+                })
+            }
+            Internal::Chunked { s, a } => InternalIter::Chunked(ChunksIter::new(s.sz as usize, chunk_index(a))),
+        }
+    }
+    fn contains(self, e: u32) -> bool {
+        match self {
+            Internal::Empty => false,
+            Internal::Stack(t) => t.contains(e),
+            Internal::Dense { base, a, .. } => dense_contains(base, a, e),
+            Internal::Heap { s, a } => heap_contains(s, a, e),
+            Internal::Big { s, a } => big_contains(s, a, e),
+            Internal::Runs { a, .. } => runs_contains(a, e),
+            Internal::Chunked { a, .. } => chunked_contains(a, e),
+        }
+    }
+    /// The number of runs of consecutive elements, counting no further
+    /// than `limit`.
+    fn count_runs(self, limit: usize) -> usize {
+        match self {
+            Internal::Runs { a, .. } => core::cmp::min(a[0] as usize, limit),
+            Internal::Dense { a, .. } => {
+                // A run starts at each bit that is set but follows a
+                // clear one.
+                let mut n = 0;
+                let mut carry = 0;
+                for &w in a {
+                    n += (w & !(w << 1 | carry)).count_ones() as usize;
+                    carry = w >> 31;
+                    if n >= limit {
+                        return limit;
+                    }
+                }
+                n
+            }
+            _ => self.iter().filter(|&x| x == 0 || !self.contains(x - 1)).take(limit).count(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum InternalIter<'a> {
     Empty,
     Stack(Tiny),
     Heap(HeapIter<'a>),
     Big(BigIter<'a>),
+    Runs(RunsIter<'a>),
     Dense(DenseIter<'a>),
+    Chunked(ChunksIter<'a>),
 }
 
-impl<'a> Iterator for Iter<'a> {
+impl<'a> Iterator for InternalIter<'a> {
     type Item = u32;
     #[inline]
     fn next(&mut self) -> Option<u32> {
         match self {
-            Iter::Empty => None,
-            Iter::Stack(t) => t.next(),
-            Iter::Dense(it) => it.next(),
-            Iter::Big(it) => it.next(),
-            Iter::Heap(it) => it.next(),
+            InternalIter::Empty => None,
+            InternalIter::Stack(t) => t.next(),
+            InternalIter::Dense(it) => it.next(),
+            InternalIter::Big(it) => it.next(),
+            InternalIter::Heap(it) => it.next(),
+            InternalIter::Runs(it) => it.next(),
+            InternalIter::Chunked(it) => it.next(),
         }
     }
     #[inline]
     fn count(self) -> usize {
         match self {
-            Iter::Empty => 0,
-            Iter::Stack(t) => t.count(),
-            Iter::Dense(it) => it.count(),
-            Iter::Big(it) => it.count(),
-            Iter::Heap(it) => it.count(),
+            InternalIter::Empty => 0,
+            InternalIter::Stack(t) => t.count(),
+            InternalIter::Dense(it) => it.count(),
+            InternalIter::Big(it) => it.count(),
+            InternalIter::Heap(it) => it.count(),
+            InternalIter::Runs(it) => it.count(),
+            InternalIter::Chunked(it) => it.count(),
         }
     }
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
         match self {
-            Iter::Empty => (0, Some(0)),
-            Iter::Stack(t) => t.size_hint(),
-            Iter::Dense(it) => it.size_hint(),
-            Iter::Big(it) => it.size_hint(),
-            Iter::Heap(it) => it.size_hint(),
+            InternalIter::Empty => (0, Some(0)),
+            InternalIter::Stack(t) => t.size_hint(),
+            InternalIter::Dense(it) => it.size_hint(),
+            InternalIter::Big(it) => it.size_hint(),
+            InternalIter::Heap(it) => it.size_hint(),
+            InternalIter::Runs(it) => it.size_hint(),
+            InternalIter::Chunked(it) => it.size_hint(),
         }
     }
     #[inline]
     fn min(self) -> Option<u32> {
         match self {
-            Iter::Empty => None,
-            Iter::Stack(t) => t.min(),
-            Iter::Dense(it) => it.min(),
-            Iter::Big(it) => it.min(),
-            Iter::Heap(it) => it.min(),
+            InternalIter::Empty => None,
+            InternalIter::Stack(t) => t.min(),
+            InternalIter::Dense(it) => it.min(),
+            InternalIter::Big(it) => it.min(),
+            InternalIter::Heap(it) => it.min(),
+            InternalIter::Runs(it) => it.min(),
+            InternalIter::Chunked(it) => it.min(),
         }
     }
     #[inline]
     fn max(self) -> Option<u32> {
         match self {
-            Iter::Empty => None,
-            Iter::Stack(t) => t.max(),
-            Iter::Dense(it) => it.max(),
-            Iter::Big(it) => it.max(),
-            Iter::Heap(it) => it.max(),
+            InternalIter::Empty => None,
+            InternalIter::Stack(t) => t.max(),
+            InternalIter::Dense(it) => it.max(),
+            InternalIter::Big(it) => it.max(),
+            InternalIter::Heap(it) => it.max(),
+            InternalIter::Runs(it) => it.max(),
+            InternalIter::Chunked(it) => it.max(),
         }
     }
     #[inline]
     fn last(self) -> Option<u32> {
         match self {
-            Iter::Empty => None,
-            Iter::Stack(t) => t.last(),
-            Iter::Dense(it) => it.last(),
-            Iter::Big(it) => it.last(),
-            Iter::Heap(it) => it.last(),
+            InternalIter::Empty => None,
+            InternalIter::Stack(mut t) => t.next_back(),
+            InternalIter::Dense(it) => it.last(),
+            InternalIter::Big(it) => it.last(),
+            InternalIter::Heap(it) => it.last(),
+            InternalIter::Runs(it) => it.last(),
+            InternalIter::Chunked(it) => it.last(),
         }
     }
 }
 
-#[derive(Debug)]
+impl<'a> DoubleEndedIterator for InternalIter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<u32> {
+        match self {
+            InternalIter::Empty => None,
+            InternalIter::Stack(t) => t.next_back(),
+            InternalIter::Heap(it) => it.next_back(),
+            InternalIter::Big(it) => it.next_back(),
+            InternalIter::Runs(it) => it.next_back(),
+            InternalIter::Dense(it) => it.next_back(),
+            InternalIter::Chunked(it) => it.next_back(),
+        }
+    }
+}
+
+/// An iterator over the elements of a [`SetU32`].
+///
+/// Sets stored as hash tables give their elements in no particular
+/// order, and the others give them in increasing order.
+#[derive(Debug, Clone)]
+pub struct Iter<'a>(InternalIter<'a>);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u32;
+    #[inline]
+    fn next(&mut self) -> Option<u32> {
+        self.0.next()
+    }
+    #[inline]
+    fn last(self) -> Option<u32> {
+        self.0.last()
+    }
+    #[inline]
+    fn min(self) -> Option<u32> {
+        self.0.min()
+    }
+    #[inline]
+    fn max(self) -> Option<u32> {
+        self.0.max()
+    }
+    #[inline]
+    fn count(self) -> usize {
+        self.0.count()
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<u32> {
+        self.0.next_back()
+    }
+}
+impl<'a> ExactSizeIterator for Iter<'a> {}
+impl<'a> core::iter::FusedIterator for Iter<'a> {}
+
+#[derive(Debug, Clone)]
 struct HeapIter<'a> {
     sz_left: usize,
     bits: u32,
     whichbit: u32,
+    backbit: u32,
     array: &'a [u32],
 }
 
@@ -427,6 +640,9 @@ impl<'a> Iterator for HeapIter<'a> {
     type Item = u32;
     #[inline]
     fn next(&mut self) -> Option<u32> {
+        if self.sz_left == 0 {
+            return None;
+        }
         if self.bits > 0 {
             while let Some(&x) = self.array.first() {
                 while self.whichbit < self.bits {
@@ -450,12 +666,8 @@ impl<'a> Iterator for HeapIter<'a> {
         None
     }
     #[inline]
-    fn last(self) -> Option<u32> {
-        self.array.into_iter().rev().cloned()
-            .filter(|&x| x != 0)
-            .map(|x| x >> self.bits
-                 + (x & mask(self.bits as usize)).leading_zeros() - 31)
-            .next()
+    fn last(mut self) -> Option<u32> {
+        self.next_back()
     }
     #[inline]
     fn count(self) -> usize {
@@ -469,7 +681,7 @@ impl<'a> Iterator for HeapIter<'a> {
     fn min(mut self) -> Option<u32> {
         if self.sz_left == 0 {
             None
-        } else if self.whichbit == 0 {
+        } else if self.whichbit == 0 && self.backbit == self.bits {
             let x = self.array.into_iter().cloned()
                 .filter(|x| *x != 0).min().unwrap();
             Some((x >> self.bits)*self.bits + x.trailing_zeros() as u32)
@@ -487,7 +699,7 @@ impl<'a> Iterator for HeapIter<'a> {
     fn max(mut self) -> Option<u32> {
         if self.sz_left == 0 {
             None
-        } else if self.whichbit == 0 {
+        } else if self.whichbit == 0 && self.backbit == self.bits {
             let x = self.array.into_iter().cloned()
                 .filter(|x| *x != 0).max().unwrap();
             let reference = (x >> self.bits)*self.bits;
@@ -506,7 +718,34 @@ impl<'a> Iterator for HeapIter<'a> {
     }
 }
 
-#[derive(Debug)]
+impl<'a> DoubleEndedIterator for HeapIter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<u32> {
+        if self.sz_left == 0 {
+            return None;
+        }
+        if self.bits > 0 {
+            while let Some(&x) = self.array.last() {
+                while self.backbit > 0 {
+                    self.backbit -= 1;
+                    if (x & (1 << self.backbit)) != 0 {
+                        self.sz_left -= 1;
+                        return Some(unsplit_u32(x >> self.bits, self.backbit, self.bits));
+                    }
+                }
+                self.array = self.array.split_last().unwrap().1;
+                self.backbit = self.bits;
+            }
+        } else if let Some((&last, rest)) = self.array.split_last() {
+            self.array = rest;
+            self.sz_left -= 1;
+            return Some(last);
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
 struct BigIter<'a> {
     sz_left: usize,
     bits: u32,
@@ -553,11 +792,28 @@ impl<'a> Iterator for BigIter<'a> {
     }
 }
 
-#[derive(Debug)]
+impl<'a> DoubleEndedIterator for BigIter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<u32> {
+        while let Some((&x, rest)) = self.a.split_last() {
+            self.a = rest;
+            if x != 0 {
+                self.sz_left -= 1;
+                return Some( if x == self.bits { 0 } else { x });
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
 struct DenseIter<'a> {
     sz_left: usize,
+    base: u32,
     whichword: usize,
     whichbit: u32,
+    endword: usize,
+    endmask: u32,
     a: &'a [u32],
 }
 
@@ -565,6 +821,9 @@ impl<'a> Iterator for DenseIter<'a> {
     type Item = u32;
     #[inline]
     fn next(&mut self) -> Option<u32> {
+        if self.sz_left == 0 {
+            return None;
+        }
         loop {
             if let Some(word) = self.a.get(self.whichword) {
                 while self.whichbit < 32 {
@@ -572,7 +831,7 @@ impl<'a> Iterator for DenseIter<'a> {
                     self.whichbit = 1 + bit;
                     if word & (1 << bit) != 0 {
                         self.sz_left -= 1;
-                        return Some(((self.whichword as u32) << 5) + bit as u32);
+                        return Some(self.base + ((self.whichword as u32) << 5) + bit as u32);
                     }
                 }
                 self.whichbit = 0;
@@ -583,17 +842,80 @@ impl<'a> Iterator for DenseIter<'a> {
         }
     }
     #[inline]
-    fn last(self) -> Option<u32> {
+    fn last(mut self) -> Option<u32> {
+        self.next_back()
+    }
+    #[inline]
+    fn max(self) -> Option<u32> {
+        self.last()
+    }
+    #[inline]
+    fn count(self) -> usize {
+        self.sz_left
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.sz_left, Some(self.sz_left))
+    }
+    #[inline]
+    fn min(mut self) -> Option<u32> {
+        self.next()
+    }
+}
+
+impl<'a> DoubleEndedIterator for DenseIter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<u32> {
+        if self.sz_left == 0 {
+            return None;
+        }
+        // The bits of word endword - 1 that are left are in endmask.
+        loop {
+            let word = self.a[self.endword - 1] & self.endmask;
+            if word != 0 {
+                let bit = 31 - word.leading_zeros();
+                self.endmask = (1 << bit) - 1;
+                self.sz_left -= 1;
+                return Some(self.base + ((self.endword as u32 - 1) << 5) + bit as u32);
+            }
+            self.endword -= 1;
+            self.endmask = !0;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RunsIter<'a> {
+    sz_left: usize,
+    next: u32,
+    back: u32,
+    runs: &'a [u32],
+}
+
+impl<'a> Iterator for RunsIter<'a> {
+    type Item = u32;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
         if self.sz_left == 0 {
             return None;
         }
-        let zero_words = self.a.iter().rev().cloned()
-            .take_while(|&x| x == 0).count() as u32;
-        let zero_bits = self.a[self.a.len() - 1 - zero_words as usize].leading_zeros() as u32;
-        Some(self.a.len() as u32*32 - zero_bits - 1 - zero_words*32)
+        let x = self.next;
+        let last = self.runs[1];
+        if x == last {
+            self.runs = &self.runs[2..];
+            self.next = self.runs.first().cloned().unwrap_or(0);
+        } else {
+            self.next = x + 1;
+        }
+        self.sz_left -= 1;
+        Some(x)
     }
     #[inline]
-    fn max(self) -> Option<u32> {
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+    #[inline]
+    fn max(self) -> Option<Self::Item> {
         self.last()
     }
     #[inline]
@@ -605,11 +927,140 @@ impl<'a> Iterator for DenseIter<'a> {
         (self.sz_left, Some(self.sz_left))
     }
     #[inline]
-    fn min(mut self) -> Option<u32> {
+    fn min(mut self) -> Option<Self::Item> {
         self.next()
     }
 }
 
+impl<'a> DoubleEndedIterator for RunsIter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.sz_left == 0 {
+            return None;
+        }
+        let x = self.back;
+        let first = self.runs[self.runs.len() - 2];
+        if x == first {
+            self.runs = &self.runs[..self.runs.len() - 2];
+            self.back = self.runs.last().cloned().unwrap_or(0);
+        } else {
+            self.back = x - 1;
+        }
+        self.sz_left -= 1;
+        Some(x)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a> crate::par::Split for InternalIter<'a> {
+    fn split(self) -> (Self, Option<Self>) {
+        match self {
+            InternalIter::Heap(it) => {
+                let (a, b) = it.split();
+                (InternalIter::Heap(a), b.map(InternalIter::Heap))
+            }
+            InternalIter::Big(it) => {
+                let (a, b) = it.split();
+                (InternalIter::Big(a), b.map(InternalIter::Big))
+            }
+            InternalIter::Runs(it) => {
+                let (a, b) = it.split();
+                (InternalIter::Runs(a), b.map(InternalIter::Runs))
+            }
+            InternalIter::Dense(it) => {
+                let (a, b) = it.split();
+                (InternalIter::Dense(a), b.map(InternalIter::Dense))
+            }
+            InternalIter::Chunked(it) => {
+                let (a, b) = it.split();
+                (InternalIter::Chunked(a), b.map(InternalIter::Chunked))
+            }
+            it => (it, None),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a> crate::par::Split for HeapIter<'a> {
+    fn split(self) -> (Self, Option<Self>) {
+        if self.sz_left < crate::par::MIN_LEN || self.array.len() < 2 {
+            return (self, None);
+        }
+        let (front, back) = self.array.split_at(self.array.len() / 2);
+        // Count the back half, whose last slot may be partly used up.
+        let right = if self.bits > 0 {
+            let (&last, whole) = back.split_last().unwrap();
+            whole.iter().map(|&x| (x & mask(self.bits as usize)).count_ones() as usize).sum::<usize>()
+                + (last & mask(self.backbit as usize)).count_ones() as usize
+        } else {
+            back.len()
+        };
+        (HeapIter { sz_left: self.sz_left - right, backbit: self.bits, array: front, ..self },
+         Some(HeapIter { sz_left: right, whichbit: 0, array: back, ..self }))
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a> crate::par::Split for BigIter<'a> {
+    fn split(self) -> (Self, Option<Self>) {
+        if self.sz_left < crate::par::MIN_LEN || self.a.len() < 2 {
+            return (self, None);
+        }
+        let (front, back) = self.a.split_at(self.a.len() / 2);
+        let right = back.iter().filter(|&&x| x != 0).count();
+        (BigIter { sz_left: self.sz_left - right, a: front, ..self },
+         Some(BigIter { sz_left: right, a: back, ..self }))
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a> crate::par::Split for DenseIter<'a> {
+    fn split(self) -> (Self, Option<Self>) {
+        if self.sz_left < crate::par::MIN_LEN || self.endword - self.whichword < 2 {
+            return (self, None);
+        }
+        let mid = self.whichword + (self.endword - self.whichword) / 2;
+        let right = crate::simd::count_ones_u32(&self.a[mid..self.endword - 1])
+            + (self.a[self.endword - 1] & self.endmask).count_ones() as usize;
+        (DenseIter { sz_left: self.sz_left - right, endword: mid, endmask: !0, ..self },
+         Some(DenseIter { sz_left: right, whichword: mid, whichbit: 0, ..self }))
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a> crate::par::Split for RunsIter<'a> {
+    fn split(self) -> (Self, Option<Self>) {
+        if self.sz_left < crate::par::MIN_LEN {
+            return (self, None);
+        }
+        // Find the run holding the last element of the front half, and
+        // cut it in two if the back half starts within it.
+        let half = self.sz_left / 2;
+        let n_runs = self.runs.len() / 2;
+        let mut seen = 0;
+        for i in 0..n_runs {
+            let lo = if i == 0 { self.next } else { self.runs[2*i] };
+            let hi = if i + 1 == n_runs { self.back } else { self.runs[2*i + 1] };
+            let n = (hi - lo) as usize + 1;
+            if seen + n < half {
+                seen += n;
+                continue;
+            }
+            let mid = lo + (half - seen - 1) as u32;
+            let front = RunsIter { sz_left: half, back: mid, runs: &self.runs[..2*i + 2], ..self };
+            let back = if mid < hi {
+                RunsIter { sz_left: self.sz_left - half, next: mid + 1, runs: &self.runs[2*i..], ..self }
+            } else {
+                RunsIter { sz_left: self.sz_left - half, next: self.runs[2*i + 2],
+                           runs: &self.runs[2*i + 2..], ..self }
+            };
+            return (front, Some(back));
+        }
+        unreachable!("the runs hold sz_left elements")
+    }
+}
+
+#[cfg(test)]
 impl crate::copyset::CopySet for SetU32 {
     type Item = u32;
     type Iter = IntoIter;
@@ -635,23 +1086,23 @@ impl crate::copyset::CopySet for SetU32 {
 
 /// An iterator over a set of `u32`.
 #[derive(Debug)]
-pub struct IntoIter {
+pub struct IntoIter<A: Allocator + Clone = Global, P: SetPolicy = DefaultPolicy> {
     iter: Iter<'static>,
-    set: SetU32,
+    set: SetU32<A, P>,
 }
-impl IntoIterator for SetU32 {
+impl<A: Allocator + Clone, P: SetPolicy> IntoIterator for SetU32<A, P> {
     type Item = u32;
-    type IntoIter = IntoIter;
+    type IntoIter = IntoIter<A, P>;
 
-    fn into_iter(self) -> IntoIter {
-        let iter = unsafe { std::mem::transmute(self.private_iter()) };
+    fn into_iter(self) -> IntoIter<A, P> {
+        let iter = unsafe { core::mem::transmute(self.private_iter()) };
         IntoIter {
             iter,
             set: self,
         }
     }
 }
-impl Iterator for IntoIter {
+impl<A: Allocator + Clone, P: SetPolicy> Iterator for IntoIter<A, P> {
     type Item = u32;
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
@@ -678,30 +1129,383 @@ impl Iterator for IntoIter {
         self.iter.size_hint()
     }
 }
+impl<A: Allocator + Clone, P: SetPolicy> DoubleEndedIterator for IntoIter<A, P> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back()
+    }
+}
+impl<A: Allocator + Clone, P: SetPolicy> ExactSizeIterator for IntoIter<A, P> {}
+impl<A: Allocator + Clone, P: SetPolicy> core::iter::FusedIterator for IntoIter<A, P> {}
 
-impl Clone for SetU32 {
-    fn clone(&self) -> Self {
-        if self.0 as usize & 7 == 0 && self.0 != std::ptr::null_mut() {
-            let c = self.capacity();
-            unsafe {
-                let ptr = std::alloc::alloc_zeroed(layout_for_capacity(c)) as *mut S;
-                if ptr == std::ptr::null_mut() {
-                    std::alloc::handle_alloc_error(layout_for_capacity(c));
-                }
-                std::ptr::copy_nonoverlapping(self.0 as *const u8, ptr as *mut u8,
-                                             bytes_for_capacity(c));
-                SetU32(ptr)
+/// A draining iterator over the elements of a [`SetU32`], which
+/// leaves the set empty.
+#[derive(Debug)]
+pub struct Drain<'a, A: Allocator + Clone = Global, P: SetPolicy = DefaultPolicy> {
+    iter: IntoIter<A, P>,
+    set: core::marker::PhantomData<&'a mut SetU32<A, P>>,
+}
+impl<'a, A: Allocator + Clone, P: SetPolicy> Iterator for Drain<'a, A, P> {
+    type Item = u32;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+impl<'a, A: Allocator + Clone, P: SetPolicy> DoubleEndedIterator for Drain<'a, A, P> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back()
+    }
+}
+impl<'a, A: Allocator + Clone, P: SetPolicy> ExactSizeIterator for Drain<'a, A, P> {}
+impl<'a, A: Allocator + Clone, P: SetPolicy> core::iter::FusedIterator for Drain<'a, A, P> {}
+
+/// A parallel iterator over the elements of a [`SetU32`], from
+/// [`par_iter`](rayon::iter::IntoParallelRefIterator::par_iter).
+///
+/// The set is split along its own structure: a dense bitset by ranges
+/// of words, a hash table by ranges of slots, and a set of runs by
+/// ranges of elements, while a chunked set also splits between chunks.
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone)]
+pub struct ParIter<'a>(InternalIter<'a>);
+
+#[cfg(feature = "rayon")]
+impl<'a> rayon::iter::ParallelIterator for ParIter<'a> {
+    type Item = u32;
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+        where C: rayon::iter::plumbing::UnindexedConsumer<u32>
+    {
+        rayon::iter::plumbing::bridge_unindexed(crate::par::Producer(self.0), consumer)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, A: Allocator + Clone, P: SetPolicy> rayon::iter::IntoParallelIterator for &'a SetU32<A, P> {
+    type Item = u32;
+    type Iter = ParIter<'a>;
+    fn into_par_iter(self) -> ParIter<'a> {
+        ParIter(self.internal().iter())
+    }
+}
+
+/// A cursor over the elements of a [`SetU32`] in increasing order,
+/// which can [`seek`](Cursor::seek) ahead to any value.  Make one with
+/// [`SetU32::cursor_at`].
+///
+/// Seeking in a dense bitset goes straight to the word holding the
+/// target, and seeking in runs gallops ahead through them, and
+/// seeking in a chunked set skips whole chunks by their keys.  A
+/// hash table has no order, so its elements are copied and sorted
+/// once when the cursor is made.
+#[derive(Debug, Clone)]
+pub struct Cursor<'a> {
+    head: Option<u32>,
+    rest: CursorRest<'a>,
+}
+
+impl<'a> Cursor<'a> {
+    /// The element under the cursor, which `next` will return, or
+    /// `None` at the end of the set.
+    pub fn peek(&self) -> Option<u32> {
+        self.head
+    }
+    /// Move ahead to the first element that is at least `x`, and
+    /// return it.  A cursor never moves back, so seeking to a value no
+    /// greater than [`peek`](Cursor::peek) leaves it where it is.
+    pub fn seek(&mut self, x: u32) -> Option<u32> {
+        if let Some(h) = self.head {
+            if h < x {
+                self.head = self.rest.seek(x);
             }
-        } else {
-            SetU32(self.0)
         }
+        self.head
     }
 }
 
-impl SetU32 {
-    /// The number of elements in the set
+impl<'a> Iterator for Cursor<'a> {
+    type Item = u32;
     #[inline]
-    pub fn len(&self) -> usize {
+    fn next(&mut self) -> Option<u32> {
+        let x = self.head?;
+        self.head = self.rest.next();
+        Some(x)
+    }
+}
+impl<'a> core::iter::FusedIterator for Cursor<'a> {}
+
+/// The elements after the head of a [`Cursor`].
+#[derive(Debug, Clone)]
+enum CursorRest<'a> {
+    Tiny(Tiny),
+    Sorted { v: Vec<u32>, at: usize },
+    Dense { base: u32, a: &'a [u32], word: usize, bits: u32 },
+    Runs { runs: &'a [u32], at: usize, next: u32 },
+    Chunked(ChunksCursor<'a>),
+}
+
+impl<'a> CursorRest<'a> {
+    /// The first element that is at least `x`.
+    fn seek(&mut self, x: u32) -> Option<u32> {
+        match self {
+            CursorRest::Tiny(t) => return t.find(|&e| e >= x),
+            CursorRest::Sorted { v, at } => {
+                *at = crate::search::gallop(*at, v.len(), |i| v[i] < x);
+            }
+            CursorRest::Dense { base, a, word, bits } => {
+                if x > *base {
+                    let off = x - *base;
+                    if off >> 5 >= a.len() as u32 {
+                        *word = a.len();
+                        *bits = 0;
+                        return None;
+                    }
+                    let w = (off >> 5) as usize;
+                    if w > *word {
+                        *word = w;
+                        *bits = a[w];
+                    }
+                    if w == *word {
+                        *bits &= !0 << (off & 31);
+                    }
+                }
+            }
+            CursorRest::Runs { runs, at, next } => {
+                if *at < runs.len() && *next < x {
+                    *at = 2*crate::search::gallop(*at/2, runs.len()/2, |i| runs[2*i + 1] < x);
+                    if *at < runs.len() {
+                        *next = core::cmp::max(runs[*at], x);
+                    }
+                }
+            }
+            CursorRest::Chunked(c) => return c.seek(x),
+        }
+        self.next()
+    }
+}
+
+impl<'a> Iterator for CursorRest<'a> {
+    type Item = u32;
+    fn next(&mut self) -> Option<u32> {
+        match self {
+            CursorRest::Tiny(t) => t.next(),
+            CursorRest::Sorted { v, at } => {
+                let x = v.get(*at).cloned();
+                *at = core::cmp::min(*at + 1, v.len());
+                x
+            }
+            CursorRest::Dense { base, a, word, bits } => {
+                while *bits == 0 {
+                    if *word + 1 >= a.len() {
+                        *word = a.len();
+                        return None;
+                    }
+                    *word += 1;
+                    *bits = a[*word];
+                }
+                let bit = bits.trailing_zeros();
+                *bits &= *bits - 1;
+                Some(*base + ((*word as u32) << 5) + bit)
+            }
+            CursorRest::Runs { runs, at, next } => {
+                if *at >= runs.len() {
+                    return None;
+                }
+                let x = *next;
+                if x == runs[*at + 1] {
+                    *at += 2;
+                    if *at < runs.len() {
+                        *next = runs[*at];
+                    }
+                } else {
+                    *next = x + 1;
+                }
+                Some(x)
+            }
+            CursorRest::Chunked(c) => c.next(),
+        }
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> Clone for SetU32<A, P> {
+    fn clone(&self) -> Self {
+        if self.0 as usize > 0 && self.0 as usize & 3 == 0 {
+            let c = unsafe { (*self.0).cap as usize };
+            unsafe {
+                let ptr = allocate_for_capacity(&self.1, c);
+                core::ptr::copy_nonoverlapping(self.0 as *const u8, ptr as *mut u8,
+                                             bytes_for_capacity(c));
+                let mut new = SetU32(ptr, self.1.clone(), self.2.clone());
+                if let InternalMut::Chunked { a, .. } = new.internal_mut() {
+                    // Each chunk needs its own storage, and until it
+                    // has it the copy must not free the original's.
+                    let n = core::mem::replace(&mut a[0], 0) as usize;
+                    for i in 0..n {
+                        let e = chunks::try_clone(&self.1, &a[1 + ENTRY*i..][..ENTRY])
+                            .unwrap_or_else(|err| err.bail());
+                        a[1 + ENTRY*i..][..ENTRY].copy_from_slice(&e);
+                        a[0] += 1;
+                    }
+                }
+                new
+            }
+        } else {
+            SetU32(self.0, self.1.clone(), self.2.clone())
+        }
+    }
+}
+
+impl SetU32 {
+    /// An empty set
+    #[inline]
+    pub const fn new() -> Self {
+        SetU32(0 as *mut S, Global, DefaultPolicy)
+    }
+    /// Create a set with the given capacity
+    pub fn with_capacity_and_max(cap: usize, mx: u32) -> SetU32 {
+        SetU32::with_capacity_and_max_in(cap, mx, Global)
+    }
+    /// Create a set with the given capacity and bits
+    pub fn with_capacity_and_bits(cap: usize, bits: u32) -> SetU32 {
+        SetU32::with_capacity_and_bits_in(cap, bits, Global)
+    }
+    /// Create a set from an iterator, or return an error if we could
+    /// not allocate the memory needed.
+    pub fn try_from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Result<SetU32, TryReserveError> {
+        SetU32::try_from_iter_in(iter, Global)
+    }
+    /// Create a set from elements that are sorted in increasing order
+    /// with no duplicates, such as ids read from a database index.
+    ///
+    /// This chooses the final representation in one pass and fills it
+    /// in directly, which is much faster than inserting each element.
+    /// Only debug builds check that `v` is sorted; given unsorted input
+    /// the set may hold the wrong elements.
+    pub fn from_sorted_unique(v: &[u32]) -> Self {
+        SetU32::try_from_sorted_in(v, Global, DefaultPolicy).unwrap_or_else(|err| err.bail())
+    }
+    /// Create a set from an iterator over elements that are sorted in
+    /// increasing order with no duplicates.  See
+    /// [`SetU32::from_sorted_unique`].
+    pub fn from_sorted_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        let v: Vec<u32> = iter.into_iter().collect();
+        SetU32::from_sorted_unique(&v)
+    }
+    /// Estimate the memory that collecting `len` distinct elements no
+    /// larger than `max` into a set would use, in the same terms as
+    /// `memory_report().total()`.
+    ///
+    /// This assumes the elements are spread evenly up to `max`, and
+    /// does not count any growth of the table while they are
+    /// inserted, so treat it as a guide for budgeting.
+    pub fn estimate_mem(len: usize, max: u32) -> usize {
+        let table = SetU32::estimate_table_mem(len, max);
+        // Evenly spread elements with no gaps form a single run.
+        if len as u64 > max as u64 && runs_budget(table) >= 1 {
+            runs_mem(1)
+        } else if len >= CHUNKED_MIN {
+            // A set this big is split into chunks if that is cheaper.
+            let len = core::cmp::min(len as u64, max as u64 + 1) as usize;
+            let keys = (max >> 16) as usize + 1;
+            core::cmp::min(table, chunked_mem((0..keys).map(|_| len/keys)))
+        } else {
+            table
+        }
+    }
+    /// Estimate the memory used by anything but runs.
+    fn estimate_table_mem(len: usize, max: u32) -> usize {
+        let header = core::mem::size_of::<SetU32>();
+        // There are only so many distinct values up to max.
+        let len = core::cmp::min(len as u64, max as u64 + 1) as usize;
+        if len == 0 {
+            return header;
+        }
+        let gap = max / core::cmp::max(len - 1, 1) as u32;
+        let min = max - (len - 1) as u32 * gap;
+        if len < BITSPLITS.len() {
+            let mut v = [0; 7];
+            for i in 0..len {
+                v[i] = max - (len - 1 - i) as u32 * gap;
+            }
+            if Tiny::new_sorted_deduped(&v[..len]).is_some() {
+                return header;
+            }
+        }
+        // This mirrors the choices made by FromIterator.
+        if len as u64 > ((max - min) >> 4) as u64 {
+            // The bitset starts at min, which it stores first.
+            let span = max - (min & !31);
+            let cap = 2 + span/32 + span/128;
+            return header + bytes_for_capacity(cap as usize);
+        }
+        let bits = compute_array_bits(max);
+        let cap = if bits == 0 {
+            (len+1)*11/10
+        } else {
+            let keys = core::cmp::min(len as u64, (max/bits) as u64 + 1) as usize;
+            (keys+1)*11/10
+        };
+        header + bytes_for_capacity(cap)
+    }
+}
+
+impl<A: Allocator + Clone> SetU32<A> {
+    /// An empty set that will allocate from `alloc`
+    #[inline]
+    pub fn new_in(alloc: A) -> Self {
+        SetU32(0 as *mut S, alloc, DefaultPolicy)
+    }
+    /// Create a set with the given capacity that will allocate from `alloc`
+    pub fn with_capacity_and_max_in(cap: usize, mx: u32, alloc: A) -> Self {
+        SetU32::try_with_capacity_and_max_in(cap, mx, alloc, DefaultPolicy)
+            .unwrap_or_else(|err| err.bail())
+    }
+    /// Create a set with the given capacity and bits that will
+    /// allocate from `alloc`
+    pub fn with_capacity_and_bits_in(cap: usize, bits: u32, alloc: A) -> Self {
+        // RUNS and CHUNKED mark run and chunk tables, so take the next
+        // narrower width or the next sentinel of a big table.
+        let bits = match bits {
+            RUNS => RUNS - 1,
+            CHUNKED => CHUNKED + 1,
+            b => b,
+        };
+        SetU32::try_with_capacity_and_bits_in(cap, bits, alloc, DefaultPolicy)
+            .unwrap_or_else(|err| err.bail())
+    }
+    /// Create a set from an iterator that will allocate from `alloc`,
+    /// or return an error if we could not allocate the memory needed.
+    pub fn try_from_iter_in<I: IntoIterator<Item = u32>>(iter: I, alloc: A)
+                                                         -> Result<Self, TryReserveError> {
+        SetU32::try_from_iter_with(iter, alloc, DefaultPolicy)
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> SetU32<A, P> {
+    /// The policy followed by this set
+    #[inline]
+    pub fn policy(&self) -> &P {
+        &self.2
+    }
+    /// The allocator used by this set
+    #[inline]
+    pub fn allocator(&self) -> &A {
+        &self.1
+    }
+    /// Create a set that follows `policy` and allocates from `alloc`
+    /// from elements that are sorted in increasing order with no
+    /// duplicates.  See [`SetU32::from_sorted_unique`].
+    pub fn from_sorted_unique_in(v: &[u32], alloc: A, policy: P) -> Self {
+        SetU32::try_from_sorted_in(v, alloc, policy).unwrap_or_else(|err| err.bail())
+    }
+    /// The number of elements in the set
+    #[inline]
+    pub fn len(&self) -> usize {
         match self.internal() {
             Internal::Empty => 0,
             Internal::Stack(t) => t.sz as usize,
@@ -711,9 +1515,15 @@ impl SetU32 {
             Internal::Big { s, .. } => {
                 s.sz as usize
             }
+            Internal::Runs { s, .. } => {
+                s.sz as usize
+            }
             Internal::Dense { sz, .. } => {
                 sz as usize
             }
+            Internal::Chunked { s, .. } => {
+                s.sz as usize
+            }
         }
     }
     /// The capacity of the set
@@ -728,12 +1538,19 @@ impl SetU32 {
             Internal::Big { a, .. } => {
                 a.len()
             }
+            Internal::Runs { a, .. } => {
+                a.len()
+            }
             Internal::Dense { a, .. } => {
                 a.len()
             }
+            Internal::Chunked { a, .. } => {
+                a.len()
+            }
         }
     }
     /// Print debugging information about this set.
+    #[cfg(any(feature = "std", test))]
     pub fn debug_me(&self, msg: &str) {
         match self.internal() {
             Internal::Empty => println!("empty set: {}", msg),
@@ -756,234 +1573,333 @@ impl SetU32 {
                 let v: Vec<_> = a.iter().cloned().map(|x| x % a.len() as u32).collect();
                 println!("     >>>{:?}", v);
             }
-            Internal::Dense { sz, a } => {
-                println!("{}: dense {:?}\n    {:?}\n    => {:?}",
-                         msg, sz, a, self.iter().collect::<Vec<u32>>());
+            Internal::Runs { s, a } => {
+                println!("{}: runs {:?}\n    {:?}", msg, s, runs_of(a));
+            }
+            Internal::Dense { sz, base, a } => {
+                println!("{}: dense {:?} from {}\n    {:?}\n    => {:?}",
+                         msg, sz, base, a, self.iter().collect::<Vec<u32>>());
                 println!("    foo {:?}", self.iter());
             }
+            Internal::Chunked { s, a } => {
+                println!("{}: chunked {:?}", msg, s);
+                for e in chunk_index(a).chunks(ENTRY) {
+                    println!("    chunk {} holds {}", chunks::key(e), chunks::len(e));
+                }
+            }
         }
     }
     /// Tally up how much memory is in use.
     #[inline]
     pub fn mem_used(&self) -> usize {
         match self.internal() {
-            Internal::Empty => std::mem::size_of::<Self>(),
-            Internal::Stack(_) => std::mem::size_of::<Self>(),
+            Internal::Empty => core::mem::size_of::<Self>(),
+            Internal::Stack(_) => core::mem::size_of::<Self>(),
             Internal::Heap { s, .. } =>
-                std::mem::size_of::<Self>() + s.cap as usize*4-4,
+                core::mem::size_of::<Self>() + s.cap as usize*4-4,
             Internal::Dense { a, .. } =>
-                std::mem::size_of::<Self>() + a.len()*4-4,
+                core::mem::size_of::<Self>() + a.len()*4,
             Internal::Big { s, .. } =>
-                std::mem::size_of::<Self>() + s.cap as usize*4-4,
+                core::mem::size_of::<Self>() + s.cap as usize*4-4,
+            Internal::Runs { s, .. } =>
+                core::mem::size_of::<Self>() + s.cap as usize*4-4,
+            Internal::Chunked { s, a } =>
+                core::mem::size_of::<Self>() + s.cap as usize*4-4 + chunked_storage(a),
+        }
+    }
+    /// How the set is currently stored.
+    pub fn repr(&self) -> Repr {
+        match self.internal() {
+            Internal::Empty => Repr::Empty,
+            Internal::Stack(_) => Repr::Tiny,
+            Internal::Heap { s, .. } => Repr::Heap { bits: s.bits as u32, cap: s.cap as usize },
+            Internal::Big { s, .. } => Repr::Big { cap: s.cap as usize },
+            Internal::Runs { a, .. } => Repr::Runs { runs: a[0] as usize, cap: runs_room(a.len()) },
+            Internal::Dense { a, .. } => Repr::Dense { words: a.len() },
+            Internal::Chunked { a, .. } => Repr::Chunked { chunks: a[0] as usize },
+        }
+    }
+    /// Break down the memory in use into header, table and slack.
+    pub fn memory_report(&self) -> MemoryReport {
+        let header = core::mem::size_of::<Self>();
+        match self.internal() {
+            Internal::Empty | Internal::Stack(_) => MemoryReport { header, table: 0, slack: 0 },
+            Internal::Runs { a, .. } => {
+                // The first word, which counts the runs, is overhead.
+                let used = 2*a[0] as usize;
+                MemoryReport {
+                    header: header + bytes_for_capacity(a.len()) - a.len()*4 + 4,
+                    table: used*4,
+                    slack: (a.len() - 1 - used)*4,
+                }
+            }
+            Internal::Dense { a, .. } => {
+                // The word before the bitset, which holds its base, is
+                // overhead.
+                let used = a.iter().filter(|&&x| x != 0).count();
+                MemoryReport {
+                    header: header + bytes_for_capacity(a.len() + 1) - a.len()*4,
+                    table: used*4,
+                    slack: (a.len() - used)*4,
+                }
+            }
+            Internal::Heap { a, .. } | Internal::Big { a, .. } => {
+                let used = a.iter().filter(|&&x| x != 0).count();
+                MemoryReport {
+                    header: header + bytes_for_capacity(a.len()) - a.len()*4,
+                    table: used*4,
+                    slack: (a.len() - used)*4,
+                }
+            }
+            Internal::Chunked { a, .. } => {
+                // The index, including any elements stored inline in
+                // it, is overhead; its spare entries are slack.
+                let index = chunk_index(a);
+                let (table, slack) = index.chunks(ENTRY).map(chunks::storage)
+                    .fold((0, 0), |(t, s), (u, f)| (t + u, s + f));
+                MemoryReport {
+                    header: header + bytes_for_capacity(1 + index.len()),
+                    table,
+                    slack: slack + (a.len() - 1 - index.len())*4,
+                }
+            }
         }
     }
     /// This requires that we currently be a dense! It also requires
     /// that we add another element immediately without incrementing
     /// sz.
-    unsafe fn dense_increase_mx(&mut self, mx: u32) -> &mut [u32] {
+    unsafe fn try_dense_increase_mx(&mut self, mx: u32) -> Result<&mut [u32], TryReserveError> {
         let ptr = self.0;
-        let cap = 1 + mx/32 + mx/128;
+        let base = (*ptr).array;
+        let span = mx - base;
+        // The first word holds the base, and words past u32::MAX would
+        // never be used.
+        let cap = 1 + core::cmp::min(1 + span/32 + span/128, (!base >> 5) + 1);
 
         let oldcap = (*ptr).cap;
-        self.0 = std::alloc::realloc(ptr as *mut u8,
-                                     layout_for_capacity(oldcap as usize),
-                                     bytes_for_capacity(cap as usize)) as *mut S;
-        if self.0 as usize == 0 {
-            std::alloc::handle_alloc_error(layout_for_capacity(cap as usize));
-        }
+        let new_layout = try_layout_for_capacity(cap as usize)?;
+        // The allocator zeroes the newly added words for us, and
+        // leaves the old allocation alone if it fails.
+        self.0 = match self.1.grow_zeroed(core::ptr::NonNull::new_unchecked(ptr as *mut u8),
+                                          layout_for_capacity(oldcap as usize),
+                                          new_layout) {
+            Ok(ptr) => ptr.cast::<S>().as_ptr(),
+            Err(_) => return Err(TryReserveError::alloc_error(new_layout)),
+        };
         (*self.0).cap = cap;
         (*self.0).sz += 1;
         match self.internal_mut() {
-            InternalMut::Dense { a, .. } => {
-                for i in oldcap as usize .. a.len() {
-                    a[i] = 0;
-                }
-                a
-            }
+            InternalMut::Dense { a, .. } => Ok(a),
             _ => unreachable!(),
         }
     }
 
-    fn dense_with_max(mx: u32) -> SetU32 {
-        let cap = 1 + mx/32 + mx/128;
-        // This should be stored in a dense bitset.
+    /// An empty dense bitset of `words` words, whose first bit stands
+    /// for `base` rounded down to a multiple of 32.
+    fn try_dense_in(base: u32, words: u32, alloc: A, policy: P) -> Result<Self, TryReserveError> {
+        let base = base & !31;
+        // Words past u32::MAX would never be used.
+        let words = core::cmp::min(words, (!base >> 5) + 1);
+        // The base is stored in the first word.
+        let cap = words + 1;
         unsafe {
-            let x = SetU32(std::alloc::alloc_zeroed(layout_for_capacity(cap as usize)) as *mut S);
+            let x = SetU32(try_allocate_for_capacity(&alloc, cap as usize)?, alloc, policy);
             (*x.0).cap = cap;
             (*x.0).bits = 32;
-            x
+            (*x.0).array = base;
+            Ok(x)
         }
     }
+    /// An empty dense bitset covering `mn..=mx`, with room to grow
+    /// upwards.
+    fn try_dense_with_range_in(mn: u32, mx: u32, alloc: A, policy: P)
+                               -> Result<Self, TryReserveError> {
+        let span = mx - (mn & !31);
+        SetU32::try_dense_in(mn, 1 + span/32 + span/128, alloc, policy)
+    }
 
-    /// Create a set with the given capacity
-    pub fn with_capacity_and_max(cap: usize, mx: u32) -> SetU32 {
-        if cap as u32 > mx >> 5 {
-            SetU32::dense_with_max(mx)
+    fn try_with_capacity_and_max_in(cap: usize, mx: u32, alloc: A, policy: P)
+                                     -> Result<Self, TryReserveError> {
+        SetU32::try_with_capacity_and_range_in(cap, 0, mx, alloc, policy)
+    }
+    fn try_with_capacity_and_range_in(cap: usize, mn: u32, mx: u32, alloc: A, policy: P)
+                                      -> Result<Self, TryReserveError> {
+        let p = policy.policy();
+        if p.use_dense(cap as u64, (mx - mn) as u64, cap as u64 > ((mx - mn) >> 5) as u64) {
+            SetU32::try_dense_with_range_in(mn, mx, alloc, policy)
         } else {
-            SetU32::with_capacity_and_bits(cap, compute_array_bits(mx))
+            let bits = if p.allows_heap() { compute_array_bits(mx) } else { 0 };
+            let cap = p.capacity_for(cap).unwrap_or(cap);
+            SetU32::try_with_capacity_and_bits_in(cap, bits, alloc, policy)
         }
     }
-    /// Create a set with the given capacity and bits
-    pub fn with_capacity_and_bits(cap: usize, bits: u32) -> SetU32 {
+    fn try_with_capacity_and_bits_in(cap: usize, bits: u32, alloc: A, policy: P)
+                                      -> Result<Self, TryReserveError> {
+        if cap > core::u32::MAX as usize {
+            return Err(TryReserveError::capacity_overflow());
+        }
         if cap > 0 {
             unsafe {
-                let x = SetU32(std::alloc::alloc_zeroed(layout_for_capacity(cap)) as *mut S);
+                let x = SetU32(try_allocate_for_capacity(&alloc, cap)?, alloc, policy);
                 (*x.0).cap = cap as u32;
                 (*x.0).bits = if bits == 0 {
                     let mut b = 0;
-                    while b <= 32 {
-                        b = crate::rand::rand32();
+                    while b <= CHUNKED {
+                        b = crate::rand::rand32(cap as u64 ^ b as u64);
                     }
                     b
                 } else {
                     bits
                 };
-                x
+                Ok(x)
             }
         } else {
-            SetU32(0 as *mut S)
+            Ok(SetU32(0 as *mut S, alloc, policy))
         }
     }
-    /// An empty set
-    #[inline]
-    pub const fn new() -> Self {
-        SetU32(0 as *mut S)
-    }
 
     /// Insert and return true if it was not present.
     pub fn insert(&mut self, e: u32) -> bool {
+        self.try_insert(e).unwrap_or_else(|err| err.bail())
+    }
+
+    /// Insert and return true if it was not present, or an error if
+    /// we could not allocate the memory needed.  On error the set is
+    /// left unchanged.
+    pub fn try_insert(&mut self, e: u32) -> Result<bool, TryReserveError> {
         match self.internal_mut() {
             InternalMut::Empty => {
-                if let Some(t) = Tiny::from_singleton(e) {
-                    *self = SetU32(t.to_usize() as *mut S);
-                    return true;
+                if let Some(t) = Tiny::from_singleton(e).filter(|_| self.2.policy().allows_tiny()) {
+                    self.0 = t.to_usize() as *mut S;
+                    return Ok(true);
                 }
                 // println!("I could not create tiny set with singleton {}", e);
-                *self = Self::with_capacity_and_max(1, e);
+                *self = Self::try_with_capacity_and_max_in(1, e, self.1.clone(), self.2.clone())?;
             }
             InternalMut::Stack(t) => {
                 if let Some(newt) = t.insert(e) {
-                    *self = SetU32(newt.to_usize() as *mut S);
-                    return newt.sz != t.sz;
+                    self.0 = newt.to_usize() as *mut S;
+                    return Ok(newt.sz != t.sz);
                 }
-                *self = Self::with_capacity_and_max(t.sz as usize + 1,
-                                                    t.merge(Some(e).into_iter()).max().unwrap());
+                let mn = t.min().map_or(e, |m| core::cmp::min(m, e));
+                let mx = t.merge(Some(e).into_iter()).max().unwrap();
+                let mut new = Self::try_with_capacity_and_range_in(t.sz as usize + 1, mn, mx,
+                                                                   self.1.clone(), self.2.clone())?;
                 // self.debug_me("empty array");
                 for x in t {
-                    self.insert(x);
+                    new.try_insert(x)?;
                     // self.debug_me(&format!("   ...after inserting {}", x));
                 }
-                self.insert(e);
+                new.try_insert(e)?;
                 // self.debug_me(&format!("   ...and inserting {}", e));
-                return true;
+                *self = new;
+                return Ok(true);
             }
             _ => (),
         }
+        let alloc = self.1.clone();
+        let policy = self.2.clone();
+        let p = policy.policy();
         match self.internal_mut() {
             InternalMut::Empty => unreachable!(),
             InternalMut::Stack(_) => unreachable!(),
-            InternalMut::Dense { sz, a } => {
-                let key = (e >> 5) as usize;
-                if let Some(bits) = a.get_mut(key) {
-                    let whichbit = 1 << (e & 31);
-                    let present = *bits & whichbit != 0;
-                    *bits = *bits | whichbit;
-                    if !present {
-                        *sz = *sz + 1;
-                    }
-                    !present
+            InternalMut::Dense { sz, base, a } => {
+                if let Some(new) = dense_insert_in_place(sz, base, a, e) {
+                    Ok(new)
                 } else {
-                    // println!("key is {}", key);
-                    if key > 64*(*sz as usize) {
+                    let top = (base as u64 + a.len() as u64*32 - 1) as u32;
+                    let span = if e < base { top - e } else { e - base };
+                    if runs_pay_off(&p, Internal::Dense { sz: *sz, base, a: &*a }, e, e)
+                        || chunks_pay_off(&p, Internal::Dense { sz: *sz, base, a: &*a }, e) {
+                        self.try_rebuild_with(e)?;
+                    } else if !p.use_dense(*sz as u64 + 1, span as u64, (span >> 5) as usize <= 64*(*sz as usize)) {
                         // It is getting sparse, so let us switch back
                         // to a non-hash table.
                         let cap = 1+2*(*sz as usize);
-                        let mut new = SetU32::with_capacity_and_bits(cap, 0);
+                        let mut new = SetU32::try_with_capacity_and_bits_in(cap, 0, alloc, policy)?;
                         for x in self.iter() {
-                            new.insert(x);
+                            new.try_insert(x)?;
+                        }
+                        new.try_insert(e)?;
+                        *self = new;
+                    } else if e < base {
+                        // Grow the bitset down to e, leaving a quarter
+                        // as much again spare below it.
+                        let lo = e.saturating_sub(span/4);
+                        let mut new = SetU32::try_dense_in(lo, ((top - (lo & !31)) >> 5) + 1,
+                                                           alloc, policy)?;
+                        if let InternalMut::Dense { sz: newsz, base: nb, a: na } = new.internal_mut() {
+                            let shift = ((base - nb) >> 5) as usize;
+                            na[shift..shift + a.len()].copy_from_slice(a);
+                            *newsz = *sz;
+                            dense_insert_in_place(newsz, nb, na, e);
                         }
-                        new.insert(e);
                         *self = new;
                     } else {
                         unsafe {
-                            self.dense_increase_mx(e)[key] = 1 << (e & 31);
+                            self.try_dense_increase_mx(e)?[(span >> 5) as usize] = 1 << (e & 31);
                         }
                     }
-                    true
+                    Ok(true)
                 }
             }
             InternalMut::Heap { s, a } => {
                 if compute_array_bits(e) < s.bits {
-                    let newcap = s.cap+1+(crate::rand::rand32() % s.cap);
-                    let mut new = Self::with_capacity_and_bits(newcap as usize,
-                                                               compute_array_bits(e));
+                    let used = a.iter().filter(|&&x| x != 0).count();
+                    let newcap = p.grow(s.cap as usize, used + 1, || {
+                        (s.cap+1+(crate::rand::rand32(s.cap as u64) % s.cap)) as usize
+                    });
+                    let mut new = Self::try_with_capacity_and_bits_in(newcap, compute_array_bits(e),
+                                                                      alloc, policy)?;
                     // new.debug_me("\n\nnew set");
                     for d in self.iter() {
-                        new.insert(d);
+                        new.try_insert(d)?;
                         // new.debug_me(&format!("\n -- after inserting {}", d));
                     }
-                    new.insert(e);
+                    new.try_insert(e)?;
                     // new.debug_me(&format!("\n -- after inserting {}", e));
                     *self = new;
-                    return true;
-                }
-                let (key, offset) = split_u32(e, s.bits);
-                match p_lookfor(key, a, s.bits) {
-                    LookedUp::KeyFound(idx) => {
-                        if a[idx] & (1 << offset) != 0 {
-                            return false;
-                        } else {
-                            a[idx] = a[idx] | (1 << offset);
-                            s.sz += 1;
-                            return true;
-                        }
-                    }
-                    LookedUp::EmptySpot(idx) => {
-                            a[idx] = key << s.bits | 1 << offset;
-                            s.sz += 1;
-                            return true;
-                    }
-                    LookedUp::NeedInsert => {
-                    },
+                    return Ok(true);
                 }
-                // println!("looking for space in sparse... {:?}", a);
-                if a.iter().cloned()
-                    .filter(|&x| x == 0) // look for empty spots
-                    .enumerate() // count them
-                    .any(|(n,_)| n+1 > a.len() >> 4) // we have more than 1/16 empty?
-                {
-                    let idx = p_insert(key, a, s.bits);
-                    // println!("about to insert key {} with elem {} at {}",
-                    //          key, e, idx);
-                    a[idx] = (key << s.bits) | (1 << offset);
-                    s.sz += 1;
-                    return true;
+                if let Some(new) = heap_insert_in_place(&p, s, a, e) {
+                    return Ok(new);
                 }
                 // println!("no room in the sparse set... {:?}", a);
+                if runs_pay_off(&p, Internal::Heap { s: &*s, a: &*a }, e, e)
+                    || chunks_pay_off(&p, Internal::Heap { s: &*s, a: &*a }, e) {
+                    self.try_rebuild_with(e)?;
+                    return Ok(true);
+                }
                 // We'll have to expand the set.
-                let mx = a.iter().cloned().map(|x| (x >> s.bits)*s.bits + s.bits).max().unwrap();
+                let keys = a.iter().cloned().filter(|&x| x != 0).map(|x| (x >> s.bits)*s.bits);
+                let mn = keys.clone().min().unwrap();
+                let mn = if e < mn { e } else { mn };
+                let mx = keys.max().unwrap() + s.bits;
                 let mx = if e > mx { e } else { mx };
-                if s.cap > mx >> 6 {
+                if p.use_dense(s.sz as u64 + 1, (mx - mn) as u64, s.cap > (mx - mn) >> 6) {
                     // A dense set will save memory
-                    let mut new = Self::dense_with_max(mx);
+                    let mut new = Self::try_dense_with_range_in(mn, mx, alloc, policy)?;
                     for x in self.iter() {
-                        new.insert(x);
+                        new.try_insert(x)?;
                     }
-                    new.insert(e);
+                    new.try_insert(e)?;
                     *self = new;
                 } else {
                     // Let's keep things sparse
                     // A dense set will cost us memory
-                    let newcap: u32 = s.cap + 1 + (crate::rand::rand32() % s.cap);
-                    let mut new = Self::with_capacity_and_bits(newcap as usize, s.bits);
+                    let used = a.iter().filter(|&&x| x != 0).count();
+                    let newcap = p.grow(s.cap as usize, used + 1, || {
+                        (s.cap + 1 + (crate::rand::rand32(s.cap as u64) % s.cap)) as usize
+                    });
+                    let mut new = Self::try_with_capacity_and_bits_in(newcap, s.bits, alloc, policy)?;
                     // new.debug_me("initial new");
                     for v in self.iter() {
-                        new.insert(v);
+                        new.try_insert(v)?;
                     }
-                    new.insert(e);
+                    new.try_insert(e)?;
                     *self = new;
                 }
-                true
+                Ok(true)
             }
             InternalMut::Big { s, a } => {
                 if e == s.bits {
@@ -994,9 +1910,10 @@ impl SetU32 {
                     // changing the "bits" is $O(N)$, so it's worth
                     // a high O(1) cost to reduce collisions.
                     let had_zero = p_remove(s.bits, a, 0);
+                    let mut i = s.bits;
                     loop {
-                        let i: u32 = crate::rand::rand32();
-                        if i > 32 && !a.iter().any(|&v| v == i) {
+                        i = crate::rand::rand32(i as u64);
+                        if i > CHUNKED && !a.iter().any(|&v| v == i) {
                             s.bits = i;
                             break;
                         }
@@ -1005,39 +1922,42 @@ impl SetU32 {
                         a[p_insert(s.bits, a, 0)] = s.bits;
                     }
                 }
-                let e = if e == 0 { s.bits } else { e };
-                match p_lookfor(e, a, 0) {
-                    LookedUp::KeyFound(_) => {
-                        return false;
-                    }
-                    LookedUp::EmptySpot(idx) => {
-                        a[idx] = e;
-                        s.sz += 1;
-                        return true;
+                if let Some(new) = big_insert_in_place(&p, s, a, e) {
+                    return Ok(new);
+                }
+                if runs_pay_off(&p, Internal::Big { s: &*s, a: &*a }, e, e)
+                    || chunks_pay_off(&p, Internal::Big { s: &*s, a: &*a }, e) {
+                    self.try_rebuild_with(e)?;
+                    return Ok(true);
+                }
+                // Values far from zero may still be close together.
+                let vals = a.iter().cloned().filter(|&x| x != 0).map(|x| if x == s.bits { 0 } else { x });
+                let mn = core::cmp::min(e, vals.clone().min().unwrap_or(e));
+                let mx = core::cmp::max(e, vals.max().unwrap_or(e));
+                if p.use_dense(s.sz as u64 + 1, (mx - mn) as u64, s.cap > (mx - mn) >> 5) {
+                    // A dense set will save memory
+                    let mut new = Self::try_dense_with_range_in(mn, mx, alloc, policy)?;
+                    for x in self.iter() {
+                        new.try_insert(x)?;
                     }
-                    LookedUp::NeedInsert => (),
-                }
-                // println!("looking for space in... {:?}", a);
-                if a.iter().cloned()
-                    .filter(|&x| x == 0) // look for empty spots
-                    .enumerate() // count them
-                    .any(|(n,_)| n+1 > a.len() >> 4) // we have more than 1/16 empty?
-                {
-                    let idx = p_insert(e, a, 0);
-                    // println!("about to insert at {}", p_insert(e, a, 0));
-                    a[idx] = e;
-                    s.sz += 1;
-                    return true;
+                    new.try_insert(e)?;
+                    *self = new;
+                    return Ok(true);
                 }
+                let e = if e == 0 { s.bits } else { e };
                 // println!("no room in the set... {:?}", a);
-                let newcap: u32 = s.cap + 1 + (crate::rand::rand32() % s.cap);
-                let mut new = Self::with_capacity_and_bits(newcap as usize, s.bits);
+                let newcap = p.grow(s.cap as usize, s.sz as usize + 1, || {
+                    (s.cap + 1 + (crate::rand::rand32(s.cap as u64) % s.cap)) as usize
+                });
+                let mut new = Self::try_with_capacity_and_bits_in(newcap, s.bits, alloc, policy)?;
                 // new.debug_me("initial new");
                 match new.internal_mut() {
                     InternalMut::Empty => unreachable!(),
                     InternalMut::Stack(_) => unreachable!(),
                     InternalMut::Dense { .. } => unreachable!(),
                     InternalMut::Heap { .. } => unreachable!(),
+                    InternalMut::Runs { .. } => unreachable!(),
+                    InternalMut::Chunked { .. } => unreachable!(),
                     InternalMut::Big { s: ns, a: na } => {
                         for v in a.iter().cloned().filter(|&x| x != 0) {
                             na[p_insert(v, na, 0)] = v;
@@ -1053,9 +1973,63 @@ impl SetU32 {
                     }
                 }
                 *self = new;
-                true
+                Ok(true)
+            }
+            InternalMut::Runs { s, a } => {
+                if let Some(new) = runs_insert(s, a, e, e)? {
+                    return Ok(new);
+                }
+                // We need another run, unless a table is now cheaper.
+                if runs_pay_off(&p, Internal::Runs { s: &*s, a: &*a }, e, e) {
+                    self.try_grow_runs(1)?;
+                    self.try_insert(e)
+                } else {
+                    self.try_rebuild_with(e)?;
+                    Ok(true)
+                }
+            }
+            InternalMut::Chunked { .. } => self.try_chunked_insert(e),
+        }
+    }
+
+    /// Reserve room for at least `additional` more elements, or
+    /// return an error if we could not allocate it.  On error the set
+    /// is left unchanged.
+    ///
+    /// Inserting can still allocate if the new elements force the set
+    /// to change representation.  A dense set's size depends on its
+    /// largest element rather than its length, and a set stored as
+    /// runs grows with its number of runs, and a set split into chunks
+    /// grows chunk by chunk, so reserving space in any of these does
+    /// nothing.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let needed = self.len().checked_add(additional)
+            .ok_or_else(TryReserveError::capacity_overflow)?;
+        // The tables grow once fewer than 1/16 of their slots are
+        // empty, so leave that much slack.
+        let needed = needed.checked_add(needed/16 + 1)
+            .ok_or_else(TryReserveError::capacity_overflow)?;
+        let bits = match self.internal() {
+            Internal::Empty | Internal::Stack(_) if additional == 0 => return Ok(()),
+            Internal::Empty | Internal::Stack(_) if self.2.policy().forces_dense() => return Ok(()),
+            // We don't know what is coming, so use a plain hash table,
+            // which can hold any value without being rebuilt.
+            Internal::Empty | Internal::Stack(_) => 0,
+            Internal::Dense { .. } | Internal::Runs { .. } | Internal::Chunked { .. } => return Ok(()),
+            Internal::Heap { s, .. } | Internal::Big { s, .. } => {
+                if s.cap as usize >= needed {
+                    return Ok(());
+                }
+                s.bits
             }
+        };
+        let mut new = SetU32::try_with_capacity_and_bits_in(needed, bits, self.1.clone(),
+                                                          self.2.clone())?;
+        for x in self.iter() {
+            new.try_insert(x)?;
         }
+        *self = new;
+        Ok(())
     }
 
     /// Remove
@@ -1066,174 +2040,727 @@ impl SetU32 {
                 if t.clone().any(|x| x == e) {
                     let sz = t.sz - 1;
                     if sz == 0 {
-                        *self = SetU32(0 as *mut S);
+                        self.0 = 0 as *mut S;
                     } else {
-                        *self = t.filter(|&x| x != e).collect();
+                        *self = Self::from_vec_in(t.filter(|&x| x != e).collect(),
+                                                  self.1.clone(), self.2.clone());
                     }
                     true
                 } else {
                     false
                 }
             }
-            InternalMut::Dense { sz, a } => {
-                let key = e >> 5;
-                if let Some(bits) = a.get_mut(key as usize) {
-                    let whichbit = 1 << (e & 31);
-                    let present = *bits & whichbit != 0;
-                    *bits = *bits & !whichbit;
-                    if present {
-                        *sz = *sz - 1;
-                    }
-                    present
-                } else {
-                    false
-                }
-            }
-            InternalMut::Heap { s, a } => {
-                if compute_array_bits(e) < s.bits {
-                    return false;
-                }
-                let (key, offset) = split_u32(e, s.bits);
-                if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits) {
-                    if a[idx] & (1 << offset) != 0 {
-                        let newa = a[idx] & !(1<<offset);
-                        s.sz -= 1;
-                        if newa == key << s.bits {
-                            // We've removed everything with this key,
-                            // so remove the whole key!
-                            p_remove(key, a, s.bits);
-                        } else {
-                            a[idx] = newa;
-                        }
-                        true
-                    } else {
-                        false
+            InternalMut::Dense { sz, base, a } => dense_remove(sz, base, a, e),
+            InternalMut::Heap { s, a } => heap_remove(s, a, e),
+            InternalMut::Big { s, a } => big_remove(s, a, e),
+            InternalMut::Runs { s, a } => {
+                if let Some(had_e) = runs_remove(s, a, e, e) {
+                    if had_e {
+                        self.shrink_runs();
                     }
-                } else {
-                    false
-                }
-            }
-            InternalMut::Big { s, a } => {
-                if e == s.bits {
-                    return false;
-                }
-                let e = if e == 0 { s.bits } else { e };
-                let had_e = p_remove(e, a, 0);
-                if had_e {
-                    s.sz -= 1;
+                    return had_e;
                 }
-                had_e
+                // Splitting a run needs room for another.
+                self.try_grow_runs(1).unwrap_or_else(|err| err.bail());
+                self.remove(e)
             }
+            InternalMut::Chunked { .. } => self.try_chunked_remove(e).unwrap_or_else(|err| err.bail()),
         }
     }
 
     /// Contais
     pub fn contains(&self, e: u32) -> bool {
+        self.internal().contains(e)
+    }
+
+    /// Insert every element of `v`, returning how many of them were
+    /// not already present.
+    ///
+    /// This makes room for the whole batch up front and then decodes
+    /// the set just once, rather than once per element as repeated
+    /// calls to `insert` would.
+    pub fn insert_slice(&mut self, v: &[u32]) -> usize {
+        let before = self.len();
         match self.internal() {
-            Internal::Empty => false,
-            Internal::Stack(t) => t.contains(e),
-            Internal::Dense { a, .. } => {
-                let key = e >> 5;
-                if let Some(bits) = a.get(key as usize) {
-                    bits & (1 << (e & 31)) != 0
-                } else {
-                    false
-                }
+            Internal::Empty | Internal::Stack(_) => {
+                // A small set is cheapest to rebuild in one pass.
+                let mut all: Vec<u32> = self.iter().collect();
+                all.extend_from_slice(v);
+                *self = Self::from_vec_in(all, self.1.clone(), self.2.clone());
+                return self.len() - before;
             }
-            Internal::Heap { s, a } => {
-                if compute_array_bits(e) < s.bits {
-                    // println!("too big a thing");
-                    return false;
+            Internal::Dense { base, a, .. } => {
+                // Grow the bitset (or leave it) just once each way.
+                if let Some(&mx) = v.iter().max() {
+                    if mx >= base && ((mx - base) >> 5) as usize >= a.len() {
+                        self.insert(mx);
+                    }
                 }
-                let (key, offset) = split_u32(e, s.bits);
-                if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits) {
-                    a[idx] & (1 << offset) != 0
-                } else {
-                    // self.debug_me(&format!("did not find key {} from {}", key, e));
-                    false
+                if let Some(&mn) = v.iter().min() {
+                    if mn < base {
+                        self.insert(mn);
+                    }
                 }
             }
-            Internal::Big { s, a } => {
-                if e == s.bits {
-                    return false;
-                }
-                let e = if e == 0 { s.bits } else { e };
-                p_lookfor(e, a, 0).key_found()
+            Internal::Heap { .. } | Internal::Runs { .. } | Internal::Chunked { .. } => (),
+            Internal::Big { .. } => {
+                self.try_reserve(v.len()).unwrap_or_else(|err| err.bail());
             }
         }
-    }
-
-    /// Iterate over
-    #[inline]
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item=u32> + 'a + std::fmt::Debug {
-        self.private_iter()
-    }
-    fn private_iter<'a>(&'a self) -> Iter<'a> {
-        match self.internal() {
-            Internal::Empty => Iter::Empty,
-            Internal::Stack(t) => Iter::Stack( t ),
-            Internal::Heap { s, a } => {
-                Iter::Heap(HeapIter {
-                    sz_left: s.sz as usize,
-                    bits: s.bits,
-                    whichbit: 0,
-                    array: a,
-                })
-            }
-            Internal::Big { s, a } => {
-                Iter::Big(BigIter { sz_left: s.sz as usize, bits: s.bits, a })
+        let mut rest = v;
+        while !rest.is_empty() {
+            rest = &rest[self.insert_in_place(rest)..];
+            if let Some((&e, tail)) = rest.split_first() {
+                // This one needs the table rebuilt.
+                self.insert(e);
+                rest = tail;
             }
-            Internal::Dense { a, sz } => {
-                Iter::Dense(DenseIter {
-                    sz_left: sz as usize,
-                    whichword: 0,
-                    whichbit: 0,
-                    a
-                })
+        }
+        self.len() - before
+    }
+    /// Insert elements from the front of `v` for as long as that needs
+    /// no change to the table, returning how many were inserted.
+    fn insert_in_place(&mut self, v: &[u32]) -> usize {
+        let p = self.2.policy();
+        let alloc = self.1.clone();
+        let v = v.iter().cloned();
+        match self.internal_mut() {
+            InternalMut::Empty | InternalMut::Stack(_) => 0,
+            InternalMut::Dense { sz, base, a } =>
+                v.take_while(|&e| dense_insert_in_place(sz, base, a, e).is_some()).count(),
+            InternalMut::Heap { s, a } =>
+                v.take_while(|&e| heap_insert_in_place(&p, s, a, e).is_some()).count(),
+            InternalMut::Big { s, a } =>
+                v.take_while(|&e| big_insert_in_place(&p, s, a, e).is_some()).count(),
+            InternalMut::Runs { s, a } =>
+                v.take_while(|&e| matches!(runs_insert(s, a, e, e), Ok(Some(_)))).count(),
+            InternalMut::Chunked { s, a } => {
+                // Elements landing in existing chunks need no change
+                // to the index.
+                v.take_while(|&e| match chunk_find(chunk_index(a), e >> 16) {
+                    Ok(i) => match chunks::try_insert(&alloc, chunk_at_mut(a, i), e as u16) {
+                        Ok(new) => {
+                            s.sz += new as u32;
+                            true
+                        }
+                        Err(_) => false,
+                    },
+                    Err(_) => false,
+                }).count()
             }
         }
     }
-    /// Clears the set, returning all elements in an iterator.
-    #[inline]
-    pub fn drain<'a>(&'a mut self) -> impl Iterator<Item=u32> + 'a {
-        let set: SetU32 = std::mem::replace(self, SetU32::new());
-        let iter = unsafe { std::mem::transmute(set.private_iter()) };
-        IntoIter {
-            iter,
-            set,
+
+    /// Look up every element of `v`, setting `found[i]` to whether
+    /// `v[i]` is in the set.
+    ///
+    /// This decodes the set once for the whole batch, which makes it
+    /// faster than calling `contains` for each element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `v` and `found` have different lengths.
+    pub fn contains_many(&self, v: &[u32], found: &mut [bool]) {
+        assert_eq!(v.len(), found.len(), "contains_many needs one output per element");
+        let pairs = v.iter().cloned().zip(found.iter_mut());
+        match self.internal() {
+            Internal::Empty => pairs.for_each(|(_, f)| *f = false),
+            Internal::Stack(t) => pairs.for_each(|(e, f)| *f = t.contains(e)),
+            Internal::Dense { base, a, .. } => pairs.for_each(|(e, f)| *f = dense_contains(base, a, e)),
+            Internal::Heap { s, a } => pairs.for_each(|(e, f)| *f = heap_contains(s, a, e)),
+            Internal::Big { s, a } => pairs.for_each(|(e, f)| *f = big_contains(s, a, e)),
+            Internal::Runs { a, .. } => pairs.for_each(|(e, f)| *f = runs_contains(a, e)),
+            Internal::Chunked { a, .. } => pairs.for_each(|(e, f)| *f = chunked_contains(a, e)),
         }
     }
 
-    fn internal<'a>(&'a self) -> Internal<'a> {
-        if self.0 as usize == 0 {
-            Internal::Empty
-        } else if self.0 as usize & 3 != 0 {
-            Internal::Stack(Tiny::from_usize(self.0 as usize))
-        } else {
-            let s = unsafe { &*self.0 };
-            let a = unsafe { std::slice::from_raw_parts(&s.array as *const u32, s.cap as usize) };
-            if s.bits == 0 || s.bits > 32 {
-                Internal::Big { s, a }
-            } else if s.bits == 32 {
-                Internal::Dense { sz: s.sz, a }
-            } else {
-                Internal::Heap { s, a }
+    /// Remove every element of `v`, returning how many of them were
+    /// present.
+    ///
+    /// This decodes the set once for the whole batch, which makes it
+    /// faster than calling `remove` for each element.
+    pub fn remove_slice(&mut self, v: &[u32]) -> usize {
+        let before = self.len();
+        match self.internal_mut() {
+            InternalMut::Empty => (),
+            InternalMut::Stack(t) => {
+                let left: Vec<u32> = t.filter(|x| !v.contains(x)).collect();
+                *self = Self::from_vec_in(left, self.1.clone(), self.2.clone());
             }
+            InternalMut::Dense { sz, base, a } => v.iter().for_each(|&e| { dense_remove(sz, base, a, e); }),
+            InternalMut::Heap { s, a } => v.iter().for_each(|&e| { heap_remove(s, a, e); }),
+            InternalMut::Big { s, a } => v.iter().for_each(|&e| { big_remove(s, a, e); }),
+            InternalMut::Runs { .. } | InternalMut::Chunked { .. } =>
+                v.iter().for_each(|&e| { self.remove(e); }),
         }
+        before - self.len()
     }
 
-    fn internal_mut<'a>(&'a mut self) -> InternalMut<'a> {
-        if self.0 as usize == 0 {
-            InternalMut::Empty
-        } else if self.0 as usize & 3 != 0 {
+    /// Add every element of `other` to this set.
+    ///
+    /// When both sets are dense bitsets this ors their words together,
+    /// using SIMD instructions where the CPU has them.  When both are
+    /// split into chunks it combines them chunk by chunk.
+    pub fn union_with<B: Allocator + Clone, Q: SetPolicy>(&mut self, other: &SetU32<B, Q>) {
+        if let (Internal::Chunked { a: b, .. }, Internal::Chunked { .. }) = (other.internal(), self.internal()) {
+            self.try_union_chunks(chunk_index(b)).unwrap_or_else(|err| err.bail());
+            return;
+        }
+        if let Internal::Dense { base: ob, a: b, .. } = other.internal() {
+            // Empty words at either end need no room.
+            let first = match b.iter().position(|&w| w != 0) {
+                Some(i) => i,
+                None => return,
+            };
+            let last = b.iter().rposition(|&w| w != 0).unwrap();
+            let mn = ob + ((first as u32) << 5) + b[first].trailing_zeros();
+            let mx = ob + ((last as u32) << 5) + 31 - b[last].leading_zeros();
+            if let Internal::Dense { base, a, .. } = self.internal() {
+                // Make room (or change representation) just once each way.
+                if mx >= base && ((mx - base) >> 5) as usize >= a.len() {
+                    self.insert(mx);
+                }
+                if mn < base {
+                    self.insert(mn);
+                }
+            }
+            if let InternalMut::Dense { sz, base, a } = self.internal_mut() {
+                if mn >= base && (((mx - base) >> 5) as usize) < a.len() {
+                    let at = ((ob + ((first as u32) << 5) - base) >> 5) as usize;
+                    crate::simd::or_words(&mut a[at..at + last + 1 - first], &b[first..=last]);
+                    *sz = crate::simd::count_ones_u32(a) as _;
+                    return;
+                }
+            }
+        }
+        if let Internal::Runs { a: b, .. } = other.internal() {
+            for r in runs_of(b).chunks(2) {
+                self.insert_range(r[0]..=r[1]);
+            }
+            return;
+        }
+        let v: Vec<u32> = other.iter().collect();
+        self.insert_slice(&v);
+    }
+
+    /// Remove every element that is not also in `other`.
+    ///
+    /// When both sets are dense bitsets this ands their words together,
+    /// using SIMD instructions where the CPU has them.  When both are
+    /// split into chunks it combines them chunk by chunk.
+    pub fn intersect_with<B: Allocator + Clone, Q: SetPolicy>(&mut self, other: &SetU32<B, Q>) {
+        if let (Internal::Chunked { a: b, .. }, Internal::Chunked { .. }) = (other.internal(), self.internal()) {
+            self.try_intersect_chunks(chunk_index(b)).unwrap_or_else(|err| err.bail());
+            return;
+        }
+        if let Internal::Dense { base: ob, a: b, .. } = other.internal() {
+            if let InternalMut::Dense { sz, base, a } = self.internal_mut() {
+                // Line up the words that cover the same values, and
+                // clear the rest.
+                let skip = core::cmp::min((ob.saturating_sub(base) >> 5) as usize, a.len());
+                let from = core::cmp::min((base.saturating_sub(ob) >> 5) as usize, b.len());
+                let n = core::cmp::min(a.len() - skip, b.len() - from);
+                let (head, rest) = a.split_at_mut(skip);
+                let (mid, tail) = rest.split_at_mut(n);
+                crate::simd::and_words(mid, &b[from..from + n]);
+                for w in head.iter_mut().chain(tail) {
+                    *w = 0;
+                }
+                *sz = crate::simd::count_ones_u32(mid) as _;
+                return;
+            }
+        }
+        let gone: Vec<u32> = self.iter().filter(|&x| !other.contains(x)).collect();
+        self.remove_slice(&gone);
+    }
+
+    /// Insert every value in `range`.
+    ///
+    /// A set stored as runs adds the range as a single run, merging it
+    /// with any runs it overlaps or touches, and a dense bitset fills
+    /// in whole words at once.  Any other set switches (just once) to
+    /// runs or to a dense bitset if that suits the result, and
+    /// otherwise inserts the values one at a time.
+    pub fn insert_range(&mut self, range: impl core::ops::RangeBounds<u32>) {
+        self.try_insert_range(range).unwrap_or_else(|err| err.bail())
+    }
+
+    /// Insert every value in `range`, or return an error if we could
+    /// not allocate the memory needed.  On error the set holds its old
+    /// elements and perhaps some of the range.
+    pub fn try_insert_range(&mut self, range: impl core::ops::RangeBounds<u32>)
+                            -> Result<(), TryReserveError> {
+        let (first, last) = match range_bounds(range) {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        if let Internal::Runs { .. } = self.internal() {
+        } else if runs_pay_off(&self.2.policy(), self.internal(), first, last) {
+            *self = self.try_to_runs(1)?;
+        } else if let Internal::Dense { .. } = self.internal() {
+            // Grow the bitset (or leave it) just once each way.
+            self.try_insert(last)?;
+            self.try_insert(first)?;
+            if let InternalMut::Dense { sz, base, a } = self.internal_mut() {
+                dense_fill(sz, base, a, first, last, true);
+                return Ok(());
+            }
+            return self.try_insert_range(first..=last);
+        } else if dense_pays_off(&self.2.policy(), self.internal(), first, last) {
+            *self = self.try_to_dense(first, last)?;
+            return self.try_insert_range(first..=last);
+        } else {
+            for x in first..=last {
+                self.try_insert(x)?;
+            }
+            return Ok(());
+        }
+        loop {
+            if let InternalMut::Runs { s, a } = self.internal_mut() {
+                if runs_insert(s, a, first, last)?.is_some() {
+                    return Ok(());
+                }
+            }
+            self.try_grow_runs(1)?;
+        }
+    }
+
+    /// Remove every value in `range`.
+    ///
+    /// A set stored as runs trims or splits the runs it overlaps, and a
+    /// dense bitset clears whole words at once.  Any other set removes
+    /// either each value in the range or each of its elements in the
+    /// range, whichever are fewer.
+    pub fn remove_range(&mut self, range: impl core::ops::RangeBounds<u32>) {
+        let (first, last) = match range_bounds(range) {
+            Some(r) => r,
+            None => return,
+        };
+        if let InternalMut::Dense { sz, base, a } = self.internal_mut() {
+            let top = (base as u64 + a.len() as u64*32 - 1) as u32;
+            if first <= top && last >= base {
+                dense_fill(sz, base, a, core::cmp::max(first, base), core::cmp::min(last, top), false);
+            }
+            return;
+        }
+        while let InternalMut::Runs { s, a } = self.internal_mut() {
+            if runs_remove(s, a, first, last).is_some() {
+                self.shrink_runs();
+                return;
+            }
+            // Splitting a run needs room for another.
+            self.try_grow_runs(1).unwrap_or_else(|err| err.bail());
+        }
+        if run_len(first, last) <= self.len() as u128 {
+            for x in first..=last {
+                self.remove(x);
+            }
+        } else {
+            let gone: Vec<u32> = self.iter().filter(|&x| first <= x && x <= last).collect();
+            self.remove_slice(&gone);
+        }
+    }
+
+    /// Whether every value in `range` is in the set.  This is true for
+    /// an empty range.
+    pub fn contains_range(&self, range: impl core::ops::RangeBounds<u32>) -> bool {
+        let (first, last) = match range_bounds(range) {
+            Some(r) => r,
+            None => return true,
+        };
+        match self.internal() {
+            Internal::Runs { a, .. } => runs_contains_range(a, first, last),
+            Internal::Dense { base, a, .. } => dense_contains_range(base, a, first, last),
+            set => run_len(first, last) <= self.len() as u128 && (first..=last).all(|x| set.contains(x)),
+        }
+    }
+
+    /// Make room in a set stored as runs for `extra` more runs, at
+    /// least doubling its room.
+    fn try_grow_runs(&mut self, extra: usize) -> Result<(), TryReserveError> {
+        if let Internal::Runs { a, .. } = self.internal() {
+            let runs = a[0] as usize;
+            self.try_resize_runs(core::cmp::max(2*runs, runs + extra))?;
+        }
+        Ok(())
+    }
+
+    /// Give back the room of a set stored as runs once fewer than a
+    /// quarter of its runs are in use, keeping room for twice as many
+    /// runs as it has.
+    fn shrink_runs(&mut self) {
+        if let Internal::Runs { a, .. } = self.internal() {
+            let runs = a[0] as usize;
+            if runs < runs_room(a.len())/4 {
+                // If we cannot allocate the smaller table we can keep
+                // using the one we have.
+                let _ = self.try_resize_runs(core::cmp::max(2*runs, 1));
+            }
+        }
+    }
+
+    /// Move a set stored as runs into a table with room for `room`
+    /// runs, which must be at least as many as it has.
+    fn try_resize_runs(&mut self, room: usize) -> Result<(), TryReserveError> {
+        if let Internal::Runs { s, a } = self.internal() {
+            let runs = a[0] as usize;
+            let mut new = SetU32::try_with_capacity_and_bits_in(1 + 2*room, RUNS,
+                                                                self.1.clone(), self.2.clone())?;
+            if let InternalMut::Runs { s: ns, a: na } = new.internal_mut() {
+                na[..1 + 2*runs].copy_from_slice(&a[..1 + 2*runs]);
+                ns.sz = s.sz;
+            }
+            *self = new;
+        }
+        Ok(())
+    }
+
+    /// Rebuild the set with `e` added, choosing its representation
+    /// afresh.
+    fn try_rebuild_with(&mut self, e: u32) -> Result<(), TryReserveError> {
+        let v = crate::error::try_collect(self.iter().chain(Some(e)))?;
+        *self = Self::try_from_vec_in(v, self.1.clone(), self.2.clone())?;
+        Ok(())
+    }
+
+    /// This set stored as runs, with room for `extra` more runs.
+    fn try_to_runs(&self, extra: usize) -> Result<Self, TryReserveError> {
+        let mut v = crate::error::try_collect(self.iter())?;
+        v.sort_unstable();
+        let runs = count_sorted_runs(&v);
+        Self::try_runs_from_sorted(&v, runs + extra, self.1.clone(), self.2.clone())
+    }
+
+    /// This set stored as a dense bitset that reaches `first..=last`.
+    fn try_to_dense(&self, first: u32, last: u32) -> Result<Self, TryReserveError> {
+        let mn = core::cmp::min(first, self.iter().min().unwrap_or(first));
+        let mx = core::cmp::max(last, self.iter().max().unwrap_or(last));
+        let mut new = SetU32::try_dense_with_range_in(mn, mx, self.1.clone(), self.2.clone())?;
+        if let InternalMut::Dense { sz, base, a } = new.internal_mut() {
+            for x in self.iter() {
+                dense_insert_in_place(sz, base, a, x);
+            }
+        }
+        Ok(new)
+    }
+
+    /// The values in `0..universe` that are not in the set, whose
+    /// elements must all be in that range.
+    ///
+    /// Runs of elements, or few enough of them, leave runs of gaps that
+    /// are inserted as ranges.  Otherwise a large complement is built as a dense
+    /// bitset by flipping words, and a small one from its sorted
+    /// values.
+    pub(crate) fn try_complement(&self, universe: u32) -> Result<Self, TryReserveError> {
+        debug_assert!(self.iter().max().iter().all(|&x| x < universe));
+        let p = self.2.policy();
+        let out = universe - self.len() as u32;
+        let mut new = SetU32(core::ptr::null_mut(), self.1.clone(), self.2.clone());
+        if out == 0 {
+            return Ok(new);
+        }
+        let budget = runs_budget(SetU32::estimate_table_mem(out as usize, universe - 1));
+        let is_runs = matches!(self.internal(), Internal::Runs { .. });
+        if is_runs || p.allows_runs() && self.internal().count_runs(budget) < budget {
+            let mut next = 0;
+            let mut skip = |first: u32, last: u32| {
+                if first > next {
+                    new.try_insert_range(next..first)?;
+                }
+                next = last + 1;
+                Ok(())
+            };
+            match self.internal() {
+                Internal::Runs { a, .. } => runs_of(a).chunks(2).try_for_each(|r| skip(r[0], r[1]))?,
+                _ => {
+                    let mut v = crate::error::try_collect(self.iter())?;
+                    v.sort_unstable();
+                    v.into_iter().try_for_each(|x| skip(x, x))?
+                }
+            }
+            if next < universe {
+                new.try_insert_range(next..universe)?;
+            }
+            return Ok(new);
+        }
+        let words = (universe as u64 + 31) >> 5;
+        // The bits of the set's ith word, if it is a bitset.
+        let word = |i: u64| match self.internal() {
+            Internal::Dense { base, a, .. } => (i*32).checked_sub(base as u64)
+                .and_then(|j| a.get((j/32) as usize)).cloned().unwrap_or(0),
+            _ => 0,
+        };
+        let mask = |i: u64| if i + 1 == words && universe & 31 != 0 {
+            (1 << (universe & 31)) - 1
+        } else {
+            !0
+        };
+        if p.use_dense(out as u64, universe as u64, out > universe >> 5) {
+            new = SetU32::try_dense_in(0, words as u32, self.1.clone(), self.2.clone())?;
+            if let InternalMut::Dense { sz, a, .. } = new.internal_mut() {
+                for (i, w) in a.iter_mut().enumerate() {
+                    *w = !word(i as u64) & mask(i as u64);
+                }
+                if !matches!(self.internal(), Internal::Dense { .. }) {
+                    for x in self.iter() {
+                        a[(x >> 5) as usize] &= !(1 << (x & 31));
+                    }
+                }
+                *sz = out;
+            }
+            return Ok(new);
+        }
+        let v = if let Internal::Dense { .. } = self.internal() {
+            crate::error::try_collect((0..words).flat_map(|i| {
+                let w = !word(i) & mask(i);
+                (0..32).filter(move |b| w >> b & 1 != 0).map(move |b| (i*32) as u32 + b)
+            }))?
+        } else {
+            let mut members = self.sorted().peekable();
+            crate::error::try_collect((0..universe).filter(|&x| {
+                let member = members.peek() == Some(&x);
+                if member {
+                    members.next();
+                }
+                !member
+            }))?
+        };
+        SetU32::try_from_sorted_in(&v, self.1.clone(), self.2.clone())
+    }
+
+    /// The elements in increasing order.
+    fn sorted(&self) -> impl Iterator<Item = u32> {
+        let mut v: Vec<u32> = self.iter().collect();
+        v.sort_unstable();
+        v.into_iter()
+    }
+
+    /// Insert `e` into a set split into chunks.
+    fn try_chunked_insert(&mut self, e: u32) -> Result<bool, TryReserveError> {
+        let alloc = self.1.clone();
+        if let InternalMut::Chunked { s, a } = self.internal_mut() {
+            if let Ok(i) = chunk_find(chunk_index(a), e >> 16) {
+                let new = chunks::try_insert(&alloc, chunk_at_mut(a, i), e as u16)?;
+                s.sz += new as u32;
+                return Ok(new);
+            }
+        }
+        self.try_add_chunk(chunks::try_new(&alloc, e >> 16, 1, Some(e as u16).into_iter())?)?;
+        Ok(true)
+    }
+
+    /// Remove `e` from a set split into chunks.
+    fn try_chunked_remove(&mut self, e: u32) -> Result<bool, TryReserveError> {
+        let alloc = self.1.clone();
+        if let InternalMut::Chunked { s, a } = self.internal_mut() {
+            let i = match chunk_find(chunk_index(a), e >> 16) {
+                Ok(i) => i,
+                Err(_) => return Ok(false),
+            };
+            if !chunks::try_remove(&alloc, chunk_at_mut(a, i), e as u16)? {
+                return Ok(false);
+            }
+            s.sz -= 1;
+            if chunks::len(chunk_at(a, i)) == 0 {
+                let n = a[0] as usize;
+                a.copy_within(1 + ENTRY*(i + 1)..1 + ENTRY*n, 1 + ENTRY*i);
+                a[0] -= 1;
+            }
+        }
+        self.try_unchunk_if_small()?;
+        Ok(true)
+    }
+
+    /// Add the chunk `e` to a set split into chunks, which has no chunk
+    /// with the same key.  On error `e` is freed.
+    fn try_add_chunk(&mut self, e: [u32; ENTRY]) -> Result<(), TryReserveError> {
+        if let Internal::Chunked { a, .. } = self.internal() {
+            if 1 + ENTRY*(a[0] as usize + 1) > a.len() {
+                if let Err(err) = unsafe { self.try_grow_chunks() } {
+                    unsafe { chunks::free(&self.1, &e) };
+                    return Err(err);
+                }
+            }
+        }
+        if let InternalMut::Chunked { s, a } = self.internal_mut() {
+            let n = a[0] as usize;
+            let i = chunk_find(chunk_index(a), chunks::key(&e)).unwrap_err();
+            a.copy_within(1 + ENTRY*i..1 + ENTRY*n, 1 + ENTRY*(i + 1));
+            chunk_at_mut(a, i).copy_from_slice(&e);
+            a[0] += 1;
+            s.sz += chunks::len(&e) as u32;
+        }
+        Ok(())
+    }
+
+    /// This requires that we currently be split into chunks!  It
+    /// doubles the room in the index.
+    unsafe fn try_grow_chunks(&mut self) -> Result<(), TryReserveError> {
+        let ptr = self.0;
+        let oldcap = (*ptr).cap as usize;
+        let cap = core::cmp::max(2*oldcap - 1, 1 + ENTRY);
+        let new_layout = try_layout_for_capacity(cap)?;
+        self.0 = match self.1.grow_zeroed(core::ptr::NonNull::new_unchecked(ptr as *mut u8),
+                                          layout_for_capacity(oldcap),
+                                          new_layout) {
+            Ok(ptr) => ptr.cast::<S>().as_ptr(),
+            Err(_) => return Err(TryReserveError::alloc_error(new_layout)),
+        };
+        (*self.0).cap = cap as u32;
+        Ok(())
+    }
+
+    /// Store a set split into chunks whole again, once it has shrunk
+    /// well below the size that calls for chunks.
+    fn try_unchunk_if_small(&mut self) -> Result<(), TryReserveError> {
+        if let Internal::Chunked { s, .. } = self.internal() {
+            if (s.sz as usize) < CHUNKED_MIN/2 {
+                let v = crate::error::try_collect(self.iter())?;
+                *self = Self::try_from_sorted_in(&v, self.1.clone(), self.2.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the chunks in `b`, the index of another set, to a set split
+    /// into chunks.
+    fn try_union_chunks(&mut self, b: &[u32]) -> Result<(), TryReserveError> {
+        let alloc = self.1.clone();
+        for y in b.chunks(ENTRY) {
+            if let InternalMut::Chunked { s, a } = self.internal_mut() {
+                if let Ok(i) = chunk_find(chunk_index(a), chunks::key(y)) {
+                    let x = chunk_at_mut(a, i);
+                    let before = chunks::len(x);
+                    chunks::try_union(&alloc, x, y)?;
+                    s.sz += (chunks::len(x) - before) as u32;
+                    continue;
+                }
+            }
+            self.try_add_chunk(chunks::try_clone(&alloc, y)?)?;
+        }
+        Ok(())
+    }
+
+    /// Keep only the elements of a set split into chunks that are also
+    /// in the chunks in `b`, the index of another set.
+    fn try_intersect_chunks(&mut self, b: &[u32]) -> Result<(), TryReserveError> {
+        let alloc = self.1.clone();
+        if let InternalMut::Chunked { s, a } = self.internal_mut() {
+            let n = a[0] as usize;
+            let mut res = Ok(());
+            for x in a[1..1 + ENTRY*n].chunks_mut(ENTRY) {
+                match chunk_find(b, chunks::key(x)) {
+                    Ok(j) => res = chunks::try_intersect(&alloc, x, &b[ENTRY*j..][..ENTRY]),
+                    Err(_) => {
+                        unsafe { chunks::free(&alloc, x) };
+                        x[1] = 0;
+                    }
+                }
+                if res.is_err() {
+                    break;
+                }
+            }
+            // Drop the chunks left empty, even if we stopped early.
+            let mut kept = 0;
+            for i in 0..n {
+                if chunks::len(chunk_at(a, i)) > 0 {
+                    a.copy_within(1 + ENTRY*i..1 + ENTRY*(i + 1), 1 + ENTRY*kept);
+                    kept += 1;
+                }
+            }
+            a[0] = kept as u32;
+            s.sz = chunk_index(a).chunks(ENTRY).map(chunks::len).sum::<usize>() as u32;
+            res?;
+        }
+        self.try_unchunk_if_small()
+    }
+
+    /// Iterate over
+    #[inline]
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        self.private_iter()
+    }
+    fn private_iter<'a>(&'a self) -> Iter<'a> {
+        Iter(self.internal().iter())
+    }
+    /// A cursor at the first element that is at least `x`, which then
+    /// moves through the set in increasing order and can
+    /// [`seek`](Cursor::seek) ahead.
+    ///
+    /// Small sets, bitsets and runs are walked in place, but a set
+    /// stored in a hash table is copied and sorted first, which takes
+    /// O(n log n) time and O(n) memory for each cursor.
+    pub fn cursor_at<'a>(&'a self, x: u32) -> Cursor<'a> {
+        let mut rest = match self.internal() {
+            Internal::Stack(t) => CursorRest::Tiny(t),
+            Internal::Dense { base, a, .. } => {
+                CursorRest::Dense { base, a, word: 0, bits: a.first().cloned().unwrap_or(0) }
+            }
+            Internal::Runs { a, .. } => {
+                let runs = runs_of(a);
+                CursorRest::Runs { runs, at: 0, next: runs.first().cloned().unwrap_or(0) }
+            }
+            Internal::Chunked { a, .. } => CursorRest::Chunked(ChunksCursor::new(chunk_index(a))),
+            _ => {
+                let mut v: Vec<u32> = self.iter().collect();
+                v.sort_unstable();
+                CursorRest::Sorted { v, at: 0 }
+            }
+        };
+        let head = rest.seek(x);
+        Cursor { head, rest }
+    }
+    /// Clears the set, returning all elements in an iterator.
+    #[inline]
+    pub fn drain<'a>(&'a mut self) -> Drain<'a, A, P> {
+        let empty = SetU32(0 as *mut S, self.1.clone(), self.2.clone());
+        let set: SetU32<A, P> = core::mem::replace(self, empty);
+        Drain {
+            iter: set.into_iter(),
+            set: core::marker::PhantomData,
+        }
+    }
+
+    fn internal<'a>(&'a self) -> Internal<'a> {
+        if self.0 as usize == 0 {
+            Internal::Empty
+        } else if self.0 as usize & 3 != 0 {
+            Internal::Stack(Tiny::from_usize(self.0 as usize))
+        } else {
+            let s = unsafe { &*self.0 };
+            let a = unsafe { core::slice::from_raw_parts(&s.array as *const u32, s.cap as usize) };
+            if s.bits == CHUNKED {
+                Internal::Chunked { s, a }
+            } else if s.bits == 0 || s.bits > 32 {
+                Internal::Big { s, a }
+            } else if s.bits == RUNS {
+                Internal::Runs { s, a }
+            } else if s.bits == 32 {
+                // The first word holds the value of the first bit.
+                Internal::Dense { sz: s.sz, base: a[0], a: &a[1..] }
+            } else {
+                Internal::Heap { s, a }
+            }
+        }
+    }
+
+    fn internal_mut<'a>(&'a mut self) -> InternalMut<'a> {
+        if self.0 as usize == 0 {
+            InternalMut::Empty
+        } else if self.0 as usize & 3 != 0 {
             InternalMut::Stack(Tiny::from_usize(self.0 as usize))
         } else {
             let s = unsafe { &mut *self.0 };
-            let a = unsafe { std::slice::from_raw_parts_mut(&mut s.array as *mut u32, s.cap as usize) };
-            if s.bits == 0 || s.bits > 32 {
+            let a = unsafe { core::slice::from_raw_parts_mut(&mut s.array as *mut u32, s.cap as usize) };
+            if s.bits == CHUNKED {
+                InternalMut::Chunked { s, a }
+            } else if s.bits == 0 || s.bits > 32 {
                 InternalMut::Big { s, a }
+            } else if s.bits == RUNS {
+                InternalMut::Runs { s, a }
             } else if s.bits == 32 {
-                InternalMut::Dense { sz: &mut s.sz, a }
+                let (base, a) = a.split_first_mut().unwrap();
+                InternalMut::Dense { sz: &mut s.sz, base: *base, a }
             } else {
                 InternalMut::Heap { s, a }
             }
@@ -1243,90 +2770,1152 @@ impl SetU32 {
 
 impl Default for SetU32 {
     fn default() -> Self {
-        SetU32(0 as *mut S)
+        SetU32::new()
     }
 }
 
-impl std::iter::FromIterator<u32> for SetU32 {
+impl core::iter::FromIterator<u32> for SetU32 {
     fn from_iter<T>(iter: T) -> Self
         where
         T: IntoIterator<Item = u32>
     {
-        let v: Vec<_> = iter.into_iter().collect();
-        if let Some(mx) = v.iter().cloned().max() {
-            if let Some(t) = Tiny::new(v.clone()) {
-                SetU32(t.to_usize() as *mut S)
-            } else {
-                if v.len() as u32 > mx >> 4 {
-                    // This should be stored in a dense bitset.
-                    let mut s = SetU32::with_capacity_and_max(v.len(), mx);
-                    for value in v.into_iter() {
-                        s.insert(value);
+        SetU32::from_vec_in(iter.into_iter().collect(), Global, DefaultPolicy)
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> Extend<u32> for SetU32<A, P> {
+    /// Adds the elements in one batch, as with [`SetU32::insert_slice`].
+    fn extend<I: IntoIterator<Item = u32>>(&mut self, iter: I) {
+        let v: Vec<u32> = iter.into_iter().collect();
+        self.insert_slice(&v);
+    }
+}
+
+impl<'a, A: Allocator + Clone, P: SetPolicy> Extend<&'a u32> for SetU32<A, P> {
+    fn extend<I: IntoIterator<Item = &'a u32>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().cloned())
+    }
+}
+
+impl<'a, A: Allocator + Clone, P: SetPolicy> IntoIterator for &'a SetU32<A, P> {
+    type Item = u32;
+    type IntoIter = Iter<'a>;
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Sets are equal when they have the same elements, whatever their
+/// representation, allocator or policy.
+impl<A, P, B, Q> PartialEq<SetU32<B, Q>> for SetU32<A, P>
+    where A: Allocator + Clone, P: SetPolicy, B: Allocator + Clone, Q: SetPolicy
+{
+    fn eq(&self, other: &SetU32<B, Q>) -> bool {
+        self.len() == other.len() && other.iter().all(|x| self.contains(x))
+    }
+}
+impl<A: Allocator + Clone, P: SetPolicy> Eq for SetU32<A, P> {}
+
+/// Hashes the elements in whatever order they are stored, so that
+/// equal sets hash alike whatever their representation.
+impl<A: Allocator + Clone, P: SetPolicy> core::hash::Hash for SetU32<A, P> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        crate::sets::hash_unordered(state, self.iter().map(|x| x as u64));
+    }
+}
+
+/// Sets are ordered by comparing their elements in increasing order,
+/// as a `BTreeSet` is.
+impl<A: Allocator + Clone, P: SetPolicy> PartialOrd for SetU32<A, P> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<A: Allocator + Clone, P: SetPolicy> Ord for SetU32<A, P> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.cursor_at(0).cmp(other.cursor_at(0))
+    }
+}
+
+/// Prints the elements in increasing order, as `{1, 2, 3}`.
+impl<A: Allocator + Clone, P: SetPolicy> core::fmt::Display for SetU32<A, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        crate::sets::fmt_sorted(f, self.cursor_at(0))
+    }
+}
+
+impl<const N: usize> From<[u32; N]> for SetU32 {
+    fn from(a: [u32; N]) -> Self {
+        SetU32::from_vec_in(a.to_vec(), Global, DefaultPolicy)
+    }
+}
+
+impl From<Vec<u32>> for SetU32 {
+    fn from(v: Vec<u32>) -> Self {
+        SetU32::from_vec_in(v, Global, DefaultPolicy)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S> From<std::collections::HashSet<u32, S>> for SetU32 {
+    fn from(s: std::collections::HashSet<u32, S>) -> Self {
+        SetU32::from_vec_in(s.into_iter().collect(), Global, DefaultPolicy)
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> From<SetU32<A, P>> for alloc::collections::BTreeSet<u32> {
+    fn from(s: SetU32<A, P>) -> Self {
+        s.into_iter().collect()
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> core::ops::BitOr<&SetU32<A, P>> for &SetU32<A, P> {
+    type Output = SetU32<A, P>;
+    /// Returns the union of `self` and `rhs` as a new set.
+    fn bitor(self, rhs: &SetU32<A, P>) -> SetU32<A, P> {
+        let mut s = self.clone();
+        s.union_with(rhs);
+        s
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> core::ops::BitAnd<&SetU32<A, P>> for &SetU32<A, P> {
+    type Output = SetU32<A, P>;
+    /// Returns the intersection of `self` and `rhs` as a new set.
+    fn bitand(self, rhs: &SetU32<A, P>) -> SetU32<A, P> {
+        let mut s = self.clone();
+        s.intersect_with(rhs);
+        s
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> core::ops::Sub<&SetU32<A, P>> for &SetU32<A, P> {
+    type Output = SetU32<A, P>;
+    /// Returns the elements of `self` that are not in `rhs` as a new set.
+    fn sub(self, rhs: &SetU32<A, P>) -> SetU32<A, P> {
+        let mut s = self.clone();
+        s.remove_slice(&self.common(rhs));
+        s
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> core::ops::BitXor<&SetU32<A, P>> for &SetU32<A, P> {
+    type Output = SetU32<A, P>;
+    /// Returns the elements in exactly one of `self` and `rhs` as a new set.
+    fn bitxor(self, rhs: &SetU32<A, P>) -> SetU32<A, P> {
+        let mut s = self.clone();
+        s.remove_slice(&self.common(rhs));
+        let extra: Vec<u32> = rhs.iter().filter(|&x| !self.contains(x)).collect();
+        s.insert_slice(&extra);
+        s
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> SetU32<A, P> {
+    /// The elements of both sets, found by looking through the smaller.
+    fn common(&self, other: &Self) -> Vec<u32> {
+        if self.len() <= other.len() {
+            self.iter().filter(|&x| other.contains(x)).collect()
+        } else {
+            other.iter().filter(|&x| self.contains(x)).collect()
+        }
+    }
+}
+
+/// Each thread collects its share of the elements into a set of its
+/// own, and these are then merged pairwise with
+/// [`union_with`](SetU32::union_with), always into the larger set, so
+/// bitsets, runs and chunks are combined wholesale and otherwise
+/// only the smaller set's elements are inserted.  A merge that makes
+/// the set big enough to split into chunks may first move it to
+/// chunks.
+#[cfg(feature = "rayon")]
+impl rayon::iter::FromParallelIterator<u32> for SetU32 {
+    fn from_par_iter<I>(par_iter: I) -> Self
+        where I: rayon::iter::IntoParallelIterator<Item = u32>
+    {
+        crate::par::collect(par_iter, |v| SetU32::from_vec_in(v, Global, DefaultPolicy),
+                            SetU32::len, SetU32::par_union)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl SetU32 {
+    /// Add the elements of `other`, for merging the sets that threads
+    /// have collected.  Neither set may be big enough to be split into
+    /// chunks while their union is, so when their bounds say the
+    /// union is (judged as [`estimate_mem`](SetU32::estimate_mem)
+    /// does) this set moves to chunks before the merge.
+    fn par_union(&mut self, other: &SetU32) {
+        let len = self.len() + other.len();
+        if len >= CHUNKED_MIN && !matches!(self.internal(), Internal::Chunked { .. }) {
+            if let (Some((a0, a1)), Some((b0, b1))) = (self.sorted_bounds(), other.sorted_bounds()) {
+                let (mn, mx) = (core::cmp::min(a0, b0), core::cmp::max(a1, b1));
+                let keys = ((mx >> 16) - (mn >> 16)) as usize + 1;
+                let len = core::cmp::min(len, (mx - mn) as usize + 1);
+                if chunked_mem((0..keys).map(|_| len/keys)) < SetU32::estimate_table_mem(len, mx - mn) {
+                    let v: Vec<u32> = self.iter().collect();
+                    *self = SetU32::try_chunked_from_sorted(&v, Global, DefaultPolicy)
+                        .unwrap_or_else(|err| err.bail());
+                }
+            }
+        }
+        self.union_with(other);
+    }
+    /// Bounds on the elements of a set that iterates in order, read
+    /// without looking at the elements.
+    fn sorted_bounds(&self) -> Option<(u32, u32)> {
+        match self.internal() {
+            Internal::Dense { base, a, .. } =>
+                Some((base, (base as u64 + 32*a.len() as u64 - 1) as u32)),
+            Internal::Runs { a, .. } => {
+                let r = runs_of(a);
+                Some((*r.first()?, *r.last()?))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<A: Allocator + Clone, P: SetPolicy> rayon::iter::ParallelExtend<u32> for SetU32<A, P> {
+    fn par_extend<I>(&mut self, par_iter: I)
+        where I: rayon::iter::IntoParallelIterator<Item = u32>
+    {
+        let other: SetU32 = rayon::iter::FromParallelIterator::from_par_iter(par_iter);
+        self.union_with(&other);
+    }
+}
+
+impl<A: Allocator + Clone, P: SetPolicy> SetU32<A, P> {
+    fn from_vec_in(v: Vec<u32>, alloc: A, policy: P) -> Self {
+        Self::try_from_vec_in(v, alloc, policy).unwrap_or_else(|err| err.bail())
+    }
+
+    fn try_from_vec_in(mut v: Vec<u32>, alloc: A, policy: P) -> Result<Self, TryReserveError> {
+        // An unstable sort, because a stable one would allocate.
+        v.sort_unstable();
+        v.dedup();
+        Self::try_from_sorted_in(&v, alloc, policy)
+    }
+
+    /// Build a set from sorted, deduplicated elements, choosing the
+    /// representation up front and filling in the table directly
+    /// rather than inserting one element at a time.
+    fn try_from_sorted_in(v: &[u32], alloc: A, policy: P) -> Result<Self, TryReserveError> {
+        debug_assert!(v.windows(2).all(|w| w[0] < w[1]),
+                      "input must be sorted and deduplicated");
+        let p = policy.policy();
+        let (mn, mx) = match (v.first(), v.last()) {
+            (Some(&mn), Some(&mx)) => (mn, mx),
+            _ => return Ok(SetU32(0 as *mut S, alloc, policy)),
+        };
+        if let Some(t) = Tiny::new_sorted_deduped(v).filter(|_| p.allows_tiny()) {
+            return Ok(SetU32(t.to_usize() as *mut S, alloc, policy));
+        }
+        if p.allows_runs() {
+            let runs = count_sorted_runs(v);
+            if runs <= runs_budget(SetU32::estimate_table_mem(v.len(), mx - mn)) {
+                return SetU32::try_runs_from_sorted(v, runs, alloc, policy);
+            }
+        }
+        if p.allows_chunks() && v.len() >= CHUNKED_MIN
+            && chunked_mem(chunk_groups(v).map(|g| g.len()))
+               < SetU32::estimate_table_mem(v.len(), mx - mn) {
+            return SetU32::try_chunked_from_sorted(v, alloc, policy);
+        }
+        if p.use_dense(v.len() as u64, (mx - mn) as u64, v.len() as u32 > (mx - mn) >> 4) {
+            // This should be stored in a dense bitset.
+            let mut new = SetU32::try_dense_with_range_in(mn, mx, alloc, policy)?;
+            if let InternalMut::Dense { sz, base, a } = new.internal_mut() {
+                for &x in v {
+                    let bit = 1 << (x & 31);
+                    let word = &mut a[((x - base) >> 5) as usize];
+                    if *word & bit == 0 {
+                        *word |= bit;
+                        *sz += 1;
                     }
-                    return s;
                 }
-                let bits = compute_array_bits(mx);
-                if bits == 0 {
-                    let mut s = SetU32::with_capacity_and_bits(v.len(), bits);
-                    for value in v.into_iter() {
-                        s.insert(value);
+            }
+            return Ok(new);
+        }
+        let bits = if p.allows_heap() { compute_array_bits(mx) } else { 0 };
+        if bits == 0 {
+            // Leave the same slack as a table of keys, below.
+            let cap = p.capacity_for(v.len()).unwrap_or((v.len()+1)*11/10);
+            let mut new = SetU32::try_with_capacity_and_bits_in(cap, bits, alloc, policy)?;
+            if let InternalMut::Big { s, a } = new.internal_mut() {
+                // Zero is stored as the sentinel, so the sentinel must
+                // not be an element.
+                while v.binary_search(&s.bits).is_ok() {
+                    s.bits = crate::rand::rand32(s.bits as u64);
+                    while s.bits <= CHUNKED {
+                        s.bits = crate::rand::rand32(s.bits as u64);
                     }
-                    s
+                }
+                let n = a.len();
+                let sentinel = s.bits;
+                let mut entries = crate::error::try_collect(v.iter().map(|&x| {
+                    let x = if x == 0 { sentinel } else { x };
+                    (home32(x, n), x)
+                }))?;
+                p_place_all(a, &mut entries);
+                s.sz = v.len() as u32;
+            }
+            Ok(new)
+        } else {
+            // v is sorted, so equal keys are adjacent.
+            let keys = v.iter().map(|&x| x/bits).dedup().count();
+            let cap = p.capacity_for(keys).unwrap_or((keys+1)*11/10);
+            let mut new = SetU32::try_with_capacity_and_bits_in(cap, bits, alloc, policy)?;
+            if let InternalMut::Heap { s, a } = new.internal_mut() {
+                let n = a.len();
+                let mut i = 0;
+                let words = core::iter::from_fn(|| {
+                    // Gather every element sharing this key into one
+                    // word before placing it.
+                    let (key, _) = split_u32(*v.get(i)?, bits);
+                    let mut word = key << bits;
+                    while let Some(&x) = v.get(i) {
+                        let (k, offset) = split_u32(x, bits);
+                        if k != key {
+                            break;
+                        }
+                        word |= 1 << offset;
+                        i += 1;
+                    }
+                    Some((home32(key, n), word))
+                });
+                let mut entries = crate::error::try_collect(words)?;
+                p_place_all(a, &mut entries);
+                s.sz = v.len() as u32;
+            }
+            Ok(new)
+        }
+    }
+
+    /// Store sorted, deduplicated elements as runs, with room for
+    /// `room` runs.
+    fn try_runs_from_sorted(v: &[u32], room: usize, alloc: A, policy: P)
+                            -> Result<Self, TryReserveError> {
+        let mut new = SetU32::try_with_capacity_and_bits_in(1 + 2*room, RUNS, alloc, policy)?;
+        if let InternalMut::Runs { s, a } = new.internal_mut() {
+            let mut n = 0;
+            for &x in v {
+                if n > 0 && a[2*n] + 1 == x {
+                    a[2*n] = x;
+                } else {
+                    a[2*n + 1] = x;
+                    a[2*n + 2] = x;
+                    n += 1;
+                }
+            }
+            a[0] = n as u32;
+            s.sz = v.len() as u32;
+        }
+        Ok(new)
+    }
+
+    /// Split sorted, deduplicated elements into chunks.
+    fn try_chunked_from_sorted(v: &[u32], alloc: A, policy: P) -> Result<Self, TryReserveError> {
+        let n = chunk_groups(v).count();
+        let mut new = SetU32::try_with_capacity_and_bits_in(1 + ENTRY*n, CHUNKED, alloc, policy)?;
+        let alloc = new.1.clone();
+        if let InternalMut::Chunked { s, a } = new.internal_mut() {
+            for (i, g) in chunk_groups(v).enumerate() {
+                let e = chunks::try_new(&alloc, g[0] >> 16, g.len(), g.iter().map(|&x| x as u16))?;
+                chunk_at_mut(a, i).copy_from_slice(&e);
+                // Count each chunk as it is filled in, so that on error
+                // dropping the set frees just those.
+                a[0] += 1;
+                s.sz += g.len() as u32;
+            }
+        }
+        Ok(new)
+    }
+
+    fn try_from_iter_with<I: IntoIterator<Item = u32>>(iter: I, alloc: A, policy: P)
+                                                       -> Result<Self, TryReserveError> {
+        Self::try_from_vec_in(crate::error::try_collect(iter.into_iter())?, alloc, policy)
+    }
+}
+
+/// Makes a [`SetU32`] that follows a [`Policy`] of your choosing.
+///
+/// ```
+/// use tinyset::{Repr, SetU32Builder};
+/// let mut s = SetU32Builder::new().force_big().build();
+/// s.insert(3);
+/// assert!(matches!(s.repr(), Repr::Big { .. }));
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SetU32Builder {
+    policy: Policy,
+}
+
+impl SetU32Builder {
+    /// A builder with the default policy.
+    pub fn new() -> Self {
+        SetU32Builder { policy: Policy::DEFAULT }
+    }
+
+    policy_setters!();
+
+    /// An empty set that follows the policy.
+    pub fn build(self) -> SetU32<Global, Policy> {
+        SetU32(0 as *mut S, Global, self.policy)
+    }
+    /// An empty set that follows the policy and will allocate from
+    /// `alloc`.
+    pub fn build_in<A: Allocator + Clone>(self, alloc: A) -> SetU32<A, Policy> {
+        SetU32(0 as *mut S, alloc, self.policy)
+    }
+    /// A set holding the elements of `iter` that follows the policy.
+    pub fn build_from_iter<I: IntoIterator<Item = u32>>(self, iter: I) -> SetU32<Global, Policy> {
+        SetU32::from_vec_in(iter.into_iter().collect(), Global, self.policy)
+    }
+}
+
+#[cfg(test)]
+fn test_a_collect(v: Vec<u32>) {
+    let s: SetU32 = v.iter().cloned().collect();
+    let vv: Vec<_> = s.iter().collect();
+    let ss: SetU32 = vv.iter().cloned().collect();
+    let vvv: Vec<_> = ss.iter().collect();
+    assert_eq!(vv, vvv);
+}
+
+#[test]
+fn test_collect() {
+    test_a_collect(vec![]);
+    test_a_collect(vec![0]);
+    test_a_collect(vec![0,1<<29]);
+    test_a_collect(vec![0,1<<30,1<<30]);
+    test_a_collect((0..1024).collect());
+}
+
+#[test]
+fn test_from_sorted() {
+    let cases: Vec<Vec<u32>> = vec![
+        vec![],
+        vec![0],
+        vec![1, 2, 3],
+        (0..1000).collect(),
+        (0..100).map(|x| x*1000).collect(),
+        (0..100).map(|x| x << 25).collect(),
+        (1..1000).map(|x| x*x*x).collect(),
+    ];
+    for v in cases {
+        let s = SetU32::from_sorted_unique(&v);
+        assert_eq!(s.len(), v.len());
+        let mut got: Vec<_> = s.iter().collect();
+        got.sort();
+        assert_eq!(got, v);
+        assert_eq!(SetU32::from_sorted_iter(v.iter().cloned()).repr(), s.repr());
+
+        // The set we built must behave like one made by inserting.
+        let mut s = s;
+        for &x in &v {
+            assert!(s.contains(x));
+            assert!(!s.insert(x));
+        }
+        for &x in &v {
+            assert!(s.remove(x));
+        }
+        assert_eq!(s.len(), 0);
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn test_from_sorted_unsorted() {
+    SetU32::from_sorted_unique(&[3, 1, 2]);
+}
+
+#[test]
+fn test_from_sorted_big() {
+    // Clustered ids that go in a table of single elements, which must
+    // be left with room to spare rather than filled to the brim.
+    let v: Vec<u32> = (0..20_000u32).map(|i| (i/1000)*150_001 + (i%1000)*7).collect();
+    let big_cap = |s: &SetU32<Global, Policy>| match s.repr() {
+        Repr::Big { cap } => cap,
+        r => panic!("not big: {:?}", r),
+    };
+    let s = SetU32::from_sorted_unique_in(&v, Global, Policy::DEFAULT.heap(false).chunks(false).runs(false).dense(false));
+    assert!(big_cap(&s) > v.len() + v.len()/20);
+    assert!(v.iter().all(|&x| s.contains(x) && !s.contains(x + 1)));
+    assert_eq!(s, v.iter().cloned().collect::<SetU32>());
+    // A policy's load factor is followed.
+    let s = SetU32::from_sorted_unique_in(&v, Global, Policy::DEFAULT.heap(false).chunks(false).runs(false).dense(false).max_load(0.5));
+    assert!(big_cap(&s) >= 2*v.len());
+    assert!(v.iter().all(|&x| s.contains(x)));
+    assert_eq!(s.len(), v.len());
+}
+
+#[test]
+fn test_batch() {
+    let batches: Vec<Vec<u32>> = vec![
+        vec![],
+        vec![5, 3, 5],
+        (0..1000).collect(),
+        (0..500).map(|x| x*977 % 1000).collect(),
+        (0..300).map(|x| x*x*x).collect(),
+        (0..100).map(|x| x << 25).collect(),
+    ];
+    let mut s = SetU32::new();
+    let mut t = SetU32::new();
+    for b in &batches {
+        let added = b.iter().filter(|&&x| t.insert(x)).count();
+        assert_eq!(s.insert_slice(b), added);
+        assert_eq!(s.len(), t.len());
+        let probe: Vec<u32> = b.iter().map(|&x| x + 1).chain(b.iter().cloned()).collect();
+        let mut found = vec![false; probe.len()];
+        s.contains_many(&probe, &mut found);
+        for (&x, &f) in probe.iter().zip(&found) {
+            assert_eq!(f, t.contains(x));
+        }
+    }
+    let gone: Vec<u32> = (0..2000).map(|x| x*3).collect();
+    let removed = gone.iter().filter(|&&x| t.remove(x)).count();
+    assert_eq!(s.remove_slice(&gone), removed);
+    let mut got: Vec<_> = s.iter().collect();
+    let mut want: Vec<_> = t.iter().collect();
+    got.sort();
+    want.sort();
+    assert_eq!(got, want);
+
+    let mut s: SetU32 = [1, 2].iter().cloned().collect();
+    assert_eq!(s.remove_slice(&[2, 7]), 1);
+    assert_eq!(s.insert_slice(&[1, 9]), 1);
+    assert_eq!(s.repr(), Repr::Tiny);
+}
+
+#[test]
+fn test_union_intersect() {
+    let sets: Vec<Vec<u32>> = vec![
+        vec![],
+        vec![1, 5],
+        (0..1000).filter(|x| x % 3 == 0).collect(),
+        (500..3000).collect(),
+        (0..100).map(|x| x*1000).collect(),
+        (0..100).map(|x| x << 25).collect(),
+    ];
+    for x in &sets {
+        for y in &sets {
+            let a: SetU32 = x.iter().cloned().collect();
+            let b: SetU32 = y.iter().cloned().collect();
+            let mut union: Vec<u32> = x.iter().chain(y).cloned().collect();
+            union.sort();
+            union.dedup();
+            let inter: Vec<u32> = x.iter().cloned().filter(|v| y.contains(v)).collect();
+
+            let mut s = a.clone();
+            s.union_with(&b);
+            let mut got: Vec<_> = s.iter().collect();
+            got.sort();
+            assert_eq!(got, union);
+            assert_eq!(s.len(), union.len());
+
+            let mut s = a.clone();
+            s.intersect_with(&b);
+            let mut got: Vec<_> = s.iter().collect();
+            got.sort();
+            assert_eq!(got, inter);
+            assert_eq!(s.len(), inter.len());
+        }
+    }
+}
+
+#[test]
+fn test_runs() {
+    let is_runs = |s: &SetU32| match s.repr() { Repr::Runs { .. } => true, _ => false };
+    let mut s: SetU32 = (1_000..2_000_000).collect();
+    assert_eq!(s.repr(), Repr::Runs { runs: 1, cap: 1 });
+    assert_eq!(s.len(), 1_999_000);
+    let r = s.memory_report();
+    assert_eq!((r.table, r.slack), (8, 0));
+    assert!(s.contains(1_000) && s.contains(1_999_999));
+    assert!(!s.contains(999) && !s.contains(2_000_000));
+    assert!(s.remove(5_000));
+    assert_eq!(s.repr(), Repr::Runs { runs: 2, cap: 2 });
+    assert!(!s.contains(5_000));
+    assert!(s.insert(5_000));
+    assert_eq!(s.repr(), Repr::Runs { runs: 1, cap: 2 });
+    s.insert_range(3_000_000..3_000_010);
+    s.remove_range(..1_500);
+    assert_eq!(s.len(), 1_998_510);
+    assert_eq!(s.iter().take(2).collect::<Vec<_>>(), vec![1_500, 1_501]);
+    assert_eq!(s.iter().max(), Some(3_000_009));
+    assert_eq!(s.iter().count(), s.len());
+
+    // Inserting one element at a time switches to runs as the set grows.
+    let mut s = SetU32::new();
+    for i in 0..100_000 {
+        s.insert(i);
+    }
+    assert_eq!(s.repr(), Repr::Runs { runs: 1, cap: 1 });
+    // Scattered elements send it back to a table.
+    for i in 0..2000 {
+        s.insert(200_000 + 2*i);
+    }
+    assert!(!is_runs(&s));
+    assert_eq!(s.len(), 102_000);
+    assert!((0..100_000).all(|i| s.contains(i)));
+
+    let mut s = SetU32::new();
+    s.insert_range(u32::MAX - 9..);
+    s.insert_range(..=9);
+    assert_eq!(s.len(), 20);
+    assert!(s.contains(u32::MAX) && s.contains(0));
+    s.insert_range(1 << 20..1 << 21);
+    assert!(is_runs(&s));
+    s.remove_range(..);
+    assert_eq!(s.len(), 0);
+
+    let s = SetU32Builder::new().runs(false).build_from_iter(0..100_000);
+    assert_eq!(s.repr(), Repr::Dense { words: 1 + 99_999/32 + 99_999/128 });
+
+    // Compare ranges of ranges against a BTreeSet.
+    let mut s = SetU32::new();
+    let mut b = std::collections::BTreeSet::new();
+    let mut x: u64 = 12345;
+    for step in 0..300 {
+        x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let lo = ((x >> 33) % 100_000) as u32;
+        let hi = lo + ((x >> 20) % 5_000) as u32;
+        if step % 3 == 2 {
+            s.remove_range(lo..hi);
+            b.retain(|&v| v < lo || v >= hi);
+        } else {
+            s.insert_range(lo..hi);
+            b.extend(lo..hi);
+        }
+        assert_eq!(s.len(), b.len());
+        assert_eq!(s.contains(lo), b.contains(&lo));
+        assert_eq!(s.contains(hi), b.contains(&hi));
+    }
+    assert_eq!(s.iter().collect::<Vec<_>>(), b.into_iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_with_capacity_and_bits() {
+    // Any width gives a working set, even those that mark other
+    // representations in the header.
+    let elems = [0, 1, 5, 63, 1000, 1 << 28, u32::MAX];
+    assert_eq!(SetU32::with_capacity_and_bits(8, 33).repr(), Repr::Big { cap: 8 });
+    let mut s = SetU32::with_capacity_and_bits(8, 31);
+    assert_eq!(s.repr(), Repr::Heap { bits: 30, cap: 8 });
+    for &x in elems.iter() {
+        assert!(s.insert(x));
+    }
+    let mut v: Vec<_> = s.iter().collect();
+    v.sort_unstable();
+    assert_eq!(v, elems);
+    for bits in 0..40 {
+        let mut s = SetU32::with_capacity_and_bits(8, bits);
+        for &x in elems.iter() {
+            assert!(s.insert(x), "bits {}", bits);
+        }
+        assert_eq!(s.len(), elems.len());
+        assert!(elems.iter().all(|&x| s.contains(x)), "bits {}", bits);
+        let mut v: Vec<_> = s.iter().collect();
+        v.sort_unstable();
+        assert_eq!(v, elems, "bits {}", bits);
+    }
+}
+
+#[test]
+fn test_runs_shrink() {
+    let runs_cap = |s: &SetU32| match s.repr() {
+        Repr::Runs { runs, cap } => (runs, cap),
+        r => panic!("not runs: {:?}", r),
+    };
+    // Fragment a long run into 50,000 runs, then cut most of them.
+    let mut s: SetU32 = (0..2_000_000).collect();
+    for i in 0..50_000 {
+        s.remove(40*i);
+    }
+    let (runs, cap) = runs_cap(&s);
+    assert_eq!(runs, 50_000);
+    assert!(cap >= runs);
+    s.remove_range(..1_999_640);
+    assert_eq!(runs_cap(&s), (9, 18));
+    assert_eq!(s.len(), 9*39);
+
+    // Removing elements one at a time gives the room back as well.
+    let mut s: SetU32 = (0..200_000).collect();
+    for i in (0..100_000).rev() {
+        s.remove(2*i + 1);
+    }
+    assert_eq!(runs_cap(&s).0, 100_000);
+    for i in (10..100_000).rev() {
+        s.remove(2*i);
+    }
+    let (runs, cap) = runs_cap(&s);
+    assert_eq!(runs, 10);
+    assert!(cap <= 4*runs);
+    assert!(s.iter().eq((0..10).map(|x| 2*x)));
+}
+
+#[test]
+fn test_dense_base() {
+    let is_dense = |s: &SetU32| matches!(s.repr(), Repr::Dense { .. });
+    // A cluster far from zero needs bits only for its own span.
+    let cluster: Vec<u32> = (4_000_000_000..4_000_000_000 + 500).filter(|x| x % 3 != 0).collect();
+    let s: SetU32 = cluster.iter().cloned().collect();
+    assert!(is_dense(&s));
+    assert!(s.memory_report().total() < 200);
+    assert!(s.iter().eq(cluster.iter().cloned()));
+    assert!(!s.contains(0) && !s.contains(4_000_000_000 - 1) && !s.contains(4_000_000_000 + 500));
+
+    // Inserting one at a time ends up the same way.
+    let mut s = SetU32::new();
+    let mut b = std::collections::BTreeSet::new();
+    for i in 0..10_000 {
+        s.insert(4_000_000_000 + i*3);
+        b.insert(4_000_000_000 + i*3);
+    }
+    assert!(is_dense(&s));
+    assert!(s.memory_report().total() < 10_000);
+
+    // The bitset stretches both ways.
+    let mut x: u32 = 12345;
+    for _ in 0..2_000 {
+        x = x.wrapping_mul(1103515245).wrapping_add(12345);
+        let e = 4_000_000_000 - 20_000 + (x >> 8) % 70_000;
+        assert_eq!(s.insert(e), b.insert(e));
+        if x % 5 == 0 {
+            assert_eq!(s.remove(e + 1), b.remove(&(e + 1)));
+        }
+    }
+    assert!(is_dense(&s));
+    assert_eq!(s.iter().collect::<Vec<_>>(), b.iter().cloned().collect::<Vec<_>>());
+    assert_eq!(s.iter().min(), b.iter().next().cloned());
+    assert_eq!(s.iter().max(), b.iter().next_back().cloned());
+
+    // Bitsets with different bases still combine word by word.
+    let evens: SetU32 = (1000..3000).filter(|x| x % 2 == 0).collect();
+    let thirds: SetU32 = (2000..5000).filter(|x| x % 3 == 0).collect();
+    assert!(is_dense(&evens) && is_dense(&thirds));
+    let mut u = evens.clone();
+    u.union_with(&thirds);
+    let mut i = evens.clone();
+    i.intersect_with(&thirds);
+    let mut v = thirds.clone();
+    v.union_with(&evens);
+    assert!(u.iter().eq((1000..5000).filter(|&x| (x % 2 == 0 && x < 3000) || (x % 3 == 0 && x >= 2000))));
+    assert!(i.iter().eq((2000..3000).filter(|x| x % 6 == 0)));
+    assert!(v.iter().eq(u.iter()));
+}
+
+#[test]
+fn test_chunked() {
+    let is_chunked = |s: &SetU32| matches!(s.repr(), Repr::Chunked { .. });
+    // Clusters of every size, far enough apart that neither a dense
+    // bitset nor a single table suits them.
+    let mut v = Vec::new();
+    for k in 0..300u32 {
+        let n = [1, 4, 5, 100, 4096, 4097, 20_000][k as usize % 7];
+        let step = 65536/n;
+        v.extend((0..n).map(|i| (k*211 << 16) + i*step));
+    }
+    let s = SetU32::from_sorted_unique(&v);
+    assert_eq!(s.repr(), Repr::Chunked { chunks: 300 });
+    assert_eq!(s.len(), v.len());
+    assert!(s.iter().eq(v.iter().cloned()));
+    assert_eq!(s.iter().max(), v.last().cloned());
+    assert_eq!(s.iter().min(), v.first().cloned());
+    assert!(v.iter().all(|&x| s.contains(x)));
+    assert!(!s.contains(1) && !s.contains(212 << 16) && !s.contains(u32::MAX));
+    assert!(s.memory_report().total() < 3*v.len());
+
+    // Chunks change kind as they grow and shrink past each boundary.
+    let mut s = s.clone();
+    let mut b: std::collections::BTreeSet<u32> = v.iter().cloned().collect();
+    let mut x: u32 = 1;
+    for i in 0..200_000 {
+        x = x.wrapping_mul(1103515245).wrapping_add(12345);
+        let e = ((x >> 8) % 7 * 211 << 16) + (x >> 4) % 512;
+        if i % 3 == 0 {
+            assert_eq!(s.remove(e), b.remove(&e));
+        } else {
+            assert_eq!(s.insert(e), b.insert(e));
+        }
+    }
+    assert!(is_chunked(&s));
+    assert_eq!(s.len(), b.len());
+    assert!(s.iter().eq(b.iter().cloned()));
+    for &e in v.iter().step_by(3) {
+        assert_eq!(s.remove(e), b.remove(&e));
+    }
+    assert!(s.iter().eq(b.iter().cloned()));
+
+    // Bitmaps of whole chunks beat a dense bitset with room to grow.
+    let thirds: Vec<u32> = (0..1 << 20).step_by(3).collect();
+    let s = SetU32::from_sorted_unique(&thirds);
+    assert_eq!(s.repr(), Repr::Chunked { chunks: 16 });
+    let unchunked = SetU32Builder::new().chunks(false).build_from_iter(thirds.iter().cloned());
+    assert!(matches!(unchunked.repr(), Repr::Dense { .. }));
+    assert!(unchunked.memory_report().total() > s.memory_report().total());
+
+    // Inserting one at a time ends up split into chunks as well.
+    let mut s = SetU32::new();
+    for &x in &thirds {
+        s.insert(x);
+    }
+    assert!(is_chunked(&s));
+    assert!(s.iter().eq(thirds.iter().cloned()));
+
+    // Chunked sets combine chunk by chunk.
+    let evens: Vec<u32> = v.iter().cloned().filter(|x| x % 2 == 0).collect();
+    let odds_and_more: Vec<u32> = v.iter().map(|x| x | 1).chain((0..70_000).map(|i| i << 10)).collect();
+    let a = SetU32::from_sorted_unique(&evens);
+    let c: SetU32 = odds_and_more.iter().cloned().collect();
+    assert!(is_chunked(&a) && is_chunked(&c));
+    let mut u = a.clone();
+    u.union_with(&c);
+    let mut i = a.clone();
+    i.intersect_with(&c);
+    let ua: std::collections::BTreeSet<u32> = evens.iter().chain(&odds_and_more).cloned().collect();
+    let ia: Vec<u32> = evens.iter().cloned().filter(|x| c.contains(*x)).collect();
+    assert!(u.iter().eq(ua.iter().cloned()));
+    let mut iv: Vec<u32> = i.iter().collect();
+    iv.sort_unstable();
+    assert_eq!(iv, ia);
+
+    // Small sets are stored whole again.
+    for &x in &thirds[1000..] {
+        s.remove(x);
+    }
+    assert!(!is_chunked(&s));
+    let mut left: Vec<u32> = s.iter().collect();
+    left.sort_unstable();
+    assert_eq!(left, &thirds[..1000]);
+}
+
+#[test]
+fn test_dense_ranges() {
+    let is_dense = |r: Repr| matches!(r, Repr::Dense { .. });
+    let without_runs = || SetU32Builder::new().runs(false);
+
+    // Filling and clearing partial and whole words.
+    let mut s = without_runs().build_from_iter((1_000..3_000).filter(|x| x % 3 == 0));
+    let mut b: std::collections::BTreeSet<u32> = s.iter().collect();
+    assert!(is_dense(s.repr()));
+    let mut x: u64 = 12345;
+    for step in 0..300 {
+        x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let lo = 500 + ((x >> 33) % 3_000) as u32;
+        let hi = lo + ((x >> 20) % 300) as u32;
+        if step % 2 == 1 {
+            s.remove_range(lo..hi);
+            b.retain(|&v| v < lo || v >= hi);
+        } else {
+            s.insert_range(lo..=hi);
+            b.extend(lo..=hi);
+        }
+        assert_eq!(s.len(), b.len());
+        assert_eq!(s.contains_range(lo..hi), (lo..hi).all(|v| b.contains(&v)));
+    }
+    assert!(is_dense(s.repr()));
+    assert_eq!(s.iter().collect::<Vec<_>>(), b.iter().cloned().collect::<Vec<_>>());
+    s.remove_range(..);
+    assert_eq!(s.len(), 0);
+    assert_eq!(s.iter().count(), 0);
+
+    // A big range on a sparse set becomes a bitset at once.
+    let mut s = without_runs().build_from_iter(vec![7, 1 << 20, 5_000_000]);
+    assert!(!is_dense(s.repr()));
+    s.insert_range(100..1_000_000);
+    assert!(is_dense(s.repr()));
+    assert_eq!(s.len(), 1_000_000 - 100 + 3);
+    assert!(s.contains_range(100..1_000_000) && !s.contains_range(99..1_000_000));
+    assert!(s.contains(7) && s.contains(1 << 20) && s.contains(5_000_000));
+
+    // Every representation answers contains_range.
+    let runs: SetU32 = (10..20).chain(30..40).collect();
+    let table: SetU32 = vec![1, 2, 3, 1 << 30].into_iter().collect();
+    let clusters: Vec<u32> = (0..300).flat_map(|k| (0..300).map(move |i| (k*211 << 16) + 2*i)).collect();
+    let chunked = SetU32::from_sorted_unique(&clusters);
+    assert!(matches!(chunked.repr(), Repr::Chunked { .. }));
+    assert!(chunked.contains_range(211 << 16..=211 << 16));
+    assert!(!chunked.contains_range(211 << 16..=(211 << 16) + 2));
+    for s in &[runs, table, chunked] {
+        let b: std::collections::BTreeSet<u32> = s.iter().collect();
+        for &(lo, hi) in &[(1, 4), (10, 20), (10, 21), (15, 35), (100, 200), (1 << 30, 1 << 30)] {
+            assert_eq!(s.contains_range(lo..hi), (lo..hi).all(|v| b.contains(&v)));
+        }
+        assert!(s.contains_range(5..5));
+        assert!(!s.contains_range(..));
+    }
+}
+
+#[test]
+fn test_double_ended() {
+    fn check<P: SetPolicy>(mut s: SetU32<Global, P>) {
+        let fwd: Vec<u32> = s.iter().collect();
+        assert_eq!(s.iter().len(), s.len());
+        let mut back: Vec<u32> = s.iter().rev().collect();
+        back.reverse();
+        assert_eq!(back, fwd);
+        assert_eq!(s.iter().last(), fwd.last().cloned());
+        // Taking from both ends meets in the middle.
+        let mut it = s.iter();
+        let (mut front, mut rear) = (Vec::new(), Vec::new());
+        for i in 0.. {
+            assert_eq!(it.len(), fwd.len() - front.len() - rear.len());
+            let x = if i % 3 == 0 { it.next_back() } else { it.next() };
+            match x {
+                Some(x) if i % 3 == 0 => rear.push(x),
+                Some(x) => front.push(x),
+                None => break,
+            }
+        }
+        assert_eq!((it.next(), it.next_back(), it.next()), (None, None, None));
+        front.extend(rear.into_iter().rev());
+        assert_eq!(front, fwd);
+        let mut into: Vec<u32> = s.clone().into_iter().rev().collect();
+        into.reverse();
+        assert_eq!(into, fwd);
+        let mut drain = s.drain();
+        assert_eq!(drain.len(), fwd.len());
+        let mut drained: Vec<u32> = drain.by_ref().rev().collect();
+        assert_eq!(drain.next(), None);
+        drained.reverse();
+        assert_eq!(drained, fwd);
+        assert_eq!(s.len(), 0);
+    }
+    check(SetU32::new());
+    let tiny: SetU32 = vec![1, 2, 3].into_iter().collect();
+    assert_eq!(tiny.repr(), Repr::Tiny);
+    check(tiny);
+    let heap: SetU32 = (0..200).map(|x| x*x*x).collect();
+    assert!(matches!(heap.repr(), Repr::Heap { .. }));
+    check(heap);
+    check(SetU32Builder::new().force_big().build_from_iter((0..200).map(|x| x*7)));
+    let runs: SetU32 = (0..1000).chain(5000..6000).collect();
+    assert!(matches!(runs.repr(), Repr::Runs { .. }));
+    check(runs);
+    check(SetU32Builder::new().runs(false).build_from_iter((0..3000).filter(|x| x % 3 != 0)));
+    let mut v = Vec::new();
+    for k in 0..30u32 {
+        let n = [1, 4, 5, 100, 4097, 20_000][k as usize % 6];
+        let step = 65536/n;
+        v.extend((0..n).map(|i| (k*211 << 16) + i*step));
+    }
+    let chunked = SetU32::from_sorted_unique(&v);
+    assert!(matches!(chunked.repr(), Repr::Chunked { .. }));
+    check(chunked);
+}
+
+#[test]
+fn test_cursor() {
+    // Mix steps and seeks (some of them backward), checking each
+    // against a position in the sorted elements.
+    fn check<P: SetPolicy>(s: &SetU32<Global, P>) {
+        let mut v: Vec<u32> = s.iter().collect();
+        v.sort();
+        let top = v.last().map_or(10, |&x| x + 10);
+        let first_from = |x: u32| v.iter().position(|&y| y >= x).unwrap_or(v.len());
+        assert_eq!(s.cursor_at(top).peek(), None);
+        let mut r: u64 = 1;
+        for &start in &[0, v.get(v.len()/2).cloned().unwrap_or(0), top/3] {
+            let mut c = s.cursor_at(start);
+            let mut pos = first_from(start);
+            for _ in 0..2000 {
+                r = r.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                assert_eq!(c.peek(), v.get(pos).cloned());
+                if r >> 62 == 0 {
+                    assert_eq!(c.next(), v.get(pos).cloned());
+                    pos = core::cmp::min(pos + 1, v.len());
                 } else {
-                    let mut keys: Vec<_> = v.iter().map(|&x| x/bits).collect();
-                    keys.sort();
-                    keys.dedup();
-                    let sz = (keys.len()+1)*11/10;
-                    let mut s = SetU32::with_capacity_and_bits(sz, bits);
-                    for value in v.into_iter() {
-                        s.insert(value);
-                    }
-                    s
+                    let here = v.get(pos).cloned().unwrap_or(top);
+                    let jump = (r >> 33) as u32 % core::cmp::max(1, top/40);
+                    let x = if r >> 61 & 1 == 0 { here.saturating_sub(jump) } else { here.saturating_add(jump) };
+                    pos = core::cmp::max(pos, first_from(x));
+                    assert_eq!(c.seek(x), v.get(pos).cloned());
                 }
             }
-        } else {
-            SetU32(0 as *mut S)
         }
+        let from_middle: Vec<u32> = s.cursor_at(top/2).collect();
+        assert_eq!(from_middle, v[first_from(top/2)..].to_vec());
     }
+    check(&SetU32::new());
+    let tiny: SetU32 = vec![1, 2, 3].into_iter().collect();
+    check(&tiny);
+    let tiny: SetU32 = vec![5, 900, 7000].into_iter().collect();
+    assert_eq!(tiny.repr(), Repr::Tiny);
+    check(&tiny);
+    // A small set is walked in place, with nothing copied.
+    assert!(matches!(tiny.cursor_at(0).rest, CursorRest::Tiny(_)));
+    let heap: SetU32 = (0..2000).map(|x| x*x).collect();
+    assert!(matches!(heap.repr(), Repr::Heap { .. }));
+    check(&heap);
+    check(&SetU32Builder::new().force_big().build_from_iter((0..2000).map(|x| x*7)));
+    let runs: SetU32 = (0..100).flat_map(|i| i*1000..i*1000 + 100).collect();
+    assert!(matches!(runs.repr(), Repr::Runs { .. }));
+    check(&runs);
+    let dense = SetU32Builder::new().runs(false).build_from_iter((5000..30_000).filter(|x| x % 3 != 0));
+    assert!(matches!(dense.repr(), Repr::Dense { .. }));
+    check(&dense);
+    let chunked: SetU32 = (0..100_000).map(|i| i*37).collect();
+    assert!(matches!(chunked.repr(), Repr::Chunked { .. }));
+    check(&chunked);
 }
 
-#[cfg(test)]
-fn test_a_collect(v: Vec<u32>) {
-    let s: SetU32 = v.iter().cloned().collect();
-    let vv: Vec<_> = s.iter().collect();
-    let ss: SetU32 = vv.iter().cloned().collect();
-    let vvv: Vec<_> = ss.iter().collect();
-    assert_eq!(vv, vvv);
+#[test]
+fn test_std_traits() {
+    use alloc::collections::BTreeSet;
+    let sets: Vec<SetU32> = vec![
+        SetU32::new(),
+        SetU32::from([3, 1, 2]),
+        (0..2000).map(|x| x*x).collect(),
+        (0..20).flat_map(|i| i*1000..i*1000 + 100).collect(),
+        (500..3000).filter(|x| x % 3 != 0).collect(),
+    ];
+    for a in &sets {
+        let model: BTreeSet<u32> = a.clone().into();
+        assert_eq!(a.to_string(), format!("{:?}", model));
+        assert_eq!(a, &SetU32::from(model.iter().cloned().collect::<Vec<_>>()));
+        let mut e = SetU32::new();
+        e.extend(a);
+        assert_eq!(&e, a);
+        for b in &sets {
+            let mb: BTreeSet<u32> = b.clone().into();
+            assert_eq!(a == b, model == mb);
+            assert_eq!(a.cmp(b), model.cmp(&mb));
+            assert_eq!(BTreeSet::from(a | b), &model | &mb);
+            assert_eq!(BTreeSet::from(a & b), &model & &mb);
+            assert_eq!(BTreeSet::from(a - b), &model - &mb);
+            assert_eq!(BTreeSet::from(a ^ b), &model ^ &mb);
+        }
+    }
+    #[cfg(feature = "std")]
+    {
+        let h: std::collections::HashSet<u32> = (0..100).collect();
+        assert_eq!(SetU32::from(h), (0..100).collect());
+    }
+    assert_eq!(format!("{}", SetU32::from([7])), "{7}");
 }
 
+#[cfg(feature = "rayon")]
 #[test]
-fn test_collect() {
-    test_a_collect(vec![]);
-    test_a_collect(vec![0]);
-    test_a_collect(vec![0,1<<29]);
-    test_a_collect(vec![0,1<<30,1<<30]);
-    test_a_collect((0..1024).collect());
+fn test_par_iter() {
+    use rayon::prelude::*;
+    use crate::par::Split;
+    // Split as far as possible, checking that each piece knows how
+    // many elements it holds.
+    fn pieces(it: InternalIter<'_>, out: &mut Vec<Vec<u32>>) {
+        match it.split() {
+            (a, Some(b)) => {
+                pieces(a, out);
+                pieces(b, out);
+            }
+            (a, None) => {
+                let n = a.size_hint().0;
+                let v: Vec<u32> = a.collect();
+                assert_eq!(v.len(), n);
+                out.push(v);
+            }
+        }
+    }
+    fn check<P: SetPolicy + Sync>(s: &SetU32<Global, P>, min_pieces: usize) {
+        let mut want: Vec<u32> = s.iter().collect();
+        want.sort();
+        let mut split = Vec::new();
+        pieces(s.internal().iter(), &mut split);
+        assert!(split.len() >= min_pieces, "{} pieces of {:?}", split.len(), s.repr());
+        let mut got = split.concat();
+        got.sort();
+        assert_eq!(got, want);
+        let mut par: Vec<u32> = s.par_iter().collect();
+        par.sort();
+        assert_eq!(par, want);
+        let back: SetU32 = s.par_iter().collect();
+        assert_eq!(back.len(), s.len());
+        assert!(want.iter().all(|&x| back.contains(x)));
+        let mut more: SetU32 = want.iter().map(|x| x ^ 1).collect();
+        more.par_extend(s.par_iter());
+        assert!(want.iter().all(|&x| more.contains(x) && more.contains(x ^ 1)));
+    }
+    check(&SetU32::new(), 1);
+    let tiny: SetU32 = vec![1, 2, 3].into_iter().collect();
+    check(&tiny, 1);
+    let heap: SetU32 = (0..20_000).map(|x| x*x).collect();
+    assert!(matches!(heap.repr(), Repr::Heap { .. }));
+    check(&heap, 8);
+    check(&SetU32Builder::new().force_big().build_from_iter((0..20_000).map(|x| x*7)), 8);
+    // A single run is cut part way through.
+    let run: SetU32 = (0..100_000).collect();
+    assert!(matches!(run.repr(), Repr::Runs { .. }));
+    check(&run, 8);
+    let runs: SetU32 = (0..100).flat_map(|i| i*10_000..i*10_000 + 1000).collect();
+    assert!(matches!(runs.repr(), Repr::Runs { .. }));
+    check(&runs, 8);
+    let dense = SetU32Builder::new().runs(false).build_from_iter((0..100_000).filter(|x| x % 3 != 0));
+    assert!(matches!(dense.repr(), Repr::Dense { .. }));
+    check(&dense, 8);
+    let chunked: SetU32 = (0..100_000).map(|i| i*37).collect();
+    assert!(matches!(chunked.repr(), Repr::Chunked { .. }));
+    check(&chunked, 8);
+
+    // Per-thread sets of dense data are merged as bitsets.
+    let collected: SetU32 = (0..50_000).into_par_iter().filter(|x| x % 3 != 0).collect();
+    assert!(matches!(collected.repr(), Repr::Dense { .. }));
+    assert_eq!(collected.len(), 33_333);
+    // Those spread over many chunks are merged chunk by chunk.
+    let collected: SetU32 = (0..100_000).into_par_iter().map(|i| i*37).collect();
+    assert!(matches!(collected.repr(), Repr::Chunked { .. }));
+    assert_eq!(collected.len(), 100_000);
 }
 
 fn bytes_for_capacity(sz: usize) -> usize {
-    sz*4+std::mem::size_of::<S>()-4
+    sz*4+core::mem::size_of::<S>()-4
 }
-fn layout_for_capacity(sz: usize) -> std::alloc::Layout {
+fn layout_for_capacity(sz: usize) -> alloc::alloc::Layout {
     unsafe {
-        std::alloc::Layout::from_size_align_unchecked(bytes_for_capacity(sz), 4)
+        alloc::alloc::Layout::from_size_align_unchecked(bytes_for_capacity(sz), 4)
     }
 }
+/// The layout for `sz` elements, or an error if that many would
+/// overflow.  Once this has succeeded `layout_for_capacity(sz)` is
+/// safe to use.
+fn try_layout_for_capacity(sz: usize) -> Result<alloc::alloc::Layout, TryReserveError> {
+    sz.checked_mul(4)
+        .and_then(|b| b.checked_add(core::mem::size_of::<S>()-4))
+        .and_then(|b| alloc::alloc::Layout::from_size_align(b, 4).ok())
+        .ok_or_else(TryReserveError::capacity_overflow)
+}
+/// Allocate zeroed storage for `sz` elements.
+fn try_allocate_for_capacity<A: Allocator>(alloc: &A, sz: usize) -> Result<*mut S, TryReserveError> {
+    let layout = try_layout_for_capacity(sz)?;
+    match alloc.allocate_zeroed(layout) {
+        Ok(ptr) => Ok(ptr.cast::<S>().as_ptr()),
+        Err(_) => Err(TryReserveError::alloc_error(layout)),
+    }
+}
+/// Allocate zeroed storage for `sz` elements, aborting via
+/// `handle_alloc_error` if the allocator fails.
+fn allocate_for_capacity<A: Allocator>(alloc: &A, sz: usize) -> *mut S {
+    try_allocate_for_capacity(alloc, sz).unwrap_or_else(|err| err.bail())
+}
 
-impl Drop for SetU32 {
+impl<A: Allocator, P: SetPolicy> Drop for SetU32<A, P> {
     fn drop(&mut self) {
-        if self.0 as usize > 0 {
+        if self.0 as usize > 0 && self.0 as usize & 3 == 0 {
             // make it drop by moving it out
-            let c = self.capacity();
-            if c == 0 {
-            } else {
-                unsafe {
-                    std::alloc::dealloc(self.0 as *mut u8, layout_for_capacity(c));
+            let c = unsafe { (*self.0).cap };
+            unsafe {
+                let s = &*self.0;
+                if s.bits == CHUNKED {
+                    let a = core::slice::from_raw_parts(&s.array as *const u32, c as usize);
+                    for e in chunk_index(a).chunks(ENTRY) {
+                        chunks::free(&self.1, e);
+                    }
                 }
+                self.1.deallocate(core::ptr::NonNull::new_unchecked(self.0 as *mut u8),
+                                  layout_for_capacity(c as usize));
             }
         }
     }
@@ -1339,13 +3928,19 @@ impl heapsize::HeapSizeOf for SetU32 {
             Internal::Empty => 0,
             Internal::Stack(_) => 0,
             Internal::Heap { a, .. } => {
-                std::mem::size_of::<S>() - 4 + a.len()*4
+                core::mem::size_of::<S>() - 4 + a.len()*4
             }
             Internal::Big { a, .. } => {
-                std::mem::size_of::<S>() - 4 + a.len()*4
+                core::mem::size_of::<S>() - 4 + a.len()*4
+            }
+            Internal::Runs { a, .. } => {
+                core::mem::size_of::<S>() - 4 + a.len()*4
             }
             Internal::Dense { a, .. } => {
-                std::mem::size_of::<S>() - 4 + a.len()*4
+                core::mem::size_of::<S>() + a.len()*4
+            }
+            Internal::Chunked { a, .. } => {
+                core::mem::size_of::<S>() - 4 + a.len()*4 + chunked_storage(a)
             }
         }
     }
@@ -1514,7 +4109,7 @@ mod tests {
     }
 
     fn total_size_of<T: HeapSizeOf>(x: &T) -> usize {
-        std::mem::size_of::<T>() + x.heap_size_of_children()
+        core::mem::size_of::<T>() + x.heap_size_of_children()
     }
 
     fn check_size(v: &[u32]) {
@@ -1564,7 +4159,7 @@ mod tests {
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn test_size() {
-        assert_eq!(std::mem::size_of::<S>(), 16);
+        assert_eq!(core::mem::size_of::<S>(), 16);
         collect_size_is(&[0], 8);
         incremental_size_le(&[0], 8);
         collect_size_is(&[], 8);
@@ -1588,7 +4183,7 @@ mod tests {
     #[cfg(target_pointer_width = "32")]
     #[test]
     fn test_size() {
-        assert_eq!(std::mem::size_of::<S>(), 16);
+        assert_eq!(core::mem::size_of::<S>(), 16);
         collect_size_is(&[0], 4);
         incremental_size_le(&[0], 4);
         collect_size_is(&[], 4);
@@ -1610,6 +4205,84 @@ mod tests {
         incremental_size_le(& (0..100).map(|x| x*10).collect::<Vec<_>>(), 400);
     }
 
+    #[test]
+    fn test_repr() {
+        let mut s = SetU32::new();
+        assert_eq!(s.repr(), Repr::Empty);
+        s.insert(7);
+        assert_eq!(s.repr(), Repr::Tiny);
+        assert_eq!(s.memory_report().total(), core::mem::size_of::<SetU32>());
+
+        let s: SetU32 = (0..1000).collect();
+        assert_eq!(s.repr(), Repr::Runs { runs: 1, cap: 1 });
+
+        let s: SetU32 = (0..1000).filter(|x| x % 2 == 0).collect();
+        assert_eq!(s.repr(), Repr::Dense { words: 1 + 998/32 + 998/128 });
+        let r = s.memory_report();
+        assert_eq!((r.table, r.slack), (32*4, 7*4));
+        assert_eq!(r.total(), core::mem::size_of::<SetU32>() + bytes_for_capacity(40));
+
+        let s: SetU32 = (0..100).map(|x| x*1000).collect();
+        assert!(match s.repr() { Repr::Heap { .. } => true, _ => false });
+    }
+
+    #[test]
+    fn test_estimate_mem() {
+        let header = core::mem::size_of::<SetU32>();
+        assert_eq!(SetU32::estimate_mem(0, 1000), header);
+        assert_eq!(SetU32::estimate_mem(5, 10), header);
+        for &(len, max) in &[(1000, 999), (5000, 1 << 16)] {
+            let s: SetU32 = (1..=len).map(|i| max - (len - i)*(max/(len - 1))).collect();
+            assert_eq!(SetU32::estimate_mem(len as usize, max),
+                       s.memory_report().total(), "len {} max {}", len, max);
+        }
+        assert!(SetU32::estimate_mem(1000, 1 << 30) > SetU32::estimate_mem(100, 1 << 30));
+    }
+
+    #[test]
+    fn test_policy() {
+        let is_dense = |r: Repr| match r { Repr::Dense { .. } => true, _ => false };
+        let mut s = SetU32Builder::new().dense(false).build();
+        for i in 0..1000 {
+            s.insert(i);
+            assert!(!is_dense(s.repr()));
+        }
+        assert_eq!(s.len(), 1000);
+        assert!((0..1000).all(|i| s.contains(i)));
+
+        let thirds = (0..1000).map(|i| 3*i);
+        let s = SetU32Builder::new().dense_cutoff(2).build_from_iter(thirds.clone());
+        assert!(!is_dense(s.repr()));
+        let mut s = SetU32Builder::new().dense_cutoff(4).build();
+        for x in thirds {
+            s.insert(x);
+        }
+        assert!(is_dense(s.repr()));
+
+        let mut s = SetU32Builder::new().force_dense().build();
+        s.insert(5);
+        assert!(is_dense(s.repr()));
+
+        let mut s = SetU32Builder::new().force_big().growth_factor(2.0).build();
+        s.insert(5);
+        assert_eq!(s.repr(), Repr::Big { cap: 1 });
+        let mut c = s.clone();
+        for i in 0..100 {
+            c.insert(i);
+        }
+        assert_eq!(c.repr(), Repr::Big { cap: 128 });
+        assert!(c.remove(5));
+        assert_eq!(c.repr(), Repr::Big { cap: 128 });
+
+        let mut s = SetU32Builder::new().force_big().max_load(0.5).build();
+        for i in 0..1000 {
+            s.insert(i*i);
+            let r = s.memory_report();
+            assert!(r.table <= r.slack);
+        }
+        assert_eq!(s.policy(), &Policy::DEFAULT.force_big().max_load(0.5));
+    }
+
     fn check_set_primitives(elems: &[u32]) {
         let sz = elems.len();
         println!("\n\nprimitives: {:?}\n", elems);
@@ -1652,16 +4325,549 @@ mod tests {
 
 }
 
+/// The word of a dense bitset starting at `base` that holds `e`, if
+/// `e` is not below `base`.
+fn dense_word(base: u32, e: u32) -> Option<usize> {
+    e.checked_sub(base).map(|x| (x >> 5) as usize)
+}
+
+/// Insert `e` into a dense bitset, returning whether it was new, or
+/// `None` if the bitset does not reach it.
+fn dense_insert_in_place(sz: &mut u32, base: u32, a: &mut [u32], e: u32) -> Option<bool> {
+    let bits = a.get_mut(dense_word(base, e)?)?;
+    let whichbit = 1 << (e & 31);
+    let present = *bits & whichbit != 0;
+    *bits = *bits | whichbit;
+    if !present {
+        *sz = *sz + 1;
+    }
+    Some(!present)
+}
+
+/// Insert `e` into a `Heap` table without rebuilding it, returning
+/// whether it was new, or `None` if the table must be rebuilt first.
+fn heap_insert_in_place(p: &Policy, s: &mut S, a: &mut [u32], e: u32) -> Option<bool> {
+    if compute_array_bits(e) < s.bits {
+        return None;
+    }
+    let (key, offset) = split_u32(e, s.bits);
+    let used = |a: &[u32]| a.iter().filter(|&&x| x != 0).count();
+    match p_lookfor(key, a, s.bits) {
+        LookedUp::KeyFound(idx) => {
+            if a[idx] & (1 << offset) != 0 {
+                return Some(false);
+            } else {
+                a[idx] = a[idx] | (1 << offset);
+                s.sz += 1;
+                return Some(true);
+            }
+        }
+        LookedUp::EmptySpot(idx) if p.has_room(a.len(), || used(a), || true) => {
+            a[idx] = key << s.bits | 1 << offset;
+            s.sz += 1;
+            return Some(true);
+        }
+        LookedUp::EmptySpot(_) | LookedUp::NeedInsert => {
+        },
+    }
+    // println!("looking for space in sparse... {:?}", a);
+    if p.has_room(a.len(), || used(a), || {
+        a.iter().cloned()
+            .filter(|&x| x == 0) // look for empty spots
+            .enumerate() // count them
+            .any(|(n,_)| n+1 > a.len() >> 4) // we have more than 1/16 empty?
+    }) {
+        let idx = p_insert(key, a, s.bits);
+        // println!("about to insert key {} with elem {} at {}",
+        //          key, e, idx);
+        a[idx] = (key << s.bits) | (1 << offset);
+        s.sz += 1;
+        return Some(true);
+    }
+    None
+}
+
+/// Insert `e` into a `Big` table without rebuilding it, returning
+/// whether it was new, or `None` if the table must be rebuilt first.
+fn big_insert_in_place(p: &Policy, s: &mut S, a: &mut [u32], e: u32) -> Option<bool> {
+    if e == s.bits {
+        return None;
+    }
+    let e = if e == 0 { s.bits } else { e };
+    match p_lookfor(e, a, 0) {
+        LookedUp::KeyFound(_) => {
+            return Some(false);
+        }
+        LookedUp::EmptySpot(idx) if p.has_room(a.len(), || s.sz as usize, || true) => {
+            a[idx] = e;
+            s.sz += 1;
+            return Some(true);
+        }
+        LookedUp::EmptySpot(_) | LookedUp::NeedInsert => (),
+    }
+    // println!("looking for space in... {:?}", a);
+    if p.has_room(a.len(), || s.sz as usize, || {
+        a.iter().cloned()
+            .filter(|&x| x == 0) // look for empty spots
+            .enumerate() // count them
+            .any(|(n,_)| n+1 > a.len() >> 4) // we have more than 1/16 empty?
+    }) {
+        let idx = p_insert(e, a, 0);
+        // println!("about to insert at {}", p_insert(e, a, 0));
+        a[idx] = e;
+        s.sz += 1;
+        return Some(true);
+    }
+    None
+}
+
+/// Remove `e` from a dense bitset, returning whether it was present.
+fn dense_remove(sz: &mut u32, base: u32, a: &mut [u32], e: u32) -> bool {
+    if let Some(bits) = dense_word(base, e).and_then(|i| a.get_mut(i)) {
+        let whichbit = 1 << (e & 31);
+        let present = *bits & whichbit != 0;
+        *bits = *bits & !whichbit;
+        if present {
+            *sz = *sz - 1;
+        }
+        present
+    } else {
+        false
+    }
+}
+
+/// Remove `e` from a `Heap` table, returning whether it was present.
+fn heap_remove(s: &mut S, a: &mut [u32], e: u32) -> bool {
+    if compute_array_bits(e) < s.bits {
+        return false;
+    }
+    let (key, offset) = split_u32(e, s.bits);
+    if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits) {
+        if a[idx] & (1 << offset) != 0 {
+            let newa = a[idx] & !(1<<offset);
+            s.sz -= 1;
+            if newa == key << s.bits {
+                // We've removed everything with this key,
+                // so remove the whole key!
+                p_remove(key, a, s.bits);
+            } else {
+                a[idx] = newa;
+            }
+            true
+        } else {
+            false
+        }
+    } else {
+        false
+    }
+}
+
+/// Remove `e` from a `Big` table, returning whether it was present.
+fn big_remove(s: &mut S, a: &mut [u32], e: u32) -> bool {
+    if e == s.bits {
+        return false;
+    }
+    let e = if e == 0 { s.bits } else { e };
+    let had_e = p_remove(e, a, 0);
+    if had_e {
+        s.sz -= 1;
+    }
+    had_e
+}
+
+/// Whether `e` is in a dense bitset.
+fn dense_contains(base: u32, a: &[u32], e: u32) -> bool {
+    if let Some(bits) = dense_word(base, e).and_then(|i| a.get(i)) {
+        bits & (1 << (e & 31)) != 0
+    } else {
+        false
+    }
+}
+
+/// The bits of the `i`th of the `n` words covering `first..=last` in
+/// a dense bitset that stand for values in that range.
+fn range_mask(i: usize, n: usize, first: u32, last: u32) -> u32 {
+    let mut mask = !0;
+    if i == 0 {
+        mask &= !0 << (first & 31);
+    }
+    if i == n - 1 {
+        mask &= !0 >> (31 - (last & 31));
+    }
+    mask
+}
+
+/// Set (or clear) the bits for `first..=last` in a dense bitset that
+/// reaches them all, a whole word at a time.
+fn dense_fill(sz: &mut u32, base: u32, a: &mut [u32], first: u32, last: u32, on: bool) {
+    let words = &mut a[((first - base) >> 5) as usize..=((last - base) >> 5) as usize];
+    let before = crate::simd::count_ones_u32(words);
+    let n = words.len();
+    for (i, w) in words.iter_mut().enumerate() {
+        let mask = range_mask(i, n, first, last);
+        if on {
+            *w |= mask;
+        } else {
+            *w &= !mask;
+        }
+    }
+    *sz = *sz - before as u32 + crate::simd::count_ones_u32(words) as u32;
+}
+
+/// Whether a dense bitset holds every value in `first..=last`.
+fn dense_contains_range(base: u32, a: &[u32], first: u32, last: u32) -> bool {
+    let (i, j) = match (dense_word(base, first), dense_word(base, last)) {
+        (Some(i), Some(j)) if j < a.len() => (i, j),
+        _ => return false,
+    };
+    a[i..=j].iter().enumerate().all(|(k, &w)| {
+        let mask = range_mask(k, j + 1 - i, first, last);
+        w & mask == mask
+    })
+}
+
+/// Whether `e` is in a `Heap` table.
+fn heap_contains(s: &S, a: &[u32], e: u32) -> bool {
+    if compute_array_bits(e) < s.bits {
+        // println!("too big a thing");
+        return false;
+    }
+    let (key, offset) = split_u32(e, s.bits);
+    if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits) {
+        a[idx] & (1 << offset) != 0
+    } else {
+        // self.debug_me(&format!("did not find key {} from {}", key, e));
+        false
+    }
+}
+
+/// Whether `e` is in a `Big` table.
+fn big_contains(s: &S, a: &[u32], e: u32) -> bool {
+    if e == s.bits {
+        return false;
+    }
+    let e = if e == 0 { s.bits } else { e };
+    p_lookfor(e, a, 0).key_found()
+}
+
+/// How many runs a `Runs` table of `cap` words has room for.  The
+/// first word holds the number of runs, and the rest hold the first
+/// and last element of each run in increasing order.
+fn runs_room(cap: usize) -> usize {
+    cap.saturating_sub(1) / 2
+}
+
+/// The runs in a `Runs` table, as first and last elements.
+fn runs_of(a: &[u32]) -> &[u32] {
+    &a[1..1 + 2*a[0] as usize]
+}
+
+/// The number of elements in the run `first..=last`.
+fn run_len(first: u32, last: u32) -> u128 {
+    (last - first) as u128 + 1
+}
+
+/// The number of runs in `r` that start no later than `e`.
+fn runs_up_to(r: &[u32], e: u32) -> usize {
+    let (mut lo, mut hi) = (0, r.len()/2);
+    while lo < hi {
+        let mid = (lo + hi)/2;
+        if r[2*mid] <= e {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// The number of runs of consecutive values in sorted, deduplicated
+/// elements.
+fn count_sorted_runs(v: &[u32]) -> usize {
+    v.windows(2).filter(|w| w[1] != w[0] + 1).count() + !v.is_empty() as usize
+}
+
+/// The memory used by a set stored as `runs` runs.
+fn runs_mem(runs: usize) -> usize {
+    core::mem::size_of::<SetU32>() + bytes_for_capacity(1 + 2*runs)
+}
+
+/// The most runs that fit in a quarter of the `table` bytes that a
+/// table would need, which is when runs pay for their slower lookups.
+fn runs_budget(table: usize) -> usize {
+    (table/4).saturating_sub(runs_mem(0)) / (runs_mem(1) - runs_mem(0))
+}
+
+/// Whether the elements of `set` together with `first..=last` should
+/// be stored as runs.
+fn runs_pay_off(p: &Policy, set: Internal, first: u32, last: u32) -> bool {
+    if !p.allows_runs() {
+        return false;
+    }
+    let mn = core::cmp::min(first, set.iter().min().unwrap_or(first));
+    let mx = core::cmp::max(last, set.iter().max().unwrap_or(0));
+    let len = set.iter().count() as u128 + run_len(first, last);
+    let len = core::cmp::min(len, usize::MAX as u128) as usize;
+    // A table would only need to cover the values from mn to mx.
+    let budget = runs_budget(SetU32::estimate_table_mem(len, mx - mn));
+    // The new values add at most one run.
+    set.count_runs(budget) < budget
+}
+
+/// Whether the elements of `set` together with the more than a word's
+/// worth of values in `first..=last` should be stored in a dense
+/// bitset.
+fn dense_pays_off(p: &Policy, set: Internal, first: u32, last: u32) -> bool {
+    let run = run_len(first, last);
+    if run <= 32 {
+        return false;
+    }
+    let mn = core::cmp::min(first, set.iter().min().unwrap_or(first));
+    let mx = core::cmp::max(last, set.iter().max().unwrap_or(last));
+    let len = set.iter().count() as u128 + run;
+    p.use_dense(len as u64, (mx - mn) as u64, len > ((mx - mn) >> 5) as u128)
+}
+
+/// The entries in the index of a set split into chunks.
+fn chunk_index(a: &[u32]) -> &[u32] {
+    &a[1..1 + ENTRY*a[0] as usize]
+}
+
+fn chunk_at(a: &[u32], i: usize) -> &[u32] {
+    &a[1 + ENTRY*i..][..ENTRY]
+}
+
+fn chunk_at_mut(a: &mut [u32], i: usize) -> &mut [u32] {
+    &mut a[1 + ENTRY*i..][..ENTRY]
+}
+
+/// Find the entry for `key` in `index`, or where it would go.
+fn chunk_find(index: &[u32], key: u32) -> Result<usize, usize> {
+    let (mut lo, mut hi) = (0, index.len()/ENTRY);
+    while lo < hi {
+        let mid = (lo + hi)/2;
+        let k = chunks::key(&index[ENTRY*mid..]);
+        if k < key {
+            lo = mid + 1;
+        } else if k > key {
+            hi = mid;
+        } else {
+            return Ok(mid);
+        }
+    }
+    Err(lo)
+}
+
+fn chunked_contains(a: &[u32], e: u32) -> bool {
+    match chunk_find(chunk_index(a), e >> 16) {
+        Ok(i) => chunks::contains(chunk_at(a, i), e as u16),
+        Err(_) => false,
+    }
+}
+
+/// The bytes used by the chunks of a set outside its index.
+fn chunked_storage(a: &[u32]) -> usize {
+    chunk_index(a).chunks(ENTRY).map(|e| {
+        let (used, free) = chunks::storage(e);
+        used + free
+    }).sum()
+}
+
+/// The memory used by a set split into chunks holding `lens`
+/// elements.
+fn chunked_mem(lens: impl Iterator<Item = usize>) -> usize {
+    let (n, storage) = lens.fold((0, 0), |(n, bytes), len| (n + 1, bytes + chunks::storage_for(len)));
+    core::mem::size_of::<SetU32>() + bytes_for_capacity(1 + ENTRY*n) + storage
+}
+
+/// The elements of sorted `v`, split by chunk.
+fn chunk_groups(v: &[u32]) -> impl Iterator<Item = &[u32]> {
+    let mut rest = v;
+    core::iter::from_fn(move || {
+        let &first = rest.first()?;
+        let n = rest.iter().position(|&x| x >> 16 != first >> 16).unwrap_or(rest.len());
+        let (group, tail) = rest.split_at(n);
+        rest = tail;
+        Some(group)
+    })
+}
+
+/// Whether the elements of `set` together with `e` should be split
+/// into chunks.
+fn chunks_pay_off(p: &Policy, set: Internal, e: u32) -> bool {
+    let len = set.iter().count() + 1;
+    if !p.allows_chunks() || len < CHUNKED_MIN {
+        return false;
+    }
+    let mut counts: Vec<u32> = match crate::error::try_collect((0..1 << 16).map(|_| 0)) {
+        Ok(counts) => counts,
+        Err(_) => return false,
+    };
+    for x in set.iter().chain(Some(e)) {
+        counts[(x >> 16) as usize] += 1;
+    }
+    let mn = core::cmp::min(e, set.iter().min().unwrap_or(e));
+    let mx = core::cmp::max(e, set.iter().max().unwrap_or(e));
+    let lens = counts.iter().filter(|&&n| n > 0).map(|&n| n as usize);
+    chunked_mem(lens) < SetU32::estimate_table_mem(len, mx - mn)
+}
+
+/// The first and last values in `range`, or `None` if it is empty.
+fn range_bounds(range: impl core::ops::RangeBounds<u32>) -> Option<(u32, u32)> {
+    use core::ops::Bound;
+    let first = match range.start_bound() {
+        Bound::Included(&x) => x,
+        Bound::Excluded(&x) => x.checked_add(1)?,
+        Bound::Unbounded => 0,
+    };
+    let last = match range.end_bound() {
+        Bound::Included(&x) => x,
+        Bound::Excluded(&x) => x.checked_sub(1)?,
+        Bound::Unbounded => u32::MAX,
+    };
+    if first <= last {
+        Some((first, last))
+    } else {
+        None
+    }
+}
+
+/// Whether `e` is in a `Runs` table.
+fn runs_contains(a: &[u32], e: u32) -> bool {
+    let r = runs_of(a);
+    match runs_up_to(r, e) {
+        0 => false,
+        i => e <= r[2*i - 1],
+    }
+}
+
+/// Whether a `Runs` table holds every value in `first..=last`.  Runs
+/// that touch are always merged, so these must all lie in one run.
+fn runs_contains_range(a: &[u32], first: u32, last: u32) -> bool {
+    let r = runs_of(a);
+    match runs_up_to(r, first) {
+        0 => false,
+        i => last <= r[2*i - 1],
+    }
+}
+
+/// Add `first..=last` to a `Runs` table, returning whether anything
+/// was new, `None` if the table needs room for another run first, or
+/// an error if the set would hold more than `u32::MAX` elements.
+fn runs_insert(s: &mut S, a: &mut [u32], first: u32, last: u32)
+               -> Result<Option<bool>, TryReserveError> {
+    let n = a[0] as usize;
+    let room = runs_room(a.len());
+    let r = &mut a[1..];
+    // Runs i..j overlap or touch first..=last, and merge with it.
+    let k = runs_up_to(&r[..2*n], first);
+    let i = if k > 0 && r[2*k - 1] >= first.saturating_sub(1) { k - 1 } else { k };
+    let j = runs_up_to(&r[..2*n], last.saturating_add(1));
+    let (lo, hi) = if j > i {
+        (core::cmp::min(first, r[2*i]), core::cmp::max(last, r[2*j - 1]))
+    } else {
+        (first, last)
+    };
+    let added = run_len(lo, hi) - (i..j).map(|x| run_len(r[2*x], r[2*x + 1])).sum::<u128>();
+    if added == 0 {
+        return Ok(Some(false));
+    }
+    if added > (u32::MAX - s.sz) as u128 {
+        return Err(TryReserveError::capacity_overflow());
+    }
+    if j == i {
+        if n == room {
+            return Ok(None);
+        }
+        r.copy_within(2*i..2*n, 2*i + 2);
+    } else {
+        r.copy_within(2*j..2*n, 2*i + 2);
+    }
+    r[2*i] = lo;
+    r[2*i + 1] = hi;
+    a[0] = (n + 1 + i - j) as u32;
+    s.sz += added as u32;
+    Ok(Some(true))
+}
+
+/// Remove `first..=last` from a `Runs` table, returning whether
+/// anything was present, or `None` if the table needs room for
+/// another run first.
+fn runs_remove(s: &mut S, a: &mut [u32], first: u32, last: u32) -> Option<bool> {
+    let n = a[0] as usize;
+    let room = runs_room(a.len());
+    let r = &mut a[1..];
+    // Runs i..j overlap first..=last.
+    let k = runs_up_to(&r[..2*n], first);
+    let i = if k > 0 && r[2*k - 1] >= first { k - 1 } else { k };
+    let j = runs_up_to(&r[..2*n], last);
+    if j == i {
+        return Some(false);
+    }
+    let removed: u128 = (i..j).map(|x| {
+        run_len(core::cmp::max(first, r[2*x]), core::cmp::min(last, r[2*x + 1]))
+    }).sum();
+    // What is left of the first and last of those runs.
+    let head = if r[2*i] < first { Some((r[2*i], first - 1)) } else { None };
+    let tail = if r[2*j - 1] > last { Some((last + 1, r[2*j - 1])) } else { None };
+    let kept = head.iter().chain(tail.iter()).count();
+    if n + kept - (j - i) > room {
+        return None;
+    }
+    r.copy_within(2*j..2*n, 2*(i + kept));
+    for (x, &(lo, hi)) in head.iter().chain(tail.iter()).enumerate() {
+        r[2*(i + x)] = lo;
+        r[2*(i + x) + 1] = hi;
+    }
+    a[0] = (n + kept - (j - i)) as u32;
+    s.sz -= removed as u32;
+    Some(true)
+}
+
 fn p_poverty(k: u32, idx: usize, n: usize) -> usize {
-    ((idx % n) + n - (k % n as u32) as usize) % n
+    ((idx % n) + n - home32(k, n)) % n
+}
+
+/// Fill an empty table with `entries`, each a home slot and the word
+/// to store, whose keys must be distinct and fewer than the slots.
+/// This puts each word where inserting them one at a time would, but
+/// without probing: in order of their homes, each goes in the first
+/// free slot at or after its home.  Words that run off the end wrap
+/// around to the start, ahead of the words whose home is there.
+fn p_place_all(a: &mut [u32], entries: &mut [(usize, u32)]) {
+    let n = a.len();
+    debug_assert!(entries.len() < n);
+    entries.sort_unstable_by_key(|&(home, _)| home);
+    // One past the last slot used, counting on past the end, when the
+    // first `wrapped` slots are taken by words that wrapped around.
+    let end = |wrapped: usize| {
+        entries.iter().fold(wrapped, |next, &(home, _)| core::cmp::max(home, next) + 1)
+    };
+    // Words that wrap can push more words off the end, so go round
+    // until that stops.
+    let mut wrapped = 0;
+    loop {
+        let over = end(wrapped).saturating_sub(n);
+        if over <= wrapped {
+            break;
+        }
+        wrapped = over;
+    }
+    let mut next = wrapped;
+    for &(home, word) in entries.iter() {
+        let i = core::cmp::max(home, next);
+        a[if i < n { i } else { i - n }] = word;
+        next = i + 1;
+    }
 }
 
 /// This inserts k into the array, and requires that there be room for
 /// one more element.  Otherwise, things will be sad.
 fn p_insert(k: u32, a: &mut [u32], offset: u32) -> usize {
     let n = a.len();
+    let home = home32(k, n);
     for pov in 0..n {
-        let ii = ((k + pov as u32) % n as u32) as usize;
+        let ii = (home + pov) % n;
         let ki = a[ii] >> offset;
         let pov_ki = p_poverty(ki, ii, n);
         if a[ii] == 0 || ki == k {
@@ -1689,7 +4895,7 @@ fn p_insert(k: u32, a: &mut [u32], offset: u32) -> usize {
                 }
                 if pov_kj < pov_displaced {
                     // need to steal again!
-                    std::mem::swap(&mut a[jj], &mut displaced);
+                    core::mem::swap(&mut a[jj], &mut displaced);
                     pov_displaced = pov_kj;
                 }
             }
@@ -1699,6 +4905,9 @@ fn p_insert(k: u32, a: &mut [u32], offset: u32) -> usize {
     unreachable!()
 }
 
+// The p_* tests check exact slots, which assume the unkeyed `k % n`
+// placement.
+#[cfg(not(feature = "keyed-hash"))]
 #[test]
 fn test_insert() {
     let mut a = [0,0,0,0];
@@ -1723,6 +4932,45 @@ fn test_insert() {
     }
 }
 
+#[test]
+fn test_place_all() {
+    // Every word placed at once must be where a lookup looks for it,
+    // including when most homes are at the end so that words wrap.
+    let mut r: u64 = 1;
+    for &n in &[2usize, 3, 8, 61, 1000] {
+        for &crowd in &[false, true] {
+            for len in (1..n).step_by(core::cmp::max(1, n/40)) {
+                let mut keys: Vec<u32> = (0..len).map(|j| if crowd {
+                    (n - 1 + j*n) as u32
+                } else {
+                    r = r.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    (r >> 40) as u32 | 1
+                }).collect();
+                keys.sort_unstable();
+                keys.dedup();
+                let mut a = vec![0; n];
+                let mut entries: Vec<_> = keys.iter().map(|&k| (home32(k, n), k)).collect();
+                p_place_all(&mut a, &mut entries);
+                assert_eq!(a.iter().filter(|&&w| w != 0).count(), keys.len());
+                for &k in &keys {
+                    assert!(p_lookfor(k, &a, 0).key_found(), "lost {} in {:?}", k, a);
+                }
+            }
+        }
+    }
+    #[cfg(not(feature = "keyed-hash"))]
+    {
+        // The words whose home is the last slot take the first two
+        // slots, so the word whose home is slot zero moves along.
+        let mut a = [0; 8];
+        p_place_all(&mut a, &mut [(7, 7), (7, 15), (0, 8), (7, 23)]);
+        assert_eq!(a[2..7], [8, 0, 0, 0, 0]);
+        let mut wrapped = [a[7], a[0], a[1]];
+        wrapped.sort_unstable();
+        assert_eq!(wrapped, [7, 15, 23]);
+    }
+}
+
 #[derive(Debug,Eq,PartialEq,Clone,Copy)]
 enum LookedUp {
     EmptySpot(usize),
@@ -1745,7 +4993,7 @@ impl LookedUp {
             false
         }
     }
-    #[cfg(test)]
+    #[cfg(all(test, not(feature = "keyed-hash")))]
     fn unwrap(self) -> usize {
         if let LookedUp::KeyFound(idx) = self {
             idx
@@ -1755,10 +5003,36 @@ impl LookedUp {
     }
 }
 
+/// How far ahead `p_lookfor` scans for a key before checking how
+/// rich each slot is.
+const PROBE_SCAN: usize = 32;
+
 fn p_lookfor(k: u32, a: &[u32], offset: u32) -> LookedUp {
     let n = a.len();
+    let home = home32(k, n);
+    // Most keys sit in their home slot.
+    match a[home] {
+        0 => return LookedUp::EmptySpot(home),
+        x if x >> offset == k => return LookedUp::KeyFound(home),
+        _ => (),
+    }
+    // Robin hood placement means that no key before k can be richer
+    // than k would be there, so if we reach k before an empty slot we
+    // need not check the keys we passed.  We compare several slots at
+    // a time, stopping at the end of the array so as not to wrap.
+    let scan = core::cmp::min(n, PROBE_SCAN);
+    let mut pov = 1;
+    while pov < scan {
+        let ii = (home + pov) % n;
+        let window = &a[ii..core::cmp::min(n, ii + scan - pov)];
+        match crate::simd::find_slot_u32(window, k, offset) {
+            Some(j) if window[j] != 0 => return LookedUp::KeyFound(ii + j),
+            Some(_) => break,
+            None => pov += window.len(),
+        }
+    }
     for pov in 0..n {
-        let ii = ((k + pov as u32) % n as u32) as usize;
+        let ii = (home + pov) % n;
         // println!("looking in spot ii = {} with pov={}", ii, pov);
         if a[ii] == 0 {
             // println!("got empty spot at {} for key {}", ii, k);
@@ -1777,6 +5051,7 @@ fn p_lookfor(k: u32, a: &[u32], offset: u32) -> LookedUp {
     LookedUp::NeedInsert
 }
 
+#[cfg(not(feature = "keyed-hash"))]
 #[test]
 fn test_lookfor() {
     assert_eq!(LookedUp::NeedInsert, p_lookfor(5, &[3,1,2], 0));
@@ -1786,14 +5061,15 @@ fn test_lookfor() {
 
 fn p_remove(k: u32, a: &mut [u32], offset: u32) -> bool {
     let n = a.len();
+    let home = home32(k, n);
     for i in 0..n {
-        let ii = ((k + i as u32) % n as u32) as usize;
+        let ii = (home + i) % n;
         // println!("    looking to remove at distance {} slot {}", i, ii);
         if a[ii] == 0 {
             return false;
         }
         let ki = a[ii] >> offset;
-        let iki = (((ii + n) as u32 - (ki % n as u32)) % n as u32) as usize;
+        let iki = (ii + n - home32(ki, n)) % n;
         if i > iki {
             return false;
         } else if ki == k {
@@ -1821,10 +5097,11 @@ fn p_remove(k: u32, a: &mut [u32], offset: u32) -> bool {
             return true;
         }
     }
-    panic!("bug: we should have had space in {:?} for {}", a, k)
+    // A full table has no empty slot to end the search.
+    false
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "keyed-hash")))]
 fn test_insert_remove(x: u32, a: &mut [u32]) {
     println!("test_insert_remove({}, {:?})", x, a);
     let v: Vec<u32> = a.iter().cloned().collect();
@@ -1841,6 +5118,7 @@ fn test_insert_remove(x: u32, a: &mut [u32]) {
     assert_eq!(a, &v[..]);
 }
 
+#[cfg(not(feature = "keyed-hash"))]
 #[test]
 fn test_remove() {
     let mut a = [0,0,2];
@@ -1857,3 +5135,121 @@ fn test_remove() {
 
     test_insert_remove(5,&mut [0,0,2]);
 }
+
+#[test]
+fn test_try_reserve() {
+    let mut s = SetU32::new();
+    assert_eq!(s.try_reserve(core::usize::MAX).unwrap_err().kind(),
+               crate::TryReserveErrorKind::CapacityOverflow);
+    s.try_reserve(100).unwrap();
+    let used = s.mem_used();
+    for x in 0..100 {
+        assert_eq!(s.try_insert(x << 3), Ok(true));
+    }
+    assert_eq!(s.mem_used(), used);
+    assert_eq!(s.len(), 100);
+
+    let s = SetU32::try_from_iter((0..1000).chain(0..1000).map(|x| x*7)).unwrap();
+    assert_eq!(s.len(), 1000);
+    assert!(s.contains(6993));
+}
+
+/// An allocator whose allocations are 4 mod 8, counting those live.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct OffsetAlloc(std::rc::Rc<core::cell::Cell<isize>>);
+
+#[cfg(test)]
+unsafe impl Allocator for OffsetAlloc {
+    fn allocate(&self, layout: alloc::alloc::Layout)
+                -> Result<core::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+        assert!(layout.align() <= 4);
+        let outer = alloc::alloc::Layout::from_size_align(layout.size() + 4, 8).unwrap();
+        let p = Global.allocate(outer)?.as_ptr() as *mut u8;
+        self.0.set(self.0.get() + 1);
+        let p = core::ptr::slice_from_raw_parts_mut(unsafe { p.add(4) }, layout.size());
+        Ok(core::ptr::NonNull::new(p).unwrap())
+    }
+    unsafe fn deallocate(&self, ptr: core::ptr::NonNull<u8>, layout: alloc::alloc::Layout) {
+        self.0.set(self.0.get() - 1);
+        let outer = alloc::alloc::Layout::from_size_align(layout.size() + 4, 8).unwrap();
+        Global.deallocate(core::ptr::NonNull::new_unchecked(ptr.as_ptr().sub(4)), outer)
+    }
+}
+
+/// An allocator that always fails.
+#[cfg(test)]
+#[derive(Clone)]
+struct FailingAlloc;
+
+#[cfg(test)]
+unsafe impl Allocator for FailingAlloc {
+    fn allocate(&self, _: alloc::alloc::Layout)
+                -> Result<core::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+        Err(allocator_api2::alloc::AllocError)
+    }
+    unsafe fn deallocate(&self, _: core::ptr::NonNull<u8>, _: alloc::alloc::Layout) {
+        unreachable!()
+    }
+}
+
+#[test]
+fn test_try_insert_range() {
+    let mut s = SetU32::new_in(FailingAlloc);
+    assert_eq!(s.try_insert(7), Ok(true));
+    assert!(s.try_insert_range(100..200).is_err());
+    assert!(s.try_complement(1000).is_err());
+    assert!(s.try_complement(8).is_err());
+    let mut s = SetU32::new();
+    s.try_insert_range(100..200).unwrap();
+    s.try_insert_range(150..=250).unwrap();
+    let mut v: Vec<u32> = s.iter().collect();
+    v.sort_unstable();
+    assert!(v.into_iter().eq(100..=250));
+}
+
+#[test]
+fn test_clone_offset_alloc() {
+    let live = OffsetAlloc::default();
+    {
+        let heap = (0..100).map(|x| x*7);
+        let big = (0..100).map(|x| x << 24);
+        let runs = (0..100).map(|x| x*1000).flat_map(|x| x..x + 50);
+        let dense = 0..5000;
+        let sets: Vec<Vec<u32>> = vec![heap.collect(), big.collect(), runs.collect(), dense.collect()];
+        for v in sets {
+            let mut s = SetU32::new_in(live.clone());
+            for &x in v.iter() {
+                s.insert(x);
+            }
+            assert_eq!(s.0 as usize % 8, 4);
+            let c = s.clone();
+            assert_ne!(c.0, s.0);
+            for &x in v.iter() {
+                s.remove(x);
+            }
+            assert_eq!(s.len(), 0);
+            assert_eq!(c.len(), v.len());
+            assert!(v.iter().all(|&x| c.contains(x)));
+        }
+    }
+    assert_eq!(live.0.get(), 0);
+}
+
+#[cfg(feature = "deterministic")]
+#[test]
+fn test_deterministic() {
+    fn build(step: u32) -> (usize, Vec<u32>) {
+        let mut s = SetU32::new();
+        for x in 1..1000u32 {
+            s.insert(x.wrapping_mul(step));
+        }
+        s.insert(0);
+        (s.mem_used(), s.iter().collect())
+    }
+    for &step in &[3, 1 << 12, 0x9e37_79b9] {
+        let here = build(step);
+        let there = std::thread::spawn(move || build(step)).join().unwrap();
+        assert_eq!(here, there);
+    }
+}
@@ -1,5 +1,7 @@
 //! This is a an awesome module.
 
+use alloc::vec::Vec;
+
 #[cfg(test)]
 use proptest::prelude::*;
 
@@ -42,9 +44,9 @@ impl Iterator for Tiny {
 }
 
 #[cfg(target_pointer_width = "64")]
-const MAX_TINY: u32 = std::u32::MAX;
+const MAX_TINY: u32 = core::u32::MAX;
 #[cfg(target_pointer_width = "32")]
-const MAX_TINY: u32 = std::u16::MAX as u32;
+const MAX_TINY: u32 = core::u16::MAX as u32;
 
 #[cfg(target_pointer_width = "64")]
 const START_OFFSET: u32 = 32;
@@ -302,13 +304,13 @@ impl SetU32 {
                 // assert_eq!((s.cap & 31).count_ones(), 1);
                 let cap = 1 << (s.cap & 31);
                 let start = &s.array as *const u32 as usize as *const (u32,u32);
-                let a = std::slice::from_raw_parts(start, cap);
+                let a = core::slice::from_raw_parts(start, cap);
                 assert!(a.len().count_ones() == 1);
                 Internal::Table { sz: s.sz, a }
             } else {
                 let ptr = (self.0.tiny & !3) as *mut S;
                 let s = &*ptr;
-                let a = std::slice::from_raw_parts(&s.array as *const u32,
+                let a = core::slice::from_raw_parts(&s.array as *const u32,
                                                    s.cap as usize);
                 Internal::Dense { sz: s.sz, a }
             }
@@ -326,13 +328,13 @@ impl SetU32 {
                 let cap = 1 << (s.cap & 31);
                 let available = s.cap >> 5;
                 let start = &mut s.array as *mut u32 as usize as *mut (u32,u32);
-                let a = std::slice::from_raw_parts_mut(start, cap);
+                let a = core::slice::from_raw_parts_mut(start, cap);
                 assert!(a.len().count_ones() == 1);
                 InternalMut::Table { sz: &mut s.sz, cap_ptr: &mut s.cap, available, a }
             } else {
                 let ptr = (self.0.tiny & !3) as *mut S;
                 let s = &mut *ptr;
-                let a = std::slice::from_raw_parts_mut(&mut s.array as *mut u32,
+                let a = core::slice::from_raw_parts_mut(&mut s.array as *mut u32,
                                                        s.cap as usize);
                 InternalMut::Dense { sz: &mut s.sz, a }
             }
@@ -340,7 +342,7 @@ impl SetU32 {
     }
     /// The total memory used by the set (including stack)
     pub fn mem_used(&self) -> usize {
-        let mut tot = std::mem::size_of::<usize>();
+        let mut tot = core::mem::size_of::<usize>();
         unsafe {
             if self.0.tiny == 0 {
                 // nothing
@@ -350,12 +352,12 @@ impl SetU32 {
                 let s = &*self.0.ptr;
                 // assert_eq!((s.cap & 31).count_ones(), 1);
                 let cap = 1 << (s.cap & 31);
-                tot += std::mem::size_of::<S>() - 4 + cap*8;
+                tot += core::mem::size_of::<S>() - 4 + cap*8;
                 // println!("mem_used for table {}", tot);
             } else {
                 let ptr = (self.0.tiny & !3) as *mut S;
                 let s = &*ptr;
-                tot += std::mem::size_of::<S>() - 4 + s.cap as usize*4;
+                tot += core::mem::size_of::<S>() - 4 + s.cap as usize*4;
                 // println!("mem_used for dense {}", tot);
             }
         }
@@ -651,7 +653,7 @@ impl SetU32 {
     fn dense_for_mx(mx: u32) -> Self {
         let n = 1 + mx/32 + mx/128;
         unsafe {
-            let newptr = std::alloc::alloc_zeroed(layout_for_num_u32(n)) as *mut S;
+            let newptr = alloc::alloc::alloc_zeroed(layout_for_num_u32(n)) as *mut S;
             (*newptr).cap = n;
             SetU32(I { tiny: newptr as usize | 2 })
         }
@@ -663,11 +665,11 @@ impl SetU32 {
         let n = 1 + mx/32 + mx/128;
 
         let oldcap = (*ptr).cap;
-        let newptr = std::alloc::realloc(ptr as *mut u8,
+        let newptr = alloc::alloc::realloc(ptr as *mut u8,
                                          layout_for_num_u32(oldcap),
                                          bytes_for_num_u32(n)) as *mut S;
         if newptr as usize == 0 {
-            std::alloc::handle_alloc_error(layout_for_num_u32(n));
+            alloc::alloc::handle_alloc_error(layout_for_num_u32(n));
         }
         (*newptr).cap = n;
         self.0.tiny = newptr as usize | 2;
@@ -685,7 +687,7 @@ impl SetU32 {
     fn table_with_cap(cap: u32) -> Self {
         // assert_eq!(cap.count_ones(), 1);
         unsafe {
-            let x = SetU32(I { ptr: std::alloc::alloc_zeroed(layout_for_num_u32(1<<(cap+1))) as *mut S});
+            let x = SetU32(I { ptr: alloc::alloc::alloc_zeroed(layout_for_num_u32(1<<(cap+1))) as *mut S});
             (*x.0.ptr).cap = cap | (1 << (cap + 5));
             x
         }
@@ -694,15 +696,15 @@ impl SetU32 {
 
 #[test]
 fn table_sizes() {
-    let size_without_data = std::mem::size_of::<S>() - 4;
-    let size_usize = std::mem::size_of::<usize>();
+    let size_without_data = core::mem::size_of::<S>() - 4;
+    let size_usize = core::mem::size_of::<usize>();
     assert_eq!(SetU32::table_with_cap(1).mem_used(),
                size_usize + size_without_data + 8*2);
     assert_eq!(SetU32::table_with_cap(2).mem_used(),
                size_usize + size_without_data + 8*4);
 
     let v: SetU32 = (1..1000).map(|x| x*64).collect();
-    assert_eq!(10000 + std::mem::size_of::<usize>(), v.mem_used());
+    assert_eq!(10000 + core::mem::size_of::<usize>(), v.mem_used());
 }
 
 impl Default for SetU32 {
@@ -712,7 +714,7 @@ impl Default for SetU32 {
 }
 
 fn log2(x: u32) -> u32 {
-    std::mem::size_of::<u32>() as u32*8 - x.leading_zeros()
+    core::mem::size_of::<u32>() as u32*8 - x.leading_zeros()
 }
 
 #[test]
@@ -723,7 +725,7 @@ fn test_log2() {
     assert_eq!(3, log2(4));
 }
 
-impl std::iter::FromIterator<u32> for SetU32 {
+impl core::iter::FromIterator<u32> for SetU32 {
     fn from_iter<T>(iter: T) -> Self
         where
         T: IntoIterator<Item = u32>
@@ -781,11 +783,11 @@ fn test_collect() {
 }
 
 const fn bytes_for_num_u32(sz: u32) -> usize {
-    sz as usize*4+std::mem::size_of::<S>()-4
+    sz as usize*4+core::mem::size_of::<S>()-4
 }
 
-unsafe fn layout_for_num_u32(sz: u32) -> std::alloc::Layout {
-    std::alloc::Layout::from_size_align_unchecked(bytes_for_num_u32(sz), 4)
+unsafe fn layout_for_num_u32(sz: u32) -> alloc::alloc::Layout {
+    alloc::alloc::Layout::from_size_align_unchecked(bytes_for_num_u32(sz), 4)
 }
 
 impl Drop for SetU32 {
@@ -795,7 +797,7 @@ impl Drop for SetU32 {
             if n != 0 {
                 unsafe {
                     let ptr = (self.0.tiny & !3) as *mut u8;
-                    std::alloc::dealloc(ptr, layout_for_num_u32(n));
+                    alloc::alloc::dealloc(ptr, layout_for_num_u32(n));
                 }
             }
         }
@@ -1192,7 +1194,7 @@ fn p_insert_better(k: u32, a: &mut [(u32,u32)]) -> Inserted {
                 }
                 if pov_kj < pov_displaced {
                     // need to steal again!
-                    std::mem::swap(&mut a[jj], &mut displaced);
+                    core::mem::swap(&mut a[jj], &mut displaced);
                     pov_displaced = pov_kj;
                 }
             }
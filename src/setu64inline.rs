@@ -0,0 +1,576 @@
+// Copyright 2019 David Roundy
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Sets with a wider inline buffer.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use itertools::Itertools;
+use crate::set64::Fits64;
+use crate::setu64::{self, SetU64};
+
+/// A set of `u64` that keeps its elements in `WORDS` words of its own
+/// until they no longer fit, and only then moves them to a [`SetU64`]
+/// on the heap.
+///
+/// Inline elements are kept sorted, with the smallest stored in as
+/// many bits as it needs and every later one stored as its distance
+/// from the one before, all in the same number of bits.  So two words
+/// hold 16 bytes of elements: ten ids below a million that are spaced
+/// no more than a few thousand apart, say, or any number (up to 255)
+/// of consecutive ones.  A set on the heap moves back inline when a
+/// removal leaves it with at most `8*WORDS` elements that fit.
+///
+/// Iteration is in increasing order while the elements are inline,
+/// and in no particular order while they are on the heap.
+///
+/// [`Set64Inline`] holds any [Fits64] type in the same way.
+///
+/// # Examples
+///
+/// ```
+/// use tinyset::SetU64Inline;
+///
+/// let mut s: SetU64Inline<2> = (0..10).map(|i| 500_000 + 1000*i).collect();
+/// assert!(s.is_inline());
+/// assert!(s.contains(503_000));
+/// s.insert(u64::MAX);
+/// assert!(!s.is_inline());
+/// assert_eq!(s.len(), 11);
+/// ```
+#[derive(Clone)]
+pub struct SetU64Inline<const WORDS: usize>(Inner<WORDS>);
+
+#[derive(Clone)]
+enum Inner<const W: usize> {
+    Inline(Packed<W>),
+    Heap(SetU64),
+}
+
+/// The number of bits needed to hold `x`, which is zero for zero.
+fn bits(x: u64) -> u8 {
+    64 - x.leading_zeros() as u8
+}
+
+/// The `n` bits of `words` starting at bit `at`.
+fn get_bits(words: &[u64], at: usize, n: u8) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+    let (i, s) = (at / 64, at % 64);
+    let mut x = words[i] >> s;
+    if s + n as usize > 64 {
+        x |= words[i + 1] << (64 - s);
+    }
+    if n < 64 { x & ((1 << n) - 1) } else { x }
+}
+
+/// Store `x` in the (zeroed) `n` bits of `words` starting at bit `at`.
+fn put_bits(words: &mut [u64], at: usize, n: u8, x: u64) {
+    if n == 0 {
+        return;
+    }
+    let (i, s) = (at / 64, at % 64);
+    words[i] |= x << s;
+    if s + n as usize > 64 {
+        words[i + 1] |= x >> (64 - s);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Packed<const W: usize> {
+    len: u8,
+    first_bits: u8,
+    gap_bits: u8,
+    words: [u64; W],
+}
+
+impl<const W: usize> Packed<W> {
+    const EMPTY: Packed<W> = Packed { len: 0, first_bits: 0, gap_bits: 0, words: [0; W] };
+
+    /// Whether `len` elements from `mn` to `mx` might fit, judging by
+    /// their average gap, which is no wider than their widest.
+    fn might_fit(len: usize, mn: u64, mx: u64) -> bool {
+        match len {
+            0 => true,
+            1 => bits(mn) as usize <= 64*W,
+            _ => {
+                let gap = (mx - mn - (len as u64 - 1)) / (len as u64 - 1);
+                len <= u8::MAX as usize && bits(mn) as usize + (len - 1)*bits(gap) as usize <= 64*W
+            }
+        }
+    }
+
+    /// Pack the increasing `elems`, if they fit.
+    fn encode(elems: impl Iterator<Item = u64> + Clone) -> Option<Packed<W>> {
+        let (mut len, mut first, mut last, mut gap) = (0usize, 0, 0, 0);
+        for x in elems.clone() {
+            if len == 0 {
+                first = x;
+            } else {
+                gap = core::cmp::max(gap, x - last - 1);
+            }
+            last = x;
+            len += 1;
+        }
+        let (first_bits, gap_bits) = (bits(first), bits(gap));
+        if len > u8::MAX as usize
+            || len > 0 && first_bits as usize + (len - 1)*gap_bits as usize > 64*W
+        {
+            return None;
+        }
+        let mut p = Packed { len: len as u8, first_bits, gap_bits, words: [0; W] };
+        let mut at = 0;
+        let mut prev = None;
+        for x in elems {
+            let (y, n) = match prev {
+                None => (x, first_bits),
+                Some(l) => (x - l - 1, gap_bits),
+            };
+            put_bits(&mut p.words, at, n, y);
+            at += n as usize;
+            prev = Some(x);
+        }
+        Some(p)
+    }
+    fn iter(&self) -> PackedIter<W> {
+        PackedIter { p: *self, i: 0, at: 0, last: 0 }
+    }
+}
+
+/// The elements of a [`Packed`], in increasing order.
+#[derive(Clone)]
+struct PackedIter<const W: usize> {
+    p: Packed<W>,
+    i: u8,
+    at: usize,
+    last: u64,
+}
+
+impl<const W: usize> Iterator for PackedIter<W> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        if self.i == self.p.len {
+            return None;
+        }
+        if self.i == 0 {
+            self.last = get_bits(&self.p.words, 0, self.p.first_bits);
+            self.at = self.p.first_bits as usize;
+        } else {
+            self.last += 1 + get_bits(&self.p.words, self.at, self.p.gap_bits);
+            self.at += self.p.gap_bits as usize;
+        }
+        self.i += 1;
+        Some(self.last)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = (self.p.len - self.i) as usize;
+        (n, Some(n))
+    }
+}
+
+impl<const WORDS: usize> Default for SetU64Inline<WORDS> {
+    fn default() -> Self {
+        SetU64Inline::new()
+    }
+}
+
+impl<const WORDS: usize> SetU64Inline<WORDS> {
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        SetU64Inline(Inner::Inline(Packed::EMPTY))
+    }
+    /// Returns true if the elements are stored inline, with no heap
+    /// allocation.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Inner::Inline(_))
+    }
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        match self.0 {
+            Inner::Inline(ref p) => p.len as usize,
+            Inner::Heap(ref s) => s.len(),
+        }
+    }
+    /// Returns true if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns true if the set contains a value.
+    pub fn contains(&self, e: u64) -> bool {
+        match self.0 {
+            Inner::Inline(ref p) => p.iter().take_while(|&x| x <= e).any(|x| x == e),
+            Inner::Heap(ref s) => s.contains(e),
+        }
+    }
+    /// Adds a value to the set, returning true if it was not already
+    /// present.
+    pub fn insert(&mut self, e: u64) -> bool {
+        match self.0 {
+            Inner::Inline(p) => {
+                if self.contains(e) {
+                    return false;
+                }
+                let all = p.iter().merge(core::iter::once(e));
+                self.0 = match Packed::encode(all.clone()) {
+                    Some(p) => Inner::Inline(p),
+                    None => Inner::Heap(SetU64::from_sorted_iter(all)),
+                };
+                true
+            }
+            Inner::Heap(ref mut s) => s.insert(e),
+        }
+    }
+    /// Removes an element, and returns true if that element was present.
+    pub fn remove(&mut self, e: u64) -> bool {
+        match self.0 {
+            Inner::Inline(p) => {
+                if !self.contains(e) {
+                    return false;
+                }
+                // Joining two gaps can make the rest too wide to fit.
+                let rest = p.iter().filter(move |&x| x != e);
+                self.0 = match Packed::encode(rest.clone()) {
+                    Some(p) => Inner::Inline(p),
+                    None => Inner::Heap(SetU64::from_sorted_iter(rest)),
+                };
+                true
+            }
+            Inner::Heap(ref mut s) => {
+                if !s.remove(e) {
+                    return false;
+                }
+                if s.len() > 8*WORDS {
+                    return true;
+                }
+                let (mn, mx) = (s.iter().min().unwrap_or(0), s.iter().max().unwrap_or(0));
+                if Packed::<WORDS>::might_fit(s.len(), mn, mx) {
+                    // Few enough to put in order without allocating.
+                    let s: &SetU64 = s;
+                    let sorted = core::iter::successors(s.iter().min(),
+                                                        |&x| s.iter().filter(|&y| y > x).min());
+                    if let Some(p) = Packed::encode(sorted) {
+                        self.0 = Inner::Inline(p);
+                    }
+                }
+                true
+            }
+        }
+    }
+    /// Iterates over the elements, in increasing order while they are
+    /// inline and in no particular order once they are on the heap.
+    pub fn iter(&self) -> Iter<'_, WORDS> {
+        Iter(match self.0 {
+            Inner::Inline(ref p) => InnerIter::Inline(p.iter()),
+            Inner::Heap(ref s) => InnerIter::Heap(s.iter()),
+        })
+    }
+}
+
+/// An iterator over the elements of a [`SetU64Inline`].
+#[derive(Clone)]
+pub struct Iter<'a, const WORDS: usize>(InnerIter<'a, WORDS>);
+
+#[derive(Clone)]
+enum InnerIter<'a, const W: usize> {
+    Inline(PackedIter<W>),
+    Heap(setu64::Iter<'a>),
+}
+
+impl<'a, const WORDS: usize> Iterator for Iter<'a, WORDS> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        match self.0 {
+            InnerIter::Inline(ref mut i) => i.next(),
+            InnerIter::Heap(ref mut i) => i.next(),
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.0 {
+            InnerIter::Inline(ref i) => i.size_hint(),
+            InnerIter::Heap(ref i) => i.size_hint(),
+        }
+    }
+}
+impl<'a, const WORDS: usize> ExactSizeIterator for Iter<'a, WORDS> {}
+
+impl<'a, const WORDS: usize> IntoIterator for &'a SetU64Inline<WORDS> {
+    type Item = u64;
+    type IntoIter = Iter<'a, WORDS>;
+    fn into_iter(self) -> Iter<'a, WORDS> {
+        self.iter()
+    }
+}
+
+impl<const WORDS: usize> core::fmt::Debug for SetU64Inline<WORDS> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<const WORDS: usize> PartialEq for SetU64Inline<WORDS> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && other.iter().all(|x| self.contains(x))
+    }
+}
+impl<const WORDS: usize> Eq for SetU64Inline<WORDS> {}
+
+/// Hashes the elements as [`SetU64`] does, so that a set hashes alike
+/// inline and on the heap.
+impl<const WORDS: usize> core::hash::Hash for SetU64Inline<WORDS> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        match self.0 {
+            Inner::Inline(ref p) => crate::sets::hash_unordered(state, p.iter()),
+            Inner::Heap(ref s) => s.hash(state),
+        }
+    }
+}
+
+impl<const WORDS: usize> Extend<u64> for SetU64Inline<WORDS> {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, iter: I) {
+        for x in iter {
+            self.insert(x);
+        }
+    }
+}
+
+impl<const WORDS: usize> core::iter::FromIterator<u64> for SetU64Inline<WORDS> {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        let mut s = SetU64Inline::new();
+        s.extend(iter);
+        s
+    }
+}
+
+/// A set of [Fits64] values that keeps its elements in `WORDS` words
+/// of its own until they no longer fit.
+///
+/// This is to [`SetU64Inline`] what [`Set64`](crate::Set64) is to
+/// [`SetU64`]: each element is stored as its [`Fits64::to_u64`].  So
+/// while the elements are inline they are iterated in the order of
+/// those, and on the heap in no particular order.
+///
+/// # Examples
+///
+/// ```
+/// use tinyset::Set64Inline;
+///
+/// let mut s: Set64Inline<char, 2> = "hello world".chars().collect();
+/// assert!(s.is_inline());
+/// assert!(s.contains(&'w'));
+/// assert!(s.remove(&'w'));
+/// assert_eq!(s.len(), 7);
+/// ```
+pub struct Set64Inline<T: Fits64, const WORDS: usize>(SetU64Inline<WORDS>, PhantomData<T>);
+
+impl<T: Fits64, const WORDS: usize> Clone for Set64Inline<T, WORDS> {
+    fn clone(&self) -> Self {
+        Set64Inline(self.0.clone(), PhantomData)
+    }
+}
+
+impl<T: Fits64, const WORDS: usize> Default for Set64Inline<T, WORDS> {
+    fn default() -> Self {
+        Set64Inline::new()
+    }
+}
+
+impl<T: Fits64, const WORDS: usize> Set64Inline<T, WORDS> {
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        Set64Inline(SetU64Inline::new(), PhantomData)
+    }
+    /// Returns true if the elements are stored inline, with no heap
+    /// allocation.
+    pub fn is_inline(&self) -> bool {
+        self.0.is_inline()
+    }
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    /// Returns true if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Returns true if the set contains a value.
+    pub fn contains<R: core::borrow::Borrow<T>>(&self, value: R) -> bool {
+        self.0.contains(value.borrow().to_u64())
+    }
+    /// Adds a value to the set, returning true if it was not already
+    /// present.
+    pub fn insert(&mut self, elem: T) -> bool {
+        self.0.insert(elem.to_u64())
+    }
+    /// Removes an element, and returns true if that element was present.
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.0.remove(value.to_u64())
+    }
+    /// Iterates over the elements.
+    pub fn iter(&self) -> Iter64<'_, T, WORDS> {
+        Iter64(self.0.iter(), PhantomData)
+    }
+}
+
+/// An iterator over the elements of a [`Set64Inline`].
+#[derive(Clone)]
+pub struct Iter64<'a, T: Fits64, const WORDS: usize>(Iter<'a, WORDS>, PhantomData<T>);
+
+impl<'a, T: Fits64, const WORDS: usize> Iterator for Iter64<'a, T, WORDS> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.0.next().map(|x| unsafe { T::from_u64(x) })
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+impl<'a, T: Fits64, const WORDS: usize> ExactSizeIterator for Iter64<'a, T, WORDS> {}
+
+impl<'a, T: Fits64, const WORDS: usize> IntoIterator for &'a Set64Inline<T, WORDS> {
+    type Item = T;
+    type IntoIter = Iter64<'a, T, WORDS>;
+    fn into_iter(self) -> Iter64<'a, T, WORDS> {
+        self.iter()
+    }
+}
+
+impl<T: Fits64 + core::fmt::Debug, const WORDS: usize> core::fmt::Debug for Set64Inline<T, WORDS> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Fits64, const WORDS: usize> PartialEq for Set64Inline<T, WORDS> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl<T: Fits64, const WORDS: usize> Eq for Set64Inline<T, WORDS> {}
+
+impl<T: Fits64, const WORDS: usize> core::hash::Hash for Set64Inline<T, WORDS> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T: Fits64, const WORDS: usize> Extend<T> for Set64Inline<T, WORDS> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.0.extend(iter.into_iter().map(|x| x.to_u64()))
+    }
+}
+
+impl<T: Fits64, const WORDS: usize> core::iter::FromIterator<T> for Set64Inline<T, WORDS> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut s = Set64Inline::new();
+        s.extend(iter);
+        s
+    }
+}
+
+#[test]
+fn test_bits() {
+    let mut words = [0u64; 3];
+    let mut at = 0;
+    for n in 0..=64u8 {
+        if at + n as usize > 192 {
+            break;
+        }
+        let x = if n == 0 { 0 } else { u64::MAX >> (64 - n) ^ 5 };
+        put_bits(&mut words, at, n, x & if n == 64 { u64::MAX } else { (1 << n) - 1 });
+        assert_eq!(get_bits(&words, at, n), x & if n == 64 { u64::MAX } else { (1 << n) - 1 });
+        at += n as usize;
+    }
+}
+
+#[cfg(test)]
+fn check_inline<const W: usize>(ops: &[(bool, u64)], elems: impl Fn(u64) -> u64) {
+    let mut s: SetU64Inline<W> = SetU64Inline::new();
+    let mut model = alloc::collections::BTreeSet::new();
+    for &(remove, x) in ops {
+        let x = elems(x);
+        if !remove {
+            assert_eq!(s.insert(x), model.insert(x));
+        } else {
+            let was_inline = s.is_inline();
+            let removed = s.remove(x);
+            assert_eq!(removed, model.remove(&x));
+            // A set on the heap moves back once it is small enough.
+            let fits = Packed::<W>::encode(model.iter().cloned()).is_some();
+            let small = removed && model.len() <= 8*W;
+            let (mn, mx) = (model.iter().next().cloned(), model.iter().next_back().cloned());
+            assert!(!fits || Packed::<W>::might_fit(model.len(), mn.unwrap_or(0), mx.unwrap_or(0)));
+            assert_eq!(s.is_inline(), fits && (was_inline || small));
+        }
+        assert_eq!(s.len(), model.len());
+        assert_eq!(s.contains(x), model.contains(&x));
+        if s.is_inline() {
+            assert!(s.iter().eq(model.iter().cloned()));
+        }
+    }
+    let mut v: Vec<u64> = s.iter().collect();
+    v.sort_unstable();
+    assert!(v.iter().eq(model.iter()));
+}
+
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+proptest!{
+    #[test]
+    fn check_inline_sets(ops in prop::collection::vec((prop::bool::weighted(0.4), 0u64..40), 0usize..50)) {
+        check_inline::<0>(&ops, |x| x);
+        check_inline::<1>(&ops, |x| x);
+        check_inline::<2>(&ops, |x| x * 1000 + 500_000);
+        check_inline::<2>(&ops, |x| x << 58);
+        check_inline::<3>(&ops, |x| x * x * x);
+    }
+}
+
+#[test]
+fn test_setu64inline() {
+
+    let a: SetU64Inline<2> = (0..10).map(|i| 500_000 + 1000*i).collect();
+    assert!(a.is_inline());
+    let b: SetU64Inline<2> = (0..20).map(|i| 500_000 + 1000*i).collect();
+    assert!(!b.is_inline());
+    let consecutive: SetU64Inline<1> = (1000..1255).collect();
+    assert!(consecutive.is_inline());
+    let mut b2: SetU64Inline<2> = (10..20).rev().map(|i| 500_000 + 1000*i).collect();
+    b2.extend(a.iter());
+    assert_eq!(b, b2);
+    for i in 10..20 {
+        b2.remove(500_000 + 1000*i);
+    }
+    assert!(b2.is_inline());
+    assert_eq!(a, b2);
+    // Sets that are spread too thin are ruled out before sorting.
+    assert!(Packed::<2>::might_fit(10, 500_000, 509_000));
+    assert!(!Packed::<2>::might_fit(20, 500_000, 519_000));
+    assert!(!Packed::<1>::might_fit(8, 1 << 40, (1 << 40) + (7 << 20)));
+    assert!(Packed::<0>::might_fit(1, 0, 0) && !Packed::<0>::might_fit(1, 1, 1));
+    // Too many elements to move back inline, but a set on the heap is
+    // still equal to the same elements inline, and hashes alike.
+    let mut sets = crate::interner::SetInterner::new();
+    let id = sets.intern(consecutive.clone());
+    let mut c: SetU64Inline<1> = (1000..1255).chain(Some(u64::MAX)).collect();
+    c.remove(u64::MAX);
+    assert!(!c.is_inline());
+    assert_eq!(c, consecutive);
+    assert_eq!(sets.intern(c), id);
+
+    let mut s: Set64Inline<i32, 1> = (-3..4).collect();
+    assert!(s.is_inline());
+    assert!(s.contains(-3) && !s.contains(4));
+    s.insert(i32::MIN);
+    assert!(!s.is_inline());
+    assert!(s.remove(&i32::MIN));
+    assert!(s.is_inline());
+    let mut v: Vec<i32> = s.iter().collect();
+    v.sort_unstable();
+    assert_eq!(v, (-3..4).collect::<Vec<_>>());
+}
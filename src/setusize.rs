@@ -18,9 +18,22 @@ type Item = u64;
 type Item = u32;
 
 /// A set for usize elements.
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SetUsize(Internal);
 
+impl core::fmt::Debug for SetUsize {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Prints the elements in increasing order, as `{1, 2, 3}`.
+impl core::fmt::Display for SetUsize {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        crate::sets::fmt_sorted(f, self.cursor_at(0))
+    }
+}
+
 impl Default for SetUsize {
     /// Creates an empty set..
     fn default() -> Self {
@@ -57,17 +70,47 @@ impl SetUsize {
     pub fn remove(&mut self, value: usize) -> bool {
         self.0.remove(value as Item)
     }
+    /// Adds every value in `range` to the set.
+    pub fn insert_range(&mut self, range: impl core::ops::RangeBounds<usize>) {
+        self.0.insert_range(item_bounds(range))
+    }
+    /// Removes every value in `range` from the set.
+    pub fn remove_range(&mut self, range: impl core::ops::RangeBounds<usize>) {
+        self.0.remove_range(item_bounds(range))
+    }
+    /// Returns true if the set contains every value in `range`.
+    pub fn contains_range(&self, range: impl core::ops::RangeBounds<usize>) -> bool {
+        self.0.contains_range(item_bounds(range))
+    }
     /// Iterate
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item=usize> + 'a {
-        self.0.iter().map(|x| x as usize)
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter(self.0.iter())
     }
     /// Drain
-    pub fn drain<'a>(&'a mut self) -> impl Iterator<Item=usize> + 'a {
-        self.0.drain().map(|x| x as usize)
+    pub fn drain<'a>(&'a mut self) -> Drain<'a> {
+        Drain(self.0.drain())
     }
+    /// A cursor at the first element that is at least `x`, which then
+    /// moves through the set in increasing order and can
+    /// [`seek`](Cursor::seek) ahead.
+    pub fn cursor_at<'a>(&'a self, x: usize) -> Cursor<'a> {
+        Cursor(self.0.cursor_at(x as Item))
+    }
+}
+
+/// The bounds of `range` as bounds on the internal set.
+fn item_bounds(range: impl core::ops::RangeBounds<usize>)
+               -> (core::ops::Bound<Item>, core::ops::Bound<Item>) {
+    use core::ops::Bound;
+    let convert = |b: Bound<&usize>| match b {
+        Bound::Included(&x) => Bound::Included(x as Item),
+        Bound::Excluded(&x) => Bound::Excluded(x as Item),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (convert(range.start_bound()), convert(range.end_bound()))
 }
 
-impl std::iter::FromIterator<usize> for SetUsize {
+impl core::iter::FromIterator<usize> for SetUsize {
     fn from_iter<I: IntoIterator<Item=usize>>(iter: I) -> Self {
         let iter = iter.into_iter();
         let (sz,_) = iter.size_hint();
@@ -79,11 +122,46 @@ impl std::iter::FromIterator<usize> for SetUsize {
     }
 }
 
+#[cfg(feature = "rayon")]
+impl rayon::iter::FromParallelIterator<usize> for SetUsize {
+    fn from_par_iter<I>(par_iter: I) -> Self
+        where I: rayon::iter::IntoParallelIterator<Item = usize>
+    {
+        use rayon::iter::ParallelIterator;
+        SetUsize(par_iter.into_par_iter().map(|x| x as Item).collect())
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl rayon::iter::ParallelExtend<usize> for SetUsize {
+    fn par_extend<I>(&mut self, par_iter: I)
+        where I: rayon::iter::IntoParallelIterator<Item = usize>
+    {
+        use rayon::iter::ParallelIterator;
+        self.0.par_extend(par_iter.into_par_iter().map(|x| x as Item))
+    }
+}
+
 #[cfg(target_pointer_width = "64")]
 type InternalIter = crate::setu64::IntoIter;
 #[cfg(target_pointer_width = "32")]
 type InternalIter = crate::setu32::IntoIter;
 
+#[cfg(target_pointer_width = "64")]
+type InternalBorrowedIter<'a> = crate::setu64::Iter<'a>;
+#[cfg(target_pointer_width = "32")]
+type InternalBorrowedIter<'a> = crate::setu32::Iter<'a>;
+
+#[cfg(target_pointer_width = "64")]
+type InternalDrain<'a> = crate::setu64::Drain<'a>;
+#[cfg(target_pointer_width = "32")]
+type InternalDrain<'a> = crate::setu32::Drain<'a>;
+
+#[cfg(target_pointer_width = "64")]
+type InternalCursor<'a> = crate::setu64::Cursor<'a>;
+#[cfg(target_pointer_width = "32")]
+type InternalCursor<'a> = crate::setu32::Cursor<'a>;
+
 /// An iterator.
 pub struct IntoIter( InternalIter );
 
@@ -114,6 +192,132 @@ impl Iterator for IntoIter {
         self.0.max().map(|x| x as usize)
     }
 }
+impl DoubleEndedIterator for IntoIter {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|x| x as usize)
+    }
+}
+impl ExactSizeIterator for IntoIter {}
+impl core::iter::FusedIterator for IntoIter {}
+
+/// An iterator over the elements of a [`SetUsize`].
+#[derive(Debug, Clone)]
+pub struct Iter<'a>( InternalBorrowedIter<'a> );
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = usize;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|x| x as usize)
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+    #[inline]
+    fn count(self) -> usize {
+        self.0.count()
+    }
+    #[inline]
+    fn last(self) -> Option<Self::Item> {
+        self.0.last().map(|x| x as usize)
+    }
+    #[inline]
+    fn min(self) -> Option<Self::Item> {
+        self.0.min().map(|x| x as usize)
+    }
+    #[inline]
+    fn max(self) -> Option<Self::Item> {
+        self.0.max().map(|x| x as usize)
+    }
+}
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|x| x as usize)
+    }
+}
+impl<'a> ExactSizeIterator for Iter<'a> {}
+impl<'a> core::iter::FusedIterator for Iter<'a> {}
+
+/// A draining iterator over the elements of a [`SetUsize`].
+pub struct Drain<'a>( InternalDrain<'a> );
+
+impl<'a> Iterator for Drain<'a> {
+    type Item = usize;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|x| x as usize)
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+impl<'a> DoubleEndedIterator for Drain<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|x| x as usize)
+    }
+}
+impl<'a> ExactSizeIterator for Drain<'a> {}
+impl<'a> core::iter::FusedIterator for Drain<'a> {}
+
+/// A cursor over the elements of a [`SetUsize`] in increasing order.
+#[derive(Debug, Clone)]
+pub struct Cursor<'a>( InternalCursor<'a> );
+
+impl<'a> Cursor<'a> {
+    /// The element under the cursor, which `next` will return, or
+    /// `None` at the end of the set.
+    pub fn peek(&self) -> Option<usize> {
+        self.0.peek().map(|x| x as usize)
+    }
+    /// Move ahead to the first element that is at least `x`, and
+    /// return it.  A cursor never moves back.
+    pub fn seek(&mut self, x: usize) -> Option<usize> {
+        self.0.seek(x as Item).map(|x| x as usize)
+    }
+}
+
+impl<'a> Iterator for Cursor<'a> {
+    type Item = usize;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|x| x as usize)
+    }
+}
+impl<'a> core::iter::FusedIterator for Cursor<'a> {}
+
+#[cfg(all(feature = "rayon", target_pointer_width = "64"))]
+type InternalParIter<'a> = crate::setu64::ParIter<'a>;
+#[cfg(all(feature = "rayon", target_pointer_width = "32"))]
+type InternalParIter<'a> = crate::setu32::ParIter<'a>;
+
+/// A parallel iterator over the elements of a [`SetUsize`].
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone)]
+pub struct ParIter<'a>( InternalParIter<'a> );
+
+#[cfg(feature = "rayon")]
+impl<'a> rayon::iter::ParallelIterator for ParIter<'a> {
+    type Item = usize;
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+        where C: rayon::iter::plumbing::UnindexedConsumer<usize>
+    {
+        self.0.map(|x| x as usize).drive_unindexed(consumer)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a> rayon::iter::IntoParallelIterator for &'a SetUsize {
+    type Item = usize;
+    type Iter = ParIter<'a>;
+    fn into_par_iter(self) -> ParIter<'a> {
+        ParIter(rayon::iter::IntoParallelIterator::into_par_iter(&self.0))
+    }
+}
 
 impl IntoIterator for SetUsize {
     type Item = usize;
@@ -124,6 +328,84 @@ impl IntoIterator for SetUsize {
     }
 }
 
+impl<'a> IntoIterator for &'a SetUsize {
+    type Item = usize;
+    type IntoIter = Iter<'a>;
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl Extend<usize> for SetUsize {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        self.0.extend(iter.into_iter().map(|x| x as Item))
+    }
+}
+
+impl<'a> Extend<&'a usize> for SetUsize {
+    fn extend<I: IntoIterator<Item = &'a usize>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().cloned())
+    }
+}
+
+impl<const N: usize> From<[usize; N]> for SetUsize {
+    fn from(a: [usize; N]) -> Self {
+        a.iter().cloned().collect()
+    }
+}
+
+impl From<alloc::vec::Vec<usize>> for SetUsize {
+    fn from(v: alloc::vec::Vec<usize>) -> Self {
+        SetUsize(v.into_iter().map(|x| x as Item).collect())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S> From<std::collections::HashSet<usize, S>> for SetUsize {
+    fn from(s: std::collections::HashSet<usize, S>) -> Self {
+        SetUsize(s.into_iter().map(|x| x as Item).collect())
+    }
+}
+
+impl From<SetUsize> for alloc::collections::BTreeSet<usize> {
+    fn from(s: SetUsize) -> Self {
+        s.into_iter().collect()
+    }
+}
+
+impl core::ops::BitOr<&SetUsize> for &SetUsize {
+    type Output = SetUsize;
+    /// Returns the union of `self` and `rhs` as a new set.
+    fn bitor(self, rhs: &SetUsize) -> SetUsize {
+        SetUsize(&self.0 | &rhs.0)
+    }
+}
+
+impl core::ops::BitAnd<&SetUsize> for &SetUsize {
+    type Output = SetUsize;
+    /// Returns the intersection of `self` and `rhs` as a new set.
+    fn bitand(self, rhs: &SetUsize) -> SetUsize {
+        SetUsize(&self.0 & &rhs.0)
+    }
+}
+
+impl core::ops::Sub<&SetUsize> for &SetUsize {
+    type Output = SetUsize;
+    /// Returns the elements of `self` that are not in `rhs` as a new set.
+    fn sub(self, rhs: &SetUsize) -> SetUsize {
+        SetUsize(&self.0 - &rhs.0)
+    }
+}
+
+impl core::ops::BitXor<&SetUsize> for &SetUsize {
+    type Output = SetUsize;
+    /// Returns the elements in exactly one of `self` and `rhs` as a new set.
+    fn bitxor(self, rhs: &SetUsize) -> SetUsize {
+        SetUsize(&self.0 ^ &rhs.0)
+    }
+}
+
+#[cfg(test)]
 impl crate::copyset::CopySet for SetUsize {
     type Item = usize;
     type Iter = IntoIter;
@@ -147,6 +429,21 @@ impl crate::copyset::CopySet for SetUsize {
     }
 }
 
+#[test]
+fn test_std_traits() {
+    use alloc::collections::BTreeSet;
+    let a = SetUsize::from([4, 1, 9]);
+    let mut b = SetUsize::from(vec![9, 2]);
+    assert_eq!(a.to_string(), "{1, 4, 9}");
+    assert_eq!(format!("{:?}", SetUsize::from([3])), "{3}");
+    assert_eq!(BTreeSet::from(&a | &b), [1, 2, 4, 9].iter().cloned().collect());
+    assert_eq!(BTreeSet::from(&a - &b), [1, 4].iter().cloned().collect());
+    assert_eq!(BTreeSet::from(&a ^ &b), [1, 2, 4].iter().cloned().collect());
+    assert!(a < b);
+    b.extend(&a);
+    assert_eq!(&b & &a, a);
+}
+
 #[cfg(test)]
 use proptest::prelude::*;
 #[cfg(test)]
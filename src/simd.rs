@@ -0,0 +1,355 @@
+//! Vectorized kernels for probing tables and combining bitsets.
+//!
+//! On x86_64 we use AVX2 when the CPU has it and SSE2 (which every
+//! x86_64 CPU has) otherwise.  Checking for AVX2 at run time needs
+//! `std`; without it we only use AVX2 if the crate was compiled for a
+//! target that has it.  Other architectures use plain loops.  Every
+//! kernel returns exactly what its scalar version in [`scalar`] does,
+//! whichever path it takes.
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+#[inline]
+fn has_avx2() -> bool {
+    std::is_x86_feature_detected!("avx2")
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+#[inline]
+fn has_avx2() -> bool {
+    cfg!(target_feature = "avx2")
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+#[inline]
+fn has_popcnt() -> bool {
+    std::is_x86_feature_detected!("popcnt")
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+#[inline]
+fn has_popcnt() -> bool {
+    cfg!(target_feature = "popcnt")
+}
+
+/// The plain loops that the vectorized kernels must agree with.
+pub mod scalar {
+    /// The first slot that is empty or holds key `k`.
+    pub fn find_slot_u64(a: &[u64], k: u64, offset: u64) -> Option<usize> {
+        a.iter().position(|&x| x == 0 || x >> offset == k)
+    }
+    /// The first slot that is empty or holds key `k`.
+    pub fn find_slot_u32(a: &[u32], k: u32, offset: u32) -> Option<usize> {
+        a.iter().position(|&x| x == 0 || x >> offset == k)
+    }
+    /// Set each word of `a` to its bitwise or with the matching word
+    /// of `b`.
+    pub fn or_words<T: Copy + core::ops::BitOrAssign>(a: &mut [T], b: &[T]) {
+        for (x, &y) in a.iter_mut().zip(b) {
+            *x |= y;
+        }
+    }
+    /// Set each word of `a` to its bitwise and with the matching word
+    /// of `b`.
+    pub fn and_words<T: Copy + core::ops::BitAndAssign>(a: &mut [T], b: &[T]) {
+        for (x, &y) in a.iter_mut().zip(b) {
+            *x &= y;
+        }
+    }
+    /// The number of bits set in `a`.
+    pub fn count_ones_u64(a: &[u64]) -> usize {
+        a.iter().map(|w| w.count_ones() as usize).sum()
+    }
+    /// The number of bits set in `a`.
+    pub fn count_ones_u32(a: &[u32]) -> usize {
+        a.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
+
+/// The first slot in `a` that is empty or holds key `k` (stored above
+/// the low `offset` bits).
+#[inline]
+pub fn find_slot_u64(a: &[u64], k: u64, offset: u64) -> Option<usize> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            return unsafe { find_slot_u64_avx2(a, k, offset) };
+        }
+        unsafe { find_slot_u64_sse2(a, k, offset) }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    scalar::find_slot_u64(a, k, offset)
+}
+
+/// The first slot in `a` that is empty or holds key `k` (stored above
+/// the low `offset` bits).
+#[inline]
+pub fn find_slot_u32(a: &[u32], k: u32, offset: u32) -> Option<usize> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            return unsafe { find_slot_u32_avx2(a, k, offset) };
+        }
+        unsafe { find_slot_u32_sse2(a, k, offset) }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    scalar::find_slot_u32(a, k, offset)
+}
+
+/// Set each word of `a` to its bitwise or with the matching word of `b`.
+pub fn or_words<T: Copy + core::ops::BitOrAssign>(a: &mut [T], b: &[T]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            return unsafe { or_words_avx2(a, b) };
+        }
+    }
+    // The compiler vectorizes this with SSE2 by itself.
+    scalar::or_words(a, b)
+}
+
+/// Set each word of `a` to its bitwise and with the matching word of `b`.
+pub fn and_words<T: Copy + core::ops::BitAndAssign>(a: &mut [T], b: &[T]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            return unsafe { and_words_avx2(a, b) };
+        }
+    }
+    // The compiler vectorizes this with SSE2 by itself.
+    scalar::and_words(a, b)
+}
+
+/// The number of bits set in `a`.
+pub fn count_ones_u64(a: &[u64]) -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(a.as_ptr() as *const u8, a.len()*8)
+        };
+        if has_avx2() {
+            return unsafe { count_ones_avx2(bytes) };
+        } else if has_popcnt() {
+            return unsafe { count_ones_popcnt(bytes) };
+        }
+    }
+    scalar::count_ones_u64(a)
+}
+
+/// The number of bits set in `a`.
+pub fn count_ones_u32(a: &[u32]) -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(a.as_ptr() as *const u8, a.len()*4)
+        };
+        if has_avx2() {
+            return unsafe { count_ones_avx2(bytes) };
+        } else if has_popcnt() {
+            return unsafe { count_ones_popcnt(bytes) };
+        }
+    }
+    scalar::count_ones_u32(a)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn find_slot_u64_avx2(a: &[u64], k: u64, offset: u64) -> Option<usize> {
+    let zero = _mm256_setzero_si256();
+    let key = _mm256_set1_epi64x(k as i64);
+    let shift = _mm_cvtsi64_si128(offset as i64);
+    let mut i = 0;
+    while i + 4 <= a.len() {
+        let x = _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i);
+        let hit = _mm256_or_si256(_mm256_cmpeq_epi64(x, zero),
+                                  _mm256_cmpeq_epi64(_mm256_srl_epi64(x, shift), key));
+        let mask = _mm256_movemask_pd(_mm256_castsi256_pd(hit));
+        if mask != 0 {
+            return Some(i + mask.trailing_zeros() as usize);
+        }
+        i += 4;
+    }
+    scalar::find_slot_u64(&a[i..], k, offset).map(|j| i + j)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn find_slot_u64_sse2(a: &[u64], k: u64, offset: u64) -> Option<usize> {
+    // SSE2 can only compare 32-bit lanes, so a 64-bit lane matches
+    // when both of its halves do.
+    unsafe fn eq64(x: __m128i, y: __m128i) -> __m128i {
+        let eq = _mm_cmpeq_epi32(x, y);
+        _mm_and_si128(eq, _mm_shuffle_epi32(eq, 0b10_11_00_01))
+    }
+    let zero = _mm_setzero_si128();
+    let key = _mm_set1_epi64x(k as i64);
+    let shift = _mm_cvtsi64_si128(offset as i64);
+    let mut i = 0;
+    while i + 2 <= a.len() {
+        let x = _mm_loadu_si128(a.as_ptr().add(i) as *const __m128i);
+        let hit = _mm_or_si128(eq64(x, zero), eq64(_mm_srl_epi64(x, shift), key));
+        let mask = _mm_movemask_pd(_mm_castsi128_pd(hit));
+        if mask != 0 {
+            return Some(i + mask.trailing_zeros() as usize);
+        }
+        i += 2;
+    }
+    scalar::find_slot_u64(&a[i..], k, offset).map(|j| i + j)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn find_slot_u32_avx2(a: &[u32], k: u32, offset: u32) -> Option<usize> {
+    let zero = _mm256_setzero_si256();
+    let key = _mm256_set1_epi32(k as i32);
+    let shift = _mm_cvtsi32_si128(offset as i32);
+    let mut i = 0;
+    while i + 8 <= a.len() {
+        let x = _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i);
+        let hit = _mm256_or_si256(_mm256_cmpeq_epi32(x, zero),
+                                  _mm256_cmpeq_epi32(_mm256_srl_epi32(x, shift), key));
+        let mask = _mm256_movemask_ps(_mm256_castsi256_ps(hit));
+        if mask != 0 {
+            return Some(i + mask.trailing_zeros() as usize);
+        }
+        i += 8;
+    }
+    scalar::find_slot_u32(&a[i..], k, offset).map(|j| i + j)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn find_slot_u32_sse2(a: &[u32], k: u32, offset: u32) -> Option<usize> {
+    let zero = _mm_setzero_si128();
+    let key = _mm_set1_epi32(k as i32);
+    let shift = _mm_cvtsi32_si128(offset as i32);
+    let mut i = 0;
+    while i + 4 <= a.len() {
+        let x = _mm_loadu_si128(a.as_ptr().add(i) as *const __m128i);
+        let hit = _mm_or_si128(_mm_cmpeq_epi32(x, zero),
+                               _mm_cmpeq_epi32(_mm_srl_epi32(x, shift), key));
+        let mask = _mm_movemask_ps(_mm_castsi128_ps(hit));
+        if mask != 0 {
+            return Some(i + mask.trailing_zeros() as usize);
+        }
+        i += 4;
+    }
+    scalar::find_slot_u32(&a[i..], k, offset).map(|j| i + j)
+}
+
+// These two are the scalar loops, compiled with AVX2 enabled so that
+// the compiler vectorizes them 256 bits at a time.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn or_words_avx2<T: Copy + core::ops::BitOrAssign>(a: &mut [T], b: &[T]) {
+    scalar::or_words(a, b)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn and_words_avx2<T: Copy + core::ops::BitAndAssign>(a: &mut [T], b: &[T]) {
+    scalar::and_words(a, b)
+}
+
+/// Count bits by looking up each nibble in a table, 32 bytes at a
+/// time, as described by Muła, Kurz and Lemire in "Faster Population
+/// Counts Using AVX2 Instructions".
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn count_ones_avx2(a: &[u8]) -> usize {
+    let table = _mm256_setr_epi8(0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+                                 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4);
+    let low = _mm256_set1_epi8(0x0f);
+    let mut total = _mm256_setzero_si256();
+    let mut i = 0;
+    while i + 32 <= a.len() {
+        let x = _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i);
+        let lo = _mm256_shuffle_epi8(table, _mm256_and_si256(x, low));
+        let hi = _mm256_shuffle_epi8(table, _mm256_and_si256(_mm256_srli_epi16(x, 4), low));
+        // Sum the (at most 8) bits of each byte into four 64-bit lanes.
+        total = _mm256_add_epi64(total, _mm256_sad_epu8(_mm256_add_epi8(lo, hi),
+                                                        _mm256_setzero_si256()));
+        i += 32;
+    }
+    let mut lanes = [0u64; 4];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, total);
+    let tail: usize = a[i..].iter().map(|b| b.count_ones() as usize).sum();
+    lanes.iter().sum::<u64>() as usize + tail
+}
+
+/// The scalar loop, compiled to use the `popcnt` instruction.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "popcnt")]
+unsafe fn count_ones_popcnt(a: &[u8]) -> usize {
+    let mut chunks = a.chunks_exact(8);
+    let mut total = 0;
+    for c in &mut chunks {
+        let mut w = [0; 8];
+        w.copy_from_slice(c);
+        total += u64::from_ne_bytes(w).count_ones() as usize;
+    }
+    total + chunks.remainder().iter().map(|b| b.count_ones() as usize).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A simple generator, so that we test the same inputs every time.
+    fn words(n: usize, seed: u64) -> Vec<u64> {
+        let mut x = seed;
+        (0..n).map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        }).collect()
+    }
+
+    #[test]
+    fn test_find_slot() {
+        for n in 0..40 {
+            for &offset in &[0, 5, 31] {
+                let mut a = words(n, n as u64 + 1);
+                // Plant some empty slots and some copies of the key.
+                for (i, x) in a.iter_mut().enumerate() {
+                    if i % 7 == 3 {
+                        *x = 0;
+                    } else if i % 11 == 5 {
+                        *x = (12345 << offset) | (i as u64 & ((1 << offset) - 1));
+                    }
+                }
+                for start in 0..n {
+                    let a = &a[start..];
+                    assert_eq!(find_slot_u64(a, 12345, offset),
+                               scalar::find_slot_u64(a, 12345, offset));
+                    let b: Vec<u32> = a.iter().map(|&x| x as u32).collect();
+                    assert_eq!(find_slot_u32(&b, 12345, offset as u32),
+                               scalar::find_slot_u32(&b, 12345, offset as u32));
+                    assert_eq!(find_slot_u32(&b, 0, offset as u32),
+                               scalar::find_slot_u32(&b, 0, offset as u32));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_words() {
+        for n in 0..100 {
+            let a = words(n, 3);
+            let b = words(n, 5);
+            let (mut x, mut y) = (a.clone(), a.clone());
+            or_words(&mut x, &b);
+            scalar::or_words(&mut y, &b);
+            assert_eq!(x, y);
+            and_words(&mut x, &a);
+            scalar::and_words(&mut y, &a);
+            assert_eq!(x, y);
+            assert_eq!(count_ones_u64(&x), scalar::count_ones_u64(&x));
+            let z: Vec<u32> = a.iter().map(|&w| w as u32).collect();
+            assert_eq!(count_ones_u32(&z), scalar::count_ones_u32(&z));
+        }
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright 2019 David Roundy
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A set of `u64` that fits in one word and never allocates.
+
+use crate::error::Full;
+use crate::setu64::{SetU64, Tiny};
+
+/// The most elements a [`TinySetU64`] can hold.
+const MAX_LEN: usize = 7;
+
+/// A set of `u64` held in a single `usize`, using the same encoding
+/// as a small [`SetU64`], which never touches the heap.
+///
+/// The elements are stored sorted, the smallest as it is and each
+/// later one as its distance from the one before, splitting the word
+/// more finely the more elements there are.  On a 64-bit target that
+/// is one element below 2<sup>61</sup>, or (say) four whose smallest
+/// is below 2<sup>25</sup> and which are spaced less than 4096 apart,
+/// up to seven.  An insert that would not fit returns [`Full`].
+///
+/// Because it is `Copy` and its [bits](TinySetU64::to_bits) are a
+/// `usize`, the set can live in an `AtomicUsize`.
+///
+/// # Examples
+///
+/// ```
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use tinyset::TinySetU64;
+///
+/// static PENDING: AtomicUsize = AtomicUsize::new(TinySetU64::new().to_bits());
+///
+/// // Add 3 without taking a lock.
+/// let mut old = PENDING.load(Ordering::Acquire);
+/// loop {
+///     let mut s = TinySetU64::from_bits(old).unwrap();
+///     s.try_insert(3).unwrap();
+///     match PENDING.compare_exchange(old, s.to_bits(), Ordering::AcqRel, Ordering::Acquire) {
+///         Ok(_) => break,
+///         Err(x) => old = x,
+///     }
+/// }
+/// let mut s = TinySetU64::from_bits(PENDING.load(Ordering::Acquire)).unwrap();
+/// assert!(s.contains(3));
+/// assert!(s.try_insert(1 << 62).is_err());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TinySetU64(usize);
+
+impl TinySetU64 {
+    /// An empty set.
+    pub const fn new() -> Self {
+        TinySetU64(0)
+    }
+    /// Returns the number of elements.
+    pub const fn len(self) -> usize {
+        self.0 & 7
+    }
+    /// Returns true if the set is empty.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+    /// The word the set is stored in.
+    pub const fn to_bits(self) -> usize {
+        self.0
+    }
+    /// The set stored in `bits`, if it is the word of some set.
+    pub fn from_bits(bits: usize) -> Option<Self> {
+        if bits == 0 {
+            return Some(TinySetU64(0));
+        }
+        let mut v = [0; MAX_LEN];
+        let n = fill(&mut v, Tiny::from_usize(bits));
+        // Every word decodes to some elements, but only one word holds
+        // them, which is the one we would have built.
+        Tiny::new_sorted_deduped(&v[..n])
+            .filter(|t| t.to_usize() == bits)
+            .map(|_| TinySetU64(bits))
+    }
+    /// Returns true if the set contains `e`.
+    pub fn contains(self, e: u64) -> bool {
+        self.tiny().contains(e)
+    }
+    /// Adds `e`, returning whether it was new, or [`Full`] if the set
+    /// cannot hold it.
+    pub fn try_insert(&mut self, e: u64) -> Result<bool, Full> {
+        let t = self.tiny();
+        let new = if t.sz == 0 { Tiny::from_singleton(e) } else { t.insert(e) };
+        let new = new.ok_or(Full)?;
+        self.0 = new.to_usize();
+        Ok(new.sz != t.sz)
+    }
+    /// Removes `e`, returning whether it was there.
+    ///
+    /// This fails with [`Full`] in the rare case that the elements left
+    /// do not fit: fewer elements give each distance more bits, but
+    /// with six elements the smallest has no more bits than with five,
+    /// so removing it can leave a smallest element that is too big.
+    pub fn try_remove(&mut self, e: u64) -> Result<bool, Full> {
+        let t = self.tiny();
+        if !t.contains(e) {
+            return Ok(false);
+        }
+        let mut v = [0; MAX_LEN];
+        let n = fill(&mut v, t.filter(|&x| x != e));
+        self.0 = if n == 0 {
+            0
+        } else {
+            Tiny::new_sorted_deduped(&v[..n]).ok_or(Full)?.to_usize()
+        };
+        Ok(true)
+    }
+    /// Iterates over the elements in order.
+    pub fn iter(self) -> Iter {
+        Iter(self.tiny())
+    }
+    fn tiny(self) -> Tiny {
+        Tiny::from_usize(self.0)
+    }
+}
+
+/// Copy the elements into `v`, returning how many there were.
+fn fill(v: &mut [u64; MAX_LEN], elems: impl Iterator<Item = u64>) -> usize {
+    let mut n = 0;
+    for (slot, x) in v.iter_mut().zip(elems) {
+        *slot = x;
+        n += 1;
+    }
+    n
+}
+
+/// An iterator over the elements of a [`TinySetU64`], in order.
+#[derive(Clone)]
+pub struct Iter(Tiny);
+
+impl Iterator for Iter {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        self.0.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+impl DoubleEndedIterator for Iter {
+    fn next_back(&mut self) -> Option<u64> {
+        self.0.next_back()
+    }
+}
+impl ExactSizeIterator for Iter {}
+impl core::iter::FusedIterator for Iter {}
+
+impl IntoIterator for TinySetU64 {
+    type Item = u64;
+    type IntoIter = Iter;
+    fn into_iter(self) -> Iter {
+        self.iter()
+    }
+}
+
+impl core::fmt::Debug for TinySetU64 {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl core::fmt::Display for TinySetU64 {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        crate::sets::fmt_sorted(f, self.iter())
+    }
+}
+
+impl PartialOrd for TinySetU64 {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for TinySetU64 {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl From<TinySetU64> for SetU64 {
+    fn from(s: TinySetU64) -> SetU64 {
+        s.iter().collect()
+    }
+}
+
+#[cfg(test)]
+fn check_tiny_ops(ops: &[(bool, u64, u64)]) {
+    use alloc::collections::BTreeSet;
+    for &scale in &[1u64, 97, 1 << 12, 1 << 20, 1 << 40] {
+        let mut s = TinySetU64::new();
+        let mut model = BTreeSet::new();
+        for &(remove, hi, lo) in ops {
+            let x = hi * scale + lo;
+            let before = s;
+            if remove {
+                match s.try_remove(x) {
+                    Ok(was) => assert_eq!(was, model.remove(&x)),
+                    Err(Full) => assert_eq!(s, before),
+                }
+            } else {
+                match s.try_insert(x) {
+                    Ok(new) => assert_eq!(new, model.insert(x)),
+                    Err(Full) => {
+                        assert_eq!(s, before);
+                        assert!(!model.contains(&x));
+                    }
+                }
+            }
+            assert_eq!(s.len(), model.len());
+            assert_eq!(s.contains(x), model.contains(&x));
+            assert!(s.iter().eq(model.iter().cloned()));
+            assert!(s.iter().rev().eq(model.iter().rev().cloned()));
+            assert_eq!(TinySetU64::from_bits(s.to_bits()), Some(s));
+            let big = SetU64::from(s);
+            assert!(big.iter().eq(model.iter().cloned()));
+        }
+    }
+}
+
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+proptest!{
+    #[test]
+    fn check_tiny_sets(ops in prop::collection::vec((prop::bool::weighted(0.3), 0u64..12, 0u64..3), 0usize..100)) {
+        check_tiny_ops(&ops);
+    }
+}
+
+#[test]
+fn test_tinysetu64() {
+    // Seven small elements fit, and an eighth never does.
+    let mut s = TinySetU64::new();
+    for x in 0..7 {
+        assert_eq!(s.try_insert(x), Ok(true));
+    }
+    assert_eq!(s.try_insert(3), Ok(false));
+    assert_eq!(s.try_insert(7), Err(Full));
+    assert_eq!(format!("{} {:?}", s, s), "{0, 1, 2, 3, 4, 5, 6} {0, 1, 2, 3, 4, 5, 6}");
+    // Words that no set is stored in are refused.
+    assert_eq!(TinySetU64::from_bits(8), None);
+    assert_eq!(TinySetU64::from_bits(usize::MAX & !7), None);
+    const EMPTY: TinySetU64 = TinySetU64::new();
+    assert!(EMPTY.is_empty());
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn test_tinysetu64_remove_full() {
+    // Six elements give the smallest 21 bits, as do five.
+    let mut s = TinySetU64::new();
+    let first = (1 << 21) - 1;
+    for x in 0..6 {
+        s.try_insert(first + 100*x).unwrap();
+    }
+    assert_eq!(s.len(), 6);
+    assert_eq!(s.try_remove(first), Err(Full));
+    assert_eq!(s.len(), 6);
+    assert_eq!(s.try_remove(first + 500), Ok(true));
+    assert_eq!(s.try_remove(first), Ok(true));
+    assert!(s.iter().eq((1..5).map(|x| first + 100*x)));
+}